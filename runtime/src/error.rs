@@ -0,0 +1,33 @@
+// Errors from the runtime and the parts of the library built on it
+#[derive(Debug)]
+pub enum RuntimeError {
+    MemoryAllocationError,
+    ThreadPoolError(String),
+    SIMDError(String),
+    IndexOutOfBounds { index: i64, len: usize },
+    ArithmeticOverflow,
+    DivisionByZero,
+    SliceOutOfBounds { start: usize, end: usize, len: usize },
+    NotCharBoundary { index: usize },
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuntimeError::MemoryAllocationError => write!(f, "Memory allocation failed"),
+            RuntimeError::ThreadPoolError(msg) => write!(f, "Thread pool error: {}", msg),
+            RuntimeError::SIMDError(msg) => write!(f, "SIMD operation error: {}", msg),
+            RuntimeError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} out of bounds for length {}", index, len)
+            }
+            RuntimeError::ArithmeticOverflow => write!(f, "Arithmetic overflow"),
+            RuntimeError::DivisionByZero => write!(f, "Division by zero"),
+            RuntimeError::SliceOutOfBounds { start, end, len } => {
+                write!(f, "Range {}..{} out of bounds for length {}", start, end, len)
+            }
+            RuntimeError::NotCharBoundary { index } => write!(f, "Byte index {} is not a character boundary", index),
+        }
+    }
+}
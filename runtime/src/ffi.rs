@@ -0,0 +1,197 @@
+// Only compiled code calls these, and it passes valid pointers
+#![allow(clippy::missing_safety_doc)]
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::error::RuntimeError;
+use crate::string::SwiftString;
+
+// C ABI entry points called by compiled Swift++ programs. The compiler
+// declares these by name, so keep the symbols in sync with codegen.
+// Strings are passed as their bytes and length, and returned through a
+// pointer to a `SwiftString`.
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_print_str(ptr: *const u8, len: usize) {
+    print(format_args!("{}", SwiftString::as_str(ptr, len)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_println_str(ptr: *const u8, len: usize) {
+    println(format_args!("{}", SwiftString::as_str(ptr, len)));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_print_i64(value: i64) {
+    print(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_println_i64(value: i64) {
+    println(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_print_u64(value: u64) {
+    print(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_println_u64(value: u64) {
+    println(format_args!("{}", value));
+}
+
+/// Print an `i128`, passed as its low and high 64 bits
+#[no_mangle]
+pub extern "C" fn swiftpp_print_i128(low: u64, high: u64) {
+    print(format_args!("{}", ((high as u128) << 64 | low as u128) as i128));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_println_i128(low: u64, high: u64) {
+    println(format_args!("{}", ((high as u128) << 64 | low as u128) as i128));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_print_f64(value: f64) {
+    print(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_println_f64(value: f64) {
+    println(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_print_bool(value: bool) {
+    print(format_args!("{}", value));
+}
+
+#[no_mangle]
+pub extern "C" fn swiftpp_println_bool(value: bool) {
+    println(format_args!("{}", value));
+}
+
+/// Store `left` followed by `right` at `out`, in a new buffer
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_string_concat(
+    left: *const u8,
+    left_len: usize,
+    right: *const u8,
+    right_len: usize,
+    out: *mut SwiftString,
+) {
+    let mut value = String::with_capacity(left_len + right_len);
+    value.push_str(SwiftString::as_str(left, left_len));
+    value.push_str(SwiftString::as_str(right, right_len));
+    out.write(SwiftString::from_string(value));
+}
+
+/// Free the buffer of a string that owns one
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_string_free(ptr: *mut u8, cap: usize) {
+    SwiftString::free(ptr, cap);
+}
+
+/// -1, 0 or 1 as `left` sorts before, the same as or after `right`,
+/// comparing bytes
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_string_compare(left: *const u8, left_len: usize, right: *const u8, right_len: usize) -> i32 {
+    SwiftString::as_str(left, left_len).cmp(SwiftString::as_str(right, right_len)) as i32
+}
+
+/// Where the bytes `start..end` of a string begin. A range that's out of
+/// bounds or splits a character is reported with the location of the slice.
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_string_slice(
+    ptr: *const u8,
+    len: usize,
+    start: usize,
+    end: usize,
+    line: u32,
+    column: u32,
+) -> *const u8 {
+    let value = SwiftString::as_str(ptr, len);
+    let error = if start > end || end > len {
+        RuntimeError::SliceOutOfBounds { start, end, len }
+    } else if !value.is_char_boundary(start) {
+        RuntimeError::NotCharBoundary { index: start }
+    } else if !value.is_char_boundary(end) {
+        RuntimeError::NotCharBoundary { index: end }
+    } else {
+        return ptr.add(start);
+    };
+    fail(error, line, column)
+}
+
+// `to_string` on each kind of value, formatted the way `print` does it
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_i64(value: i64, out: *mut SwiftString) {
+    out.write(SwiftString::from_string(value.to_string()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_u64(value: u64, out: *mut SwiftString) {
+    out.write(SwiftString::from_string(value.to_string()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_i128(low: u64, high: u64, out: *mut SwiftString) {
+    let value = ((high as u128) << 64 | low as u128) as i128;
+    out.write(SwiftString::from_string(value.to_string()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_f64(value: f64, out: *mut SwiftString) {
+    out.write(SwiftString::from_string(value.to_string()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_bool(value: bool, out: *mut SwiftString) {
+    out.write(SwiftString::from_string(value.to_string()));
+}
+
+// A copy, so the result owns its buffer like any other `to_string`
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_to_string_str(ptr: *const u8, len: usize, out: *mut SwiftString) {
+    out.write(SwiftString::from_string(SwiftString::as_str(ptr, len).to_string()));
+}
+
+/// Called when an index is out of bounds; reports the error with the
+/// location of the index expression and exits
+#[no_mangle]
+pub extern "C" fn swiftpp_index_out_of_bounds(index: i64, len: i64, line: u32, column: u32) -> ! {
+    fail(RuntimeError::IndexOutOfBounds { index, len: len as usize }, line, column)
+}
+
+/// Called when integer arithmetic overflows in a build with overflow checks;
+/// reports the error with the location of the operation and exits
+#[no_mangle]
+pub extern "C" fn swiftpp_arithmetic_overflow(line: u32, column: u32) -> ! {
+    fail(RuntimeError::ArithmeticOverflow, line, column)
+}
+
+/// Called when an integer is divided by zero in a build with overflow checks;
+/// reports the error with the location of the operation and exits
+#[no_mangle]
+pub extern "C" fn swiftpp_division_by_zero(line: u32, column: u32) -> ! {
+    fail(RuntimeError::DivisionByZero, line, column)
+}
+
+fn print(args: fmt::Arguments) {
+    io::stdout().write_fmt(args).unwrap();
+}
+
+fn println(args: fmt::Arguments) {
+    print(args);
+    io::stdout().write_all(b"\n").unwrap();
+}
+
+// Report a runtime error at a line and column of the program and exit
+fn fail(error: RuntimeError, line: u32, column: u32) -> ! {
+    let _ = io::stdout().flush();
+    eprintln!("error: {} at line {}, column {}", error, line, column);
+    std::process::exit(101);
+}
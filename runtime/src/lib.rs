@@ -0,0 +1,11 @@
+// The runtime linked into every Swift++ executable: program startup and the
+// C ABI entry points compiled code calls. The compiler crate re-exports
+// these as `swiftpp::runtime` and `swiftpp::stdlib` items.
+
+pub mod error;
+pub mod ffi;
+pub mod startup;
+pub mod string;
+
+pub use error::RuntimeError;
+pub use string::SwiftString;
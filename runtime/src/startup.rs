@@ -0,0 +1,34 @@
+use std::ffi::CStr;
+use std::io::{self, Write};
+use std::os::raw::{c_char, c_int};
+use std::sync::OnceLock;
+
+static ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Called by the generated `main` before the program's own `main` runs
+///
+/// # Safety
+/// `argv` must point to `argc` pointers, each null or a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn swiftpp_rt_init(argc: c_int, argv: *const *const c_char) {
+    let mut args = Vec::with_capacity(argc.max(0) as usize);
+    for i in 0..argc.max(0) as isize {
+        let arg = *argv.offset(i);
+        if !arg.is_null() {
+            args.push(CStr::from_ptr(arg).to_string_lossy().into_owned());
+        }
+    }
+    let _ = ARGS.set(args);
+}
+
+/// Called by the generated `main` after the program's own `main` returns
+#[no_mangle]
+pub extern "C" fn swiftpp_rt_shutdown() {
+    // Output written without a trailing newline is still buffered
+    let _ = io::stdout().flush();
+}
+
+/// Command-line arguments the program was started with
+pub fn args() -> &'static [String] {
+    ARGS.get().map(|args| args.as_slice()).unwrap_or(&[])
+}
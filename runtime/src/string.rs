@@ -0,0 +1,52 @@
+use std::mem::ManuallyDrop;
+use std::{slice, str};
+
+/// The runtime representation of a Swift++ `string`: UTF-8 bytes, their
+/// length and the capacity of the buffer holding them. Compiled code lays it
+/// out as `{ i8*, i64, i64 }`.
+///
+/// A literal, or a slice of another string, borrows its bytes and has a
+/// capacity of 0. Any other string owns its buffer, which compiled code frees
+/// with `swiftpp_string_free` when an owned binding holding it goes away. The
+/// borrow checker treats slicing such a binding as borrowing it, so a slice
+/// can't outlive the buffer it points into.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SwiftString {
+    pub ptr: *const u8,
+    pub len: usize,
+    pub cap: usize,
+}
+
+impl SwiftString {
+    /// Hand the buffer of `value` over to compiled code
+    pub fn from_string(value: String) -> Self {
+        let mut value = ManuallyDrop::new(value);
+        SwiftString {
+            ptr: value.as_mut_ptr(),
+            len: value.len(),
+            cap: value.capacity(),
+        }
+    }
+
+    /// Take back a buffer `from_string` handed over. A capacity of 0 means
+    /// the string borrows its bytes, so there's nothing to free.
+    ///
+    /// # Safety
+    /// `ptr` and `cap` must come from the same `from_string`, which must not
+    /// have been freed already
+    pub unsafe fn free(ptr: *mut u8, cap: usize) {
+        if cap > 0 {
+            drop(Vec::from_raw_parts(ptr, 0, cap));
+        }
+    }
+
+    /// # Safety
+    /// `ptr` must point to `len` bytes of UTF-8 that outlive the result
+    pub unsafe fn as_str<'a>(ptr: *const u8, len: usize) -> &'a str {
+        if len == 0 {
+            return "";
+        }
+        str::from_utf8_unchecked(slice::from_raw_parts(ptr, len))
+    }
+}
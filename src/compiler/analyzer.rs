@@ -1,50 +1,271 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use super::ast::*;
-
-#[derive(Debug, Clone)]
-pub struct TypeEnvironment {
-    variables: HashMap<String, Type>,
-    functions: HashMap<String, FunctionType>,
-    structs: HashMap<String, StructType>,
-}
+use super::borrowck::{place_name, BorrowChecker};
+use super::cfg::{self, ControlFlowGraph};
+use super::diagnostic::Diagnostic;
+use super::patterns::{self, Domain, Pat};
 
 #[derive(Debug, Clone)]
 pub struct FunctionType {
+    // Each call instantiates these with fresh type variables
+    generics: Vec<GenericParam>,
     params: Vec<(String, Type)>,
-    return_type: Option<Type>,
+    // A type variable unless annotated. One that no `return` fixes means the
+    // function returns nothing.
+    return_type: Type,
 }
 
 #[derive(Debug, Clone)]
 pub struct StructType {
-    fields: HashMap<String, Type>,
+    generics: Vec<GenericParam>,
+    // In declaration order, which is also the layout order
+    fields: Vec<(String, Type)>,
+}
+
+impl StructType {
+    fn field(&self, name: &str) -> Option<&Type> {
+        self.fields.iter().find(|(field, _)| field == name).map(|(_, ty)| ty)
+    }
+
+    // The type parameters bound to `arguments`, as `Type::substitute` takes them
+    fn arguments(&self, arguments: &[Type]) -> HashMap<String, Type> {
+        self.generics.iter().map(|param| param.name.clone()).zip(arguments.iter().cloned()).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumType {
+    // Each variant's name and payload types. A variant's position is its tag.
+    variants: Vec<(String, Vec<Type>)>,
+}
+
+impl EnumType {
+    fn variant(&self, name: &str) -> Option<usize> {
+        self.variants.iter().position(|(variant, _)| variant == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraitType {
+    // Each method's name, parameter types and return type (`None` for
+    // nothing). The first parameter is `self`, and `Self` stands for the
+    // implementing type.
+    methods: Vec<(String, Vec<Type>, Option<Type>)>,
+}
+
+impl TraitType {
+    fn method(&self, name: &str) -> Option<&(String, Vec<Type>, Option<Type>)> {
+        self.methods.iter().find(|(method, _, _)| method == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Struct,
+    Enum,
+    Trait,
+}
+
+pub type SymbolId = usize;
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // `None` for structs, enums, traits, functions without a return type, and declarations
+    // whose type could not be determined
+    pub ty: Option<Type>,
+    // The name at the declaration
+    pub definition: Span,
+    pub uses: Vec<Span>,
+}
+
+// Every declaration the analyzer resolved, with the places it is referenced
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    fn add(&mut self, symbol: Symbol) -> SymbolId {
+        self.symbols.push(symbol);
+        self.symbols.len() - 1
+    }
+
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    // The symbol declared or referenced at byte `offset`. The end of a span
+    // counts too, so a cursor right after a name still finds it.
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        let covers = |span: &Span| span.start <= offset && offset <= span.end;
+        self.symbols
+            .iter()
+            .find(|symbol| covers(&symbol.definition) || symbol.uses.iter().any(covers))
+    }
+}
+
+// The names declared directly in one block. Symbols declared here are listed
+// separately because a shadowed `let` disappears from `names`.
+struct Scope {
+    id: usize,
+    names: HashMap<String, SymbolId>,
+    declared: Vec<SymbolId>,
+}
+
+// A reference to a name that wasn't in scope. If a later `let` in one of the
+// enclosing scopes declares it, the error becomes a use-before-definition error.
+struct UnresolvedUse {
+    name: String,
+    span: Span,
+    error: usize,
+    scopes: Vec<usize>,
+}
+
+// A type that must implement a trait because of a bound on a type parameter.
+// Checked once inference has settled what the type is.
+struct Obligation {
+    ty: Type,
+    bound: String,
+    // Where the bound is declared
+    bound_span: Span,
+    // The call or struct literal that instantiated the type parameter
+    span: Span,
+    // The type parameters in scope at `span`, whose own bounds count
+    type_params: Vec<GenericParam>,
 }
 
 pub struct SemanticAnalyzer {
-    environment: TypeEnvironment,
-    errors: Vec<String>,
+    functions: HashMap<String, (FunctionType, SymbolId)>,
+    structs: HashMap<String, (StructType, SymbolId)>,
+    enums: HashMap<String, (EnumType, SymbolId)>,
+    traits: HashMap<String, (TraitType, SymbolId)>,
+    // Each trait with a type that implements it
+    impls: Vec<(String, Type)>,
+    // The methods of impls, by the span of their names, since they share
+    // names with each other and with functions
+    methods: HashMap<Span, FunctionType>,
+    // The type parameters of the function being analyzed
+    type_params: Vec<GenericParam>,
+    // The implementing type, while analyzing an impl's methods
+    self_type: Option<Type>,
+    obligations: Vec<Obligation>,
+    // The type arguments inferred for each generic call and struct literal,
+    // by its span, the receiver type of each method call, and the operand
+    // type of each arithmetic, comparison, unary operation, cast, compound
+    // assignment and match
+    instantiations: HashMap<Span, Vec<(String, Type)>>,
+    receivers: HashMap<Span, Type>,
+    operand_types: HashMap<Span, Type>,
+    // The referenced type of each `*r`, by its span, and of each reference
+    // reached through implicitly by a field access or method call, by the
+    // span of the access; with whether the reference is `ref mut`
+    derefs: HashMap<Span, (Type, bool)>,
+    auto_derefs: HashMap<Span, (Type, bool)>,
+    // The array or slice type of each index and slice expression, by its span
+    array_types: HashMap<Span, Type>,
+    // The type of each unsuffixed number literal, and the value of each
+    // integer literal, with whether it's the operand of `-`, to check it fits
+    literal_types: HashMap<Span, Type>,
+    integer_literals: Vec<(Span, u128, bool, Type)>,
+    // The variables standing for the type of an unsuffixed literal, which
+    // only unify with integer or float types and default to `i32` or `f64`
+    integer_vars: HashSet<usize>,
+    float_vars: HashSet<usize>,
+    // The `let mut` bindings
+    mutable: HashSet<SymbolId>,
+    // How many loops enclose the statement being analyzed, within its function
+    loop_depth: usize,
+    // From the borrow checker: the identifiers whose use moves their value,
+    // and the `let` bindings that own theirs
+    moves: HashSet<Span>,
+    owned_bindings: HashSet<Span>,
+    scopes: Vec<Scope>,
+    next_scope_id: usize,
+    symbols: SymbolTable,
+    unresolved: Vec<UnresolvedUse>,
+    // What each `Type::Var` has been unified with, and where that happened
+    bindings: Vec<Option<(Type, Span)>>,
+    // The return type of each function being analyzed, innermost last
+    return_types: Vec<Type>,
+    errors: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
 }
 
 impl SemanticAnalyzer {
     pub fn new() -> Self {
         SemanticAnalyzer {
-            environment: TypeEnvironment {
-                variables: HashMap::new(),
-                functions: HashMap::new(),
-                structs: HashMap::new(),
-            },
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: Vec::new(),
+            methods: HashMap::new(),
+            type_params: Vec::new(),
+            self_type: None,
+            obligations: Vec::new(),
+            instantiations: HashMap::new(),
+            receivers: HashMap::new(),
+            operand_types: HashMap::new(),
+            derefs: HashMap::new(),
+            auto_derefs: HashMap::new(),
+            array_types: HashMap::new(),
+            literal_types: HashMap::new(),
+            integer_literals: Vec::new(),
+            integer_vars: HashSet::new(),
+            float_vars: HashSet::new(),
+            mutable: HashSet::new(),
+            loop_depth: 0,
+            moves: HashSet::new(),
+            owned_bindings: HashSet::new(),
+            scopes: Vec::new(),
+            next_scope_id: 0,
+            symbols: SymbolTable::default(),
+            unresolved: Vec::new(),
+            bindings: Vec::new(),
+            return_types: Vec::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    pub fn analyze(&mut self, program: &Program) -> Result<(), Vec<String>> {
+    pub fn analyze(&mut self, program: &Program) -> Result<(), Vec<Diagnostic>> {
+        self.push_scope();
+
         // First pass: collect all type declarations
         self.collect_declarations(program);
-        
+
         // Second pass: analyze statements and expressions
         for statement in &program.statements {
-            self.analyze_statement(statement)?;
+            self.analyze_statement(statement);
+        }
+
+        self.pop_scope();
+        self.unresolved.clear();
+        self.finish_inference();
+        self.check_obligations();
+        self.check_integer_literals();
+
+        // Third pass: control flow, once return types are known
+        self.check_control_flow(&program.statements);
+
+        // Ownership rules only make sense for a well-typed program
+        if self.errors.is_empty() {
+            let mut checker = BorrowChecker::new(&self.symbols);
+            checker.check(program);
+            self.errors.extend(checker.errors().iter().cloned());
+            self.moves = checker.moves().clone();
+            self.owned_bindings = checker.owned_bindings().clone();
         }
-        
+
         if self.errors.is_empty() {
             Ok(())
         } else {
@@ -52,209 +273,2567 @@ impl SemanticAnalyzer {
         }
     }
 
-    fn collect_declarations(&mut self, program: &Program) {
-        for statement in &program.statements {
-            match statement {
-                Statement::Function { name, params, return_type, .. } => {
-                    self.environment.functions.insert(
-                        name.clone(),
-                        FunctionType {
-                            params: params.clone(),
-                            return_type: return_type.clone(),
-                        },
-                    );
+    // Unused variables and similar problems that don't stop compilation
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // Write the inferred types of unannotated bindings, parameters and return
+    // types back into the program, along with the type arguments of generic
+    // calls and the receiver types of method calls, so code generation sees
+    // complete types. Moves and dereferences the program leaves implicit
+    // become explicit, and bindings that own their values are marked `own`.
+    pub fn annotate(&self, program: &mut Program) {
+        self.annotate_statements(&mut program.statements);
+    }
+
+    fn annotate_statements(&self, statements: &mut [Statement]) {
+        for statement in statements {
+            match &mut statement.kind {
+                StatementKind::Let { name_span, type_annotation, initializer, .. } => {
+                    if type_annotation.is_none() {
+                        let symbol = self
+                            .symbols
+                            .iter()
+                            .find(|symbol| symbol.kind == SymbolKind::Variable && symbol.definition == *name_span);
+                        *type_annotation = symbol.and_then(|symbol| symbol.ty.clone());
+                    }
+                    if self.owned_bindings.contains(name_span) {
+                        *type_annotation = type_annotation.take().map(|ty| match ty {
+                            Type::Own(_) => ty,
+                            ty => Type::Own(Box::new(ty)),
+                        });
+                    }
+                    self.annotate_expression(initializer);
+                }
+                StatementKind::Function { name, name_span, params, return_type, body, .. } => {
+                    if let Some(function_type) = self.function_type_at(name, *name_span) {
+                        for (param, (_, ty)) in params.iter_mut().zip(&function_type.params) {
+                            if param.ty.is_none() {
+                                param.ty = Some(self.resolve_type(ty));
+                            }
+                        }
+                        let inferred = self.resolve_type(&function_type.return_type);
+                        if return_type.is_none() && !matches!(inferred, Type::Var(_)) {
+                            *return_type = Some(inferred);
+                        }
+                    }
+                    self.annotate_statements(body);
                 }
-                Statement::Struct { name, fields } => {
-                    let mut field_types = HashMap::new();
-                    for (field_name, field_type) in fields {
-                        field_types.insert(field_name.clone(), field_type.clone());
+                StatementKind::Return(Some(expr)) | StatementKind::Expression(expr) => self.annotate_expression(expr),
+                StatementKind::Assign { target, value, operand_type, .. } => {
+                    *operand_type = self.operand_types.get(&statement.span).map(|ty| self.resolve_type(ty));
+                    self.annotate_expression(target);
+                    self.annotate_expression(value);
+                }
+                StatementKind::Block(body) => self.annotate_statements(body),
+                StatementKind::While { condition, body } => {
+                    self.annotate_expression(condition);
+                    self.annotate_statements(body);
+                }
+                StatementKind::If { condition, then_branch, else_branch } => {
+                    self.annotate_expression(condition);
+                    self.annotate_statements(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.annotate_statements(else_branch);
                     }
-                    self.environment.structs.insert(
-                        name.clone(),
-                        StructType { fields: field_types },
-                    );
                 }
+                StatementKind::Impl(impl_decl) => self.annotate_statements(&mut impl_decl.methods),
                 _ => {}
             }
         }
     }
 
-    fn analyze_statement(&mut self, statement: &Statement) -> Result<(), Vec<String>> {
-        match statement {
-            Statement::Let { name, type_annotation, initializer } => {
-                let expr_type = self.analyze_expression(initializer)?;
-                
-                if let Some(annotated_type) = type_annotation {
-                    if !self.types_match(&expr_type, annotated_type) {
-                        self.errors.push(format!(
-                            "Type mismatch: expected {:?}, found {:?}",
-                            annotated_type, expr_type
-                        ));
+    fn annotate_expression(&self, expr: &mut Expression) {
+        let span = expr.span;
+        let type_arguments = |analyzer: &Self| -> Vec<Type> {
+            analyzer
+                .instantiations
+                .get(&span)
+                .map(|arguments| arguments.iter().map(|(_, ty)| analyzer.resolve_type(ty)).collect())
+                .unwrap_or_default()
+        };
+        match &mut expr.kind {
+            ExpressionKind::Call { function, arguments, type_arguments: inferred } => {
+                *inferred = type_arguments(self);
+                self.annotate_expression(function);
+                arguments.iter_mut().for_each(|argument| self.annotate_expression(argument));
+            }
+            ExpressionKind::StructLiteral { fields, type_arguments: inferred, .. } => {
+                *inferred = type_arguments(self);
+                fields.iter_mut().for_each(|(_, value)| self.annotate_expression(value));
+            }
+            ExpressionKind::MethodCall { receiver, arguments, receiver_type, .. } => {
+                *receiver_type = self.receivers.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(receiver);
+                self.annotate_auto_deref(receiver, span);
+                arguments.iter_mut().for_each(|argument| self.annotate_expression(argument));
+            }
+            ExpressionKind::Binary { left, right, operand_type, .. } => {
+                *operand_type = self.operand_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(left);
+                self.annotate_expression(right);
+            }
+            ExpressionKind::Unary { operand, operand_type, .. } => {
+                *operand_type = self.operand_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(operand);
+            }
+            ExpressionKind::Borrow { operand, .. } => self.annotate_expression(operand),
+            ExpressionKind::FieldAccess { object, .. } => {
+                self.annotate_expression(object);
+                self.annotate_auto_deref(object, span);
+            }
+            ExpressionKind::Identifier(_) if self.moves.contains(&span) => {
+                let identifier = Expression::new(std::mem::replace(&mut expr.kind, ExpressionKind::Boolean(false)), span);
+                expr.kind = ExpressionKind::Move(Box::new(identifier));
+            }
+            ExpressionKind::Deref { operand, pointee } => {
+                *pointee = self.derefs.get(&span).map(|(ty, _)| self.resolve_type(ty));
+                self.annotate_expression(operand);
+            }
+            ExpressionKind::Index { array, index, array_type } => {
+                *array_type = self.array_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(array);
+                self.annotate_expression(index);
+            }
+            ExpressionKind::Slice { operand, array_type } => {
+                *array_type = self.array_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(operand);
+            }
+            ExpressionKind::ArrayLiteral(elements) => elements.iter_mut().for_each(|element| self.annotate_expression(element)),
+            ExpressionKind::Interpolation(desugared) => self.annotate_expression(desugared),
+            ExpressionKind::Range { start, end } => {
+                self.annotate_expression(start);
+                self.annotate_expression(end);
+            }
+            ExpressionKind::Integer(_, ty) | ExpressionKind::Float(_, ty) if ty.is_none() => {
+                *ty = self.literal_types.get(&span).map(|ty| self.resolve_type(ty));
+            }
+            ExpressionKind::Cast { operand, operand_type, .. } => {
+                *operand_type = self.operand_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(operand);
+            }
+            ExpressionKind::Match { scrutinee, arms, scrutinee_type } => {
+                *scrutinee_type = self.operand_types.get(&span).map(|ty| self.resolve_type(ty));
+                self.annotate_expression(scrutinee);
+                arms.iter_mut().for_each(|arm| self.annotate_expression(&mut arm.body));
+            }
+            ExpressionKind::If { condition, then_branch, else_branch } => {
+                self.annotate_expression(condition);
+                self.annotate_expression(then_branch);
+                self.annotate_expression(else_branch);
+            }
+            _ => {}
+        }
+    }
+
+    // Dereference the object of the field access or method call at `span`
+    // if it's a reference
+    fn annotate_auto_deref(&self, object: &mut Box<Expression>, span: Span) {
+        if let Some((pointee, _)) = self.auto_derefs.get(&span) {
+            let operand_span = object.span;
+            let operand = std::mem::replace(object, Box::new(Expression::new(ExpressionKind::Boolean(false), operand_span)));
+            let pointee = Some(self.resolve_type(pointee));
+            **object = Expression::new(ExpressionKind::Deref { operand, pointee }, operand_span);
+        }
+    }
+
+    fn collect_declarations(&mut self, program: &Program) {
+        for statement in &program.statements {
+            match &statement.kind {
+                StatementKind::Function { name, name_span, generics, params, return_type, .. } => {
+                    self.declare_function(name, *name_span, generics, params, return_type);
+                }
+                StatementKind::Struct { name, name_span, generics, fields } => {
+                    if let Some(previous) = self.item_declaration(name) {
+                        self.report_redefinition(name, *name_span, previous);
+                        continue;
                     }
+                    let id = self.symbols.add(Symbol {
+                        name: name.clone(),
+                        kind: SymbolKind::Struct,
+                        ty: None,
+                        definition: *name_span,
+                        uses: Vec::new(),
+                    });
+                    let struct_type = StructType {
+                        generics: generics.clone(),
+                        fields: fields.clone(),
+                    };
+                    self.structs.insert(name.clone(), (struct_type, id));
                 }
-                
-                self.environment.variables.insert(name.clone(), expr_type);
-            }
-            
-            Statement::Function { name, params, return_type, body } => {
-                // Create new scope for function body
-                let mut function_env = self.environment.clone();
-                
-                // Add parameters to function scope
-                for (param_name, param_type) in params {
-                    function_env.variables.insert(param_name.clone(), param_type.clone());
+                StatementKind::Enum(Enum { name, name_span, variants }) => {
+                    if let Some(previous) = self.item_declaration(name) {
+                        self.report_redefinition(name, *name_span, previous);
+                        continue;
+                    }
+                    let mut seen: Vec<&Variant> = Vec::new();
+                    for variant in variants {
+                        if let Some(previous) = seen.iter().find(|previous| previous.name == variant.name) {
+                            self.errors.push(
+                                Diagnostic::error(format!("Variant `{}` is defined multiple times", variant.name), variant.span)
+                                    .with_code("E0108")
+                                    .with_primary_label("redefined here")
+                                    .with_label(previous.span, "previous definition here"),
+                            );
+                        }
+                        seen.push(variant);
+                    }
+                    let id = self.symbols.add(Symbol {
+                        name: name.clone(),
+                        kind: SymbolKind::Enum,
+                        ty: None,
+                        definition: *name_span,
+                        uses: Vec::new(),
+                    });
+                    let variants = variants
+                        .iter()
+                        .map(|variant| (variant.name.clone(), variant.fields.iter().map(|(_, ty)| ty.clone()).collect()))
+                        .collect();
+                    self.enums.insert(name.clone(), (EnumType { variants }, id));
                 }
-                
-                // Analyze function body
-                let mut analyzer = SemanticAnalyzer {
-                    environment: function_env,
-                    errors: Vec::new(),
-                };
-                
-                for stmt in body {
-                    analyzer.analyze_statement(stmt)?;
+                StatementKind::Trait(Trait { name, name_span, methods }) => {
+                    if let Some(previous) = self.item_declaration(name) {
+                        self.report_redefinition(name, *name_span, previous);
+                        continue;
+                    }
+                    let mut trait_type = TraitType { methods: Vec::new() };
+                    for method in methods {
+                        if let Some(previous) = methods.iter().find(|previous| previous.name == method.name) {
+                            if previous.name_span != method.name_span {
+                                self.errors.push(
+                                    Diagnostic::error(format!("Method `{}` is defined multiple times", method.name), method.name_span)
+                                        .with_code("E0108")
+                                        .with_primary_label("redefined here")
+                                        .with_label(previous.name_span, "previous definition here"),
+                                );
+                                continue;
+                            }
+                        }
+                        let mut params = Vec::new();
+                        for param in &method.params {
+                            match &param.ty {
+                                Some(ty) => params.push(ty.clone()),
+                                None => {
+                                    self.errors.push(
+                                        Diagnostic::error(format!("Type annotations needed for `{}`", param.name), param.span)
+                                            .with_code("E0110")
+                                            .with_primary_label("trait methods have no body to infer it from"),
+                                    );
+                                    params.push(self.fresh_var());
+                                }
+                            }
+                        }
+                        trait_type.methods.push((method.name.clone(), params, method.return_type.clone()));
+                    }
+                    let id = self.symbols.add(Symbol {
+                        name: name.clone(),
+                        kind: SymbolKind::Trait,
+                        ty: None,
+                        definition: *name_span,
+                        uses: Vec::new(),
+                    });
+                    self.traits.insert(name.clone(), (trait_type, id));
                 }
-                
-                // Merge errors
-                self.errors.extend(analyzer.errors);
+                _ => {}
             }
-            
-            Statement::Return(expr) => {
-                if let Some(expr) = expr {
-                    self.analyze_expression(expr)?;
+        }
+
+        // Field types can name types declared further down
+        for statement in &program.statements {
+            let (name, name_span, field_types, kind): (_, _, Vec<&Type>, _) = match &statement.kind {
+                StatementKind::Struct { name, name_span, generics, fields } => {
+                    self.check_bounds_exist(generics);
+                    (name, name_span, fields.iter().map(|(_, ty)| ty).collect(), "struct")
+                }
+                StatementKind::Enum(Enum { name, name_span, variants }) => {
+                    let fields = variants.iter().flat_map(|variant| &variant.fields).map(|(_, ty)| ty).collect();
+                    (name, name_span, fields, "enum")
+                }
+                StatementKind::Trait(Trait { methods, .. }) => {
+                    for method in methods {
+                        for param in &method.params {
+                            if let Some(ty) = &param.ty {
+                                self.check_type_exists(ty, param.span);
+                            }
+                        }
+                        if let Some(return_type) = &method.return_type {
+                            self.check_type_exists(return_type, method.name_span);
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            for field_type in field_types {
+                self.check_type_exists(field_type, *name_span);
+                if contains_ref(field_type) {
+                    self.errors.push(
+                        Diagnostic::error(format!("A {} field can't hold a reference", kind), *name_span)
+                            .with_code("E0127")
+                            .with_primary_label(format!("`{}` is a field of `{}`", field_type, name))
+                            .with_note("a reference can't outlive the place it borrows from, so it can't be stored"),
+                    );
                 }
             }
-            
-            Statement::Expression(expr) => {
-                self.analyze_expression(expr)?;
+            if self.contains_type(&Type::Custom(name.clone(), Vec::new()), name, &mut Vec::new()) {
+                self.errors.push(
+                    Diagnostic::error(format!("Recursive {} `{}` has infinite size", kind, name), *name_span)
+                        .with_code("E0116")
+                        .with_note(format!("a {} can't contain itself, directly or through other types", kind)),
+                );
             }
-            
-            Statement::If { condition, then_branch, else_branch } => {
-                let condition_type = self.analyze_expression(condition)?;
-                if !matches!(condition_type, Type::Bool) {
-                    self.errors.push("If condition must be a boolean".to_string());
-                }
-                
-                for stmt in then_branch {
-                    self.analyze_statement(stmt)?;
+        }
+
+        // Impls need every trait and type
+        for statement in &program.statements {
+            if let StatementKind::Impl(impl_decl) = &statement.kind {
+                self.declare_impl(impl_decl);
+            }
+        }
+    }
+
+    // Give each method of `impl_decl` its type, and check the methods
+    // against the trait's
+    fn declare_impl(&mut self, impl_decl: &Impl) {
+        let Impl { trait_name, trait_span, self_type, type_span, methods } = impl_decl;
+        let errors = self.errors.len();
+        self.check_type_exists(self_type, *type_span);
+        let trait_type = self.check_trait_exists(trait_name, *trait_span);
+
+        self.self_type = Some(self_type.clone());
+        let mut declared: Vec<(&str, Span, &[Param], &Option<Type>)> = Vec::new();
+        for method in methods {
+            let StatementKind::Function { name, name_span, generics, params, return_type, .. } = &method.kind else {
+                continue;
+            };
+            if let Some((_, previous, _, _)) = declared.iter().find(|(previous, _, _, _)| previous == name) {
+                self.errors.push(
+                    Diagnostic::error(format!("Method `{}` is defined multiple times", name), *name_span)
+                        .with_code("E0108")
+                        .with_primary_label("redefined here")
+                        .with_label(*previous, "previous definition here"),
+                );
+                continue;
+            }
+            let function_type = self.new_function_type(generics, params, return_type);
+            let param_types = function_type.params.iter().map(|(_, ty)| ty.clone()).collect();
+            self.symbols.add(Symbol {
+                name: name.clone(),
+                kind: SymbolKind::Function,
+                ty: Some(Type::Function(param_types, Box::new(function_type.return_type.clone()))),
+                definition: *name_span,
+                uses: Vec::new(),
+            });
+            self.methods.insert(*name_span, function_type);
+            declared.push((name, *name_span, params, return_type));
+        }
+        self.self_type = None;
+
+        let Some(trait_type) = trait_type else {
+            return;
+        };
+        if self.errors.len() != errors {
+            return;
+        }
+        if self.impls.iter().any(|(other_trait, other_type)| other_trait == trait_name && other_type == self_type) {
+            self.errors.push(
+                Diagnostic::error(
+                    format!("Conflicting implementations of trait `{}` for type `{}`", trait_name, self_type),
+                    *type_span,
+                )
+                .with_code("E0108")
+                .with_primary_label("implemented more than once"),
+            );
+            return;
+        }
+        self.impls.push((trait_name.clone(), self_type.clone()));
+
+        let self_arguments = HashMap::from([("Self".to_string(), self_type.clone())]);
+        for &(name, name_span, params, return_type) in &declared {
+            let Some((_, trait_params, trait_return)) = trait_type.method(name).cloned() else {
+                self.errors.push(
+                    Diagnostic::error(format!("Method `{}` is not a member of trait `{}`", name, trait_name), name_span)
+                        .with_code("E0125")
+                        .with_primary_label(format!("not a member of `{}`", trait_name)),
+                );
+                continue;
+            };
+            let function_type = self.methods[&name_span].clone();
+            if params.first().map(|param| param.name.as_str()) != Some("self") || params.len() != trait_params.len() {
+                self.errors.push(
+                    Diagnostic::error(
+                        format!(
+                            "Method `{}` has {} parameters but its declaration in trait `{}` has {}",
+                            name, params.len(), trait_name, trait_params.len()
+                        ),
+                        name_span,
+                    )
+                    .with_code("E0125")
+                    .with_note("the first parameter is `self`, as in the trait"),
+                );
+                continue;
+            }
+
+            let checked = params.iter().zip(&function_type.params).zip(&trait_params);
+            for ((param, (_, param_type)), trait_param) in checked {
+                let expected = trait_param.substitute(&self_arguments);
+                self.expect_type(&expected, param_type, param.span, |expected, found| {
+                    Diagnostic::error(
+                        format!("Method `{}` has an incompatible type for trait `{}`: expected {}, found {}", name, trait_name, expected, found),
+                        param.span,
+                    )
+                    .with_code("E0103")
+                    .with_primary_label(format!("expected `{}`", expected))
+                });
+            }
+            match (trait_return, return_type) {
+                (Some(trait_return), _) => {
+                    let expected = trait_return.substitute(&self_arguments);
+                    self.expect_type(&expected, &function_type.return_type, name_span, |expected, found| {
+                        Diagnostic::error(
+                            format!("Method `{}` has an incompatible return type for trait `{}`: expected {}, found {}", name, trait_name, expected, found),
+                            name_span,
+                        )
+                        .with_code("E0103")
+                        .with_primary_label(format!("expected `{}`", expected))
+                    });
                 }
-                
-                if let Some(else_branch) = else_branch {
-                    for stmt in else_branch {
-                        self.analyze_statement(stmt)?;
-                    }
+                (None, Some(return_type)) => {
+                    self.errors.push(
+                        Diagnostic::error(
+                            format!("Method `{}` returns {}, but its declaration in trait `{}` returns nothing", name, return_type, trait_name),
+                            name_span,
+                        )
+                        .with_code("E0103"),
+                    );
                 }
+                (None, None) => {}
+            }
+        }
+
+        let missing: Vec<_> = trait_type
+            .methods
+            .iter()
+            .filter(|(method, _, _)| !declared.iter().any(|(name, _, _, _)| name == method))
+            .map(|(method, _, _)| format!("`{}`", method))
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(
+                Diagnostic::error(
+                    format!("Not all methods of trait `{}` are implemented for `{}`: missing {}", trait_name, self_type, missing.join(", ")),
+                    *trait_span,
+                )
+                .with_code("E0125")
+                .with_primary_label(format!("missing {}", missing.join(", "))),
+            );
+        }
+    }
+
+    // The struct, enum or trait declared as `name`, which share one namespace
+    fn item_declaration(&self, name: &str) -> Option<SymbolId> {
+        self.type_declaration(name).or_else(|| self.traits.get(name).map(|(_, id)| *id))
+    }
+
+    // The struct or enum declared as `name`
+    fn type_declaration(&self, name: &str) -> Option<SymbolId> {
+        let struct_id = self.structs.get(name).map(|(_, id)| *id);
+        struct_id.or_else(|| self.enums.get(name).map(|(_, id)| *id))
+    }
+
+    fn check_type_exists(&mut self, ty: &Type, span: Span) {
+        match ty {
+            Type::Custom(name, _) if self.type_declaration(name).is_none() => {
+                self.errors.push(
+                    Diagnostic::error(format!("Undefined type: {}", name), span)
+                        .with_code("E0112")
+                        .with_primary_label(format!("`{}` is used here", name)),
+                );
             }
-            
-            Statement::While { condition, body } => {
-                let condition_type = self.analyze_expression(condition)?;
-                if !matches!(condition_type, Type::Bool) {
-                    self.errors.push("While condition must be a boolean".to_string());
+            Type::Custom(name, arguments) => {
+                let expected = self.structs.get(name).map_or(0, |(struct_type, _)| struct_type.generics.len());
+                if arguments.len() != expected {
+                    self.errors.push(
+                        Diagnostic::error(
+                            format!(
+                                "`{}` takes {} type {}, but {} {} supplied",
+                                name,
+                                expected,
+                                if expected == 1 { "argument" } else { "arguments" },
+                                arguments.len(),
+                                if arguments.len() == 1 { "was" } else { "were" },
+                            ),
+                            span,
+                        )
+                        .with_code("E0124")
+                        .with_primary_label(format!("`{}` is used here", ty)),
+                    );
                 }
-                
-                for stmt in body {
-                    self.analyze_statement(stmt)?;
+                for argument in arguments {
+                    self.check_type_exists(argument, span);
+                }
+            }
+            Type::Array(element, _) | Type::Slice(element) | Type::Ref(element) | Type::RefMut(element) => {
+                self.check_type_exists(element, span)
+            }
+            Type::Own(_) => {
+                self.errors.push(
+                    Diagnostic::error("`own` is not allowed here", span)
+                        .with_code("E0126")
+                        .with_primary_label("only a `let` binding or a function parameter can own its value"),
+                );
+            }
+            Type::Function(params, ret) => {
+                for param in params {
+                    self.check_type_exists(param, span);
                 }
+                self.check_type_exists(ret, span);
             }
-            
             _ => {}
         }
-        
-        if self.errors.is_empty() {
-            Ok(())
-        } else {
-            Err(self.errors.clone())
+    }
+
+    fn check_trait_exists(&mut self, name: &str, span: Span) -> Option<TraitType> {
+        match self.traits.get(name).cloned() {
+            Some((trait_type, id)) => {
+                self.symbols.symbols[id].uses.push(span);
+                Some(trait_type)
+            }
+            // Built in unless the program declares its own; codegen calls
+            // `drop` when an owned value goes out of scope
+            None if name == "Drop" => Some(TraitType {
+                methods: vec![("drop".to_string(), vec![Type::Param("Self".to_string())], None)],
+            }),
+            None => {
+                let mut error = Diagnostic::error(format!("Undefined trait: {}", name), span)
+                    .with_code("E0112")
+                    .with_primary_label("not found in this scope");
+                if let Some(similar) = similar_name(name, self.traits.keys()) {
+                    error = error.with_suggestion("a trait with a similar name exists", span, similar);
+                }
+                self.errors.push(error);
+                None
+            }
         }
     }
 
-    fn analyze_expression(&mut self, expr: &Expression) -> Result<Type, Vec<String>> {
-        match expr {
-            Expression::Integer(_) => Ok(Type::Int),
-            Expression::Float(_) => Ok(Type::Float),
-            Expression::String(_) => Ok(Type::String),
-            Expression::Boolean(_) => Ok(Type::Bool),
-            
-            Expression::Identifier(name) => {
-                self.environment.variables
-                    .get(name)
-                    .cloned()
-                    .ok_or_else(|| vec![format!("Undefined variable: {}", name)])
-            }
-            
-            Expression::Binary { left, operator, right } => {
-                let left_type = self.analyze_expression(left)?;
-                let right_type = self.analyze_expression(right)?;
-                
-                if !self.types_match(&left_type, &right_type) {
-                    self.errors.push(format!(
-                        "Binary operation type mismatch: {:?} {} {:?}",
-                        left_type, operator, right_type
-                    ));
-                }
-                
-                match operator {
-                    BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
-                        Ok(left_type)
+    fn check_bounds_exist(&mut self, generics: &[GenericParam]) {
+        for param in generics {
+            for (bound, span) in &param.bounds {
+                self.check_trait_exists(bound, *span);
+            }
+        }
+    }
+
+    // Whether a value of type `ty` holds a `target` value inside one of its
+    // fields or payloads
+    fn contains_type(&self, ty: &Type, target: &str, visited: &mut Vec<String>) -> bool {
+        let (name, arguments) = match ty {
+            Type::Custom(name, arguments) => (name, arguments),
+            _ => return false,
+        };
+        // Instantiations of one generic struct differ in what they contain.
+        // A generic struct that holds ever larger instantiations of itself is
+        // reported on its own, so give up on those rather than recurse forever.
+        let key = ty.to_string();
+        if visited.contains(&key) || visited.len() > 64 {
+            return false;
+        }
+        visited.push(key);
+        let field_types: Vec<Type> = match (self.structs.get(name), self.enums.get(name)) {
+            (Some((struct_type, _)), _) => {
+                let arguments = struct_type.arguments(arguments);
+                struct_type.fields.iter().map(|(_, ty)| ty.substitute(&arguments)).collect()
+            }
+            (None, Some((enum_type, _))) => enum_type.variants.iter().flat_map(|(_, fields)| fields.clone()).collect(),
+            (None, None) => return false,
+        };
+        field_types
+            .iter()
+            .any(|field_type| matches!(field_type, Type::Custom(field, _) if field == target)
+                || self.contains_type(field_type, target, visited))
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        name_span: Span,
+        generics: &[GenericParam],
+        params: &[Param],
+        return_type: &Option<Type>,
+    ) {
+        if let Some((_, previous)) = self.functions.get(name) {
+            self.report_redefinition(name, name_span, *previous);
+            return;
+        }
+
+        let function_type = self.new_function_type(generics, params, return_type);
+        let param_types = function_type.params.iter().map(|(_, ty)| ty.clone()).collect();
+        let ty = Type::Function(param_types, Box::new(function_type.return_type.clone()));
+        let id = self.symbols.add(Symbol {
+            name: name.to_string(),
+            kind: SymbolKind::Function,
+            ty: Some(ty),
+            definition: name_span,
+            uses: Vec::new(),
+        });
+        self.functions.insert(name.to_string(), (function_type, id));
+    }
+
+    // Missing annotations become type variables for inference to fill in
+    fn new_function_type(&mut self, generics: &[GenericParam], params: &[Param], return_type: &Option<Type>) -> FunctionType {
+        let params = params
+            .iter()
+            .map(|param| {
+                let ty = match &param.ty {
+                    Some(ty) => self.resolve_self(strip_own(ty)),
+                    None => self.fresh_var(),
+                };
+                (param.name.clone(), ty)
+            })
+            .collect();
+        let return_type = match return_type {
+            Some(ty) => self.resolve_self(ty),
+            None => self.fresh_var(),
+        };
+        FunctionType {
+            generics: generics.to_vec(),
+            params,
+            return_type,
+        }
+    }
+
+    // The type of the function or method declared at `name_span`; `None` for
+    // a redefinition, which was never entered into `functions`
+    fn function_type_at(&self, name: &str, name_span: Span) -> Option<FunctionType> {
+        self.functions
+            .get(name)
+            .filter(|(_, id)| self.symbols.get(*id).definition == name_span)
+            .map(|(function_type, _)| function_type)
+            .or_else(|| self.methods.get(&name_span))
+            .cloned()
+    }
+
+    // `ty` with `Self` replaced by the implementing type inside an impl
+    fn resolve_self(&self, ty: &Type) -> Type {
+        match &self.self_type {
+            Some(self_type) => ty.substitute(&HashMap::from([("Self".to_string(), self_type.clone())])),
+            None => ty.clone(),
+        }
+    }
+
+    // A generic function's signature with fresh type variables in place of
+    // its type parameters
+    fn instantiate(&mut self, function_type: FunctionType, span: Span) -> FunctionType {
+        if function_type.generics.is_empty() {
+            return function_type;
+        }
+        let arguments = self.instantiate_params(&function_type.generics, span);
+        let params = function_type
+            .params
+            .iter()
+            .map(|(name, ty)| (name.clone(), self.resolve_type(ty).substitute(&arguments)))
+            .collect();
+        FunctionType {
+            generics: Vec::new(),
+            params,
+            return_type: self.resolve_type(&function_type.return_type).substitute(&arguments),
+        }
+    }
+
+    // Fresh type variables for the type parameters `generics` of the generic
+    // call or struct literal at `span`, which must satisfy their bounds
+    fn instantiate_params(&mut self, generics: &[GenericParam], span: Span) -> HashMap<String, Type> {
+        let mut arguments = Vec::new();
+        for param in generics {
+            let ty = self.fresh_var();
+            for (bound, bound_span) in &param.bounds {
+                self.obligations.push(Obligation {
+                    ty: ty.clone(),
+                    bound: bound.clone(),
+                    bound_span: *bound_span,
+                    span,
+                    type_params: self.type_params.clone(),
+                });
+            }
+            arguments.push((param.name.clone(), ty));
+        }
+        self.instantiations.insert(span, arguments.clone());
+        arguments.into_iter().collect()
+    }
+
+    fn report_redefinition(&mut self, name: &str, span: Span, previous: SymbolId) {
+        self.errors.push(
+            Diagnostic::error(format!("`{}` is defined multiple times", name), span)
+                .with_code("E0108")
+                .with_primary_label("redefined here")
+                .with_label(self.symbols.get(previous).definition, "previous definition here"),
+        );
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope {
+            id: self.next_scope_id,
+            names: HashMap::new(),
+            declared: Vec::new(),
+        });
+        self.next_scope_id += 1;
+    }
+
+    // Leaving a scope is when its variables are known to be unused
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("scope stack underflow");
+        for id in scope.declared {
+            let symbol = self.symbols.get(id);
+            // A method's `self` is required by its trait, used or not
+            if symbol.uses.is_empty() && !symbol.name.starts_with('_') && symbol.name != "self" {
+                self.warnings.push(
+                    Diagnostic::warning(format!("unused variable: `{}`", symbol.name), symbol.definition)
+                        .with_suggestion(
+                            "if this is intentional, prefix it with an underscore",
+                            symbol.definition,
+                            format!("_{}", symbol.name),
+                        ),
+                );
+            }
+        }
+    }
+
+    fn declare_variable(&mut self, name: &str, span: Span, kind: SymbolKind, ty: Option<Type>) -> SymbolId {
+        let id = self.symbols.add(Symbol {
+            name: name.to_string(),
+            kind,
+            ty,
+            definition: span,
+            uses: Vec::new(),
+        });
+
+        let scope = self.scopes.last_mut().expect("no scope to declare in");
+        scope.names.insert(name.to_string(), id);
+        scope.declared.push(id);
+
+        // Earlier references that this declaration would have satisfied
+        let scope_id = scope.id;
+        let errors = &mut self.errors;
+        self.unresolved.retain(|unresolved| {
+            if unresolved.name != name || !unresolved.scopes.contains(&scope_id) {
+                return true;
+            }
+            errors[unresolved.error] = Diagnostic::error(
+                format!("Cannot use variable `{}` before its definition", name),
+                unresolved.span,
+            )
+            .with_code("E0109")
+            .with_primary_label("used here")
+            .with_label(span, "defined here");
+            false
+        });
+        id
+    }
+
+    fn resolve(&self, name: &str) -> Option<SymbolId> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(name).copied())
+    }
+
+    fn analyze_block(&mut self, statements: &[Statement]) {
+        self.push_scope();
+        for statement in statements {
+            self.analyze_statement(statement);
+        }
+        self.pop_scope();
+    }
+
+    fn analyze_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Let { name, name_span, mutable, type_annotation, initializer } => {
+                let type_annotation = type_annotation.as_ref().map(|ty| self.resolve_self(strip_own(ty)));
+                if let Some(annotated_type) = &type_annotation {
+                    self.check_type_exists(annotated_type, *name_span);
+                }
+                let expr_type = self.analyze_expression(initializer);
+
+                if let (Some(annotated_type), Some(expr_type)) = (&type_annotation, &expr_type) {
+                    self.expect_type(annotated_type, expr_type, initializer.span, |expected, found| {
+                        Diagnostic::error(format!("Type mismatch: expected {}, found {}", expected, found), initializer.span)
+                            .with_code("E0103")
+                            .with_primary_label(format!("expected `{}`", expected))
+                    });
+                }
+
+                // The binding is only visible after its initializer
+                let ty = type_annotation.or(expr_type);
+                let id = self.declare_variable(name, *name_span, SymbolKind::Variable, ty);
+                if *mutable {
+                    self.mutable.insert(id);
+                }
+            }
+
+            StatementKind::Function { name, name_span, generics, params, return_type, body, .. } => {
+                // Functions nested in blocks aren't hoisted
+                if self.scopes.len() > 1 {
+                    self.declare_function(name, *name_span, generics, params, return_type);
+                }
+                let function_type = match self.function_type_at(name, *name_span) {
+                    Some(function_type) => function_type,
+                    None => self.new_function_type(generics, params, return_type),
+                };
+                self.check_bounds_exist(generics);
+                for param in params {
+                    match &param.ty {
+                        // A method's caller can't tell which arguments it takes ownership of
+                        Some(ty) if self.self_type.is_some() => self.check_type_exists(ty, param.span),
+                        Some(ty) => self.check_type_exists(strip_own(ty), param.span),
+                        // Calls instantiate the declared signature, so there
+                        // is nothing to infer an unannotated parameter from
+                        None if !generics.is_empty() => {
+                            self.errors.push(
+                                Diagnostic::error(format!("Type annotations needed for `{}`", param.name), param.span)
+                                    .with_code("E0110")
+                                    .with_primary_label("parameters of generic functions need a type")
+                                    .with_label(*name_span, format!("`{}` is generic", name)),
+                            );
+                        }
+                        None => {}
                     }
-                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater | BinaryOp::Less |
-                    BinaryOp::GreaterEqual | BinaryOp::LessEqual => {
-                        Ok(Type::Bool)
+                }
+                if let Some(return_type) = return_type {
+                    self.check_type_exists(return_type, *name_span);
+                }
+
+                // Only globals are visible from a function body, not the locals
+                // of an enclosing function
+                let enclosing = self.scopes.split_off(1);
+                self.push_scope();
+                for (param, (_, ty)) in params.iter().zip(&function_type.params) {
+                    if let Some(&previous) = self.scopes.last().unwrap().names.get(&param.name) {
+                        self.errors.push(
+                            Diagnostic::error(
+                                format!("Parameter `{}` is bound more than once", param.name),
+                                param.span,
+                            )
+                            .with_code("E0108")
+                            .with_label(self.symbols.get(previous).definition, "first bound here"),
+                        );
                     }
+                    self.declare_variable(&param.name, param.span, SymbolKind::Parameter, Some(ty.clone()));
+                }
+                self.return_types.push(function_type.return_type);
+                let outer_params = std::mem::replace(&mut self.type_params, generics.clone());
+                let loop_depth = std::mem::take(&mut self.loop_depth);
+                for stmt in body {
+                    self.analyze_statement(stmt);
                 }
+                self.loop_depth = loop_depth;
+                self.type_params = outer_params;
+                self.return_types.pop();
+                self.pop_scope();
+                self.scopes.extend(enclosing);
             }
-            
-            Expression::Call { function, arguments } => {
-                if let Expression::Identifier(name) = &**function {
-                    if let Some(func_type) = self.environment.functions.get(name) {
-                        if arguments.len() != func_type.params.len() {
-                            self.errors.push(format!(
-                                "Wrong number of arguments: expected {}, found {}",
-                                func_type.params.len(), arguments.len()
-                            ));
-                        }
-                        
-                        for (arg, (_, param_type)) in arguments.iter().zip(&func_type.params) {
-                            let arg_type = self.analyze_expression(arg)?;
-                            if !self.types_match(&arg_type, param_type) {
-                                self.errors.push(format!(
-                                    "Argument type mismatch: expected {:?}, found {:?}",
-                                    param_type, arg_type
-                                ));
-                            }
-                        }
-                        
-                        Ok(func_type.return_type.clone().unwrap_or(Type::Int))
-                    } else {
-                        Err(vec![format!("Undefined function: {}", name)])
+
+            StatementKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    let found = self.analyze_expression(expr);
+                    if let Some(found) = found.as_ref().map(|found| self.resolve_type(found)).filter(contains_ref) {
+                        self.errors.push(
+                            Diagnostic::error("Cannot return a reference", expr.span)
+                                .with_code("E0127")
+                                .with_primary_label(format!("this is a `{}`", found))
+                                .with_note("a reference can't outlive the place it borrows from, which ends with the function"),
+                        );
+                        return;
+                    }
+                    if let (Some(expected), Some(found)) = (self.return_types.last().cloned(), found) {
+                        self.expect_type(&expected, &found, expr.span, |expected, found| {
+                            Diagnostic::error(format!("Return type mismatch: expected {}, found {}", expected, found), expr.span)
+                                .with_code("E0103")
+                                .with_primary_label(format!("expected `{}`", expected))
+                        });
                     }
-                } else {
-                    Err(vec!["Invalid function call".to_string()])
                 }
             }
-            
-            _ => Err(vec!["Unsupported expression".to_string()]),
-        }
-    }
 
-    fn types_match(&self, t1: &Type, t2: &Type) -> bool {
-        match (t1, t2) {
-            (Type::Int, Type::Int) |
-            (Type::Float, Type::Float) |
-            (Type::String, Type::String) |
-            (Type::Bool, Type::Bool) => true,
-            
-            (Type::Custom(name1), Type::Custom(name2)) => name1 == name2,
-            
-            (Type::Array(inner1), Type::Array(inner2)) => 
-                self.types_match(inner1, inner2),
-            
-            (Type::Function(params1, ret1), Type::Function(params2, ret2)) => {
-                params1.len() == params2.len() &&
-                params1.iter().zip(params2).all(|(p1, p2)| self.types_match(p1, p2)) &&
-                self.types_match(ret1, ret2)
+            StatementKind::Expression(expr) => {
+                self.analyze_expression(expr);
             }
-            
-            _ => false,
+
+            StatementKind::Assign { target, operator, value, .. } => {
+                let value_type = self.analyze_expression(value);
+                if !is_place(target) {
+                    self.errors.push(
+                        Diagnostic::error("Invalid left-hand side of assignment", target.span)
+                            .with_code("E0117")
+                            .with_primary_label("cannot assign to this expression"),
+                    );
+                    return;
+                }
+                let Some(target_type) = self.analyze_expression(target) else {
+                    return;
+                };
+                if !self.check_assignable(target) {
+                    return;
+                }
+
+                if let Some(operator) = operator {
+                    let resolved = self.resolve_type(&target_type);
+                    // `s += t` appends to a string
+                    let appends = resolved == Type::String && matches!(operator, BinaryOp::Add);
+                    if !resolved.is_numeric() && !appends && !matches!(resolved, Type::Var(_)) {
+                        self.errors.push(
+                            Diagnostic::error(format!("Cannot apply `{}=` to a value of type {}", operator, resolved), target.span)
+                                .with_code("E0104")
+                                .with_primary_label(format!("expected a number, found `{}`", resolved)),
+                        );
+                        return;
+                    }
+                    self.operand_types.insert(statement.span, target_type.clone());
+                }
+                if let Some(value_type) = value_type {
+                    self.expect_type(&target_type, &value_type, value.span, |expected, found| {
+                        Diagnostic::error(format!("Type mismatch: expected {}, found {}", expected, found), value.span)
+                            .with_code("E0103")
+                            .with_primary_label(format!("expected `{}`", expected))
+                    });
+                }
+            }
+
+            StatementKind::Block(statements) => self.analyze_block(statements),
+
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.check_condition(condition, "If");
+                self.analyze_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.analyze_block(else_branch);
+                }
+            }
+
+            StatementKind::While { condition, body } => {
+                self.check_condition(condition, "While");
+                self.loop_depth += 1;
+                self.analyze_block(body);
+                self.loop_depth -= 1;
+            }
+
+            StatementKind::Break | StatementKind::Continue if self.loop_depth == 0 => {
+                let keyword = if let StatementKind::Break = statement.kind { "break" } else { "continue" };
+                self.errors.push(
+                    Diagnostic::error(format!("`{}` outside of a loop", keyword), statement.span)
+                        .with_code("E0129")
+                        .with_primary_label(format!("cannot `{}` outside of a loop", keyword)),
+                );
+            }
+            StatementKind::Break | StatementKind::Continue => {}
+
+            StatementKind::Impl(Impl { self_type, methods, .. }) => {
+                self.self_type = Some(self_type.clone());
+                for method in methods {
+                    self.analyze_statement(method);
+                }
+                self.self_type = None;
+            }
+
+            // Collected up front
+            StatementKind::Struct { .. } | StatementKind::Enum(_) | StatementKind::Trait(_) => {}
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expression, construct: &str) {
+        if let Some(condition_type) = self.analyze_expression(condition) {
+            self.expect_type(&Type::Bool, &condition_type, condition.span, |_, found| {
+                Diagnostic::error(format!("{} condition must be a boolean", construct), condition.span)
+                    .with_code("E0103")
+                    .with_primary_label(format!("expected `bool`, found `{}`", found))
+            });
+        }
+    }
+
+    // `None` means the expression has an error that was already reported, so
+    // callers skip checks that would only repeat it
+    fn analyze_expression(&mut self, expr: &Expression) -> Option<Type> {
+        match &expr.kind {
+            ExpressionKind::Integer(value, suffix) => {
+                let ty = match suffix {
+                    Some(ty) => ty.clone(),
+                    None => {
+                        let ty = self.fresh_number_var(true);
+                        self.literal_types.insert(expr.span, ty.clone());
+                        ty
+                    }
+                };
+                self.integer_literals.push((expr.span, *value, false, ty.clone()));
+                Some(ty)
+            }
+            ExpressionKind::Float(_, suffix) => match suffix {
+                Some(ty) => Some(ty.clone()),
+                None => {
+                    let ty = self.fresh_number_var(false);
+                    self.literal_types.insert(expr.span, ty.clone());
+                    Some(ty)
+                }
+            },
+            ExpressionKind::String(_) => Some(Type::String),
+            ExpressionKind::Interpolation(desugared) => self.analyze_expression(desugared),
+            ExpressionKind::Boolean(_) => Some(Type::Bool),
+
+            ExpressionKind::Identifier(name) => match self.resolve(name) {
+                Some(id) => {
+                    self.symbols.symbols[id].uses.push(expr.span);
+                    self.symbols.get(id).ty.clone()
+                }
+                None => {
+                    self.report_undefined_variable(name, expr.span);
+                    None
+                }
+            },
+
+            ExpressionKind::Binary { left, operator, right, .. } => {
+                let left_type = self.analyze_expression(left);
+                let right_type = self.analyze_expression(right);
+                let (left_type, right_type) = (left_type?, right_type?);
+
+                if let BinaryOp::And | BinaryOp::Or = operator {
+                    for (operand, operand_type) in [(left, &left_type), (right, &right_type)] {
+                        self.expect_type(&Type::Bool, operand_type, operand.span, |_, found| {
+                            Diagnostic::error(format!("Operands of {} must be booleans", operator), operand.span)
+                                .with_code("E0104")
+                                .with_primary_label(format!("found `{}`", found))
+                        });
+                    }
+                    return Some(Type::Bool);
+                }
+
+                self.expect_type(&left_type, &right_type, expr.span, |left_type, right_type| {
+                    Diagnostic::error(
+                        format!("Binary operation type mismatch: {} {} {}", left_type, operator, right_type),
+                        expr.span,
+                    )
+                    .with_code("E0104")
+                    .with_label(left.span, format!("`{}`", left_type))
+                    .with_label(right.span, format!("`{}`", right_type))
+                });
+
+                // Operators aren't traits, so no bound makes them available
+                if let Type::Param(name) = self.resolve_type(&left_type) {
+                    self.errors.push(
+                        Diagnostic::error(
+                            format!("Binary operator `{}` cannot be applied to type parameter `{}`", operator, name),
+                            expr.span,
+                        )
+                        .with_code("E0104")
+                        .with_note("a value of a type parameter can only be passed around and used through its bounds' methods"),
+                    );
+                    return None;
+                }
+
+                // Strings concatenate and compare, and nothing else
+                let comparison = matches!(
+                    operator,
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater | BinaryOp::Less |
+                    BinaryOp::GreaterEqual | BinaryOp::LessEqual
+                );
+                if self.resolve_type(&left_type) == Type::String && !comparison && !matches!(operator, BinaryOp::Add) {
+                    self.errors.push(
+                        Diagnostic::error(format!("Binary operator `{}` cannot be applied to strings", operator), expr.span)
+                            .with_code("E0104")
+                            .with_note("strings can be joined with `+` and compared"),
+                    );
+                    return None;
+                }
+
+                if let BinaryOp::ShiftLeft | BinaryOp::ShiftRight = operator {
+                    let resolved = self.resolve_type(&left_type);
+                    if !resolved.is_integer() && !matches!(resolved, Type::Var(_)) {
+                        self.errors.push(
+                            Diagnostic::error(format!("Cannot shift a value of type {}", resolved), expr.span)
+                                .with_code("E0104")
+                                .with_primary_label("expected an integer"),
+                        );
+                        return None;
+                    }
+                }
+
+                self.operand_types.insert(expr.span, left_type.clone());
+                match operator {
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater | BinaryOp::Less |
+                    BinaryOp::GreaterEqual | BinaryOp::LessEqual => Some(Type::Bool),
+                    _ => Some(left_type),
+                }
+            }
+
+            ExpressionKind::Unary { operator, operand, .. } => {
+                let operand_type = self.analyze_expression(operand)?;
+                // `-128i8` fits, though `128i8` doesn't
+                if let (UnaryOp::Negate, ExpressionKind::Integer(..)) = (operator, &operand.kind) {
+                    if let Some(literal) = self.integer_literals.last_mut() {
+                        literal.2 = true;
+                    }
+                }
+                self.operand_types.insert(expr.span, operand_type.clone());
+                match operator {
+                    UnaryOp::Negate => {
+                        // An operand whose type isn't known yet can't be checked here
+                        let resolved = self.resolve_type(&operand_type);
+                        let signed = resolved.is_float() || resolved.integer_kind().is_some_and(|(signed, _)| signed);
+                        if !signed && !matches!(resolved, Type::Var(_)) {
+                            self.errors.push(
+                                Diagnostic::error(format!("Cannot negate a value of type {}", resolved), expr.span)
+                                    .with_code("E0104"),
+                            );
+                        }
+                        Some(operand_type)
+                    }
+                    UnaryOp::Not => {
+                        self.expect_type(&Type::Bool, &operand_type, operand.span, |_, _| {
+                            Diagnostic::error("Operand of ! must be a boolean", expr.span).with_code("E0104")
+                        });
+                        Some(Type::Bool)
+                    }
+                }
+            }
+
+            ExpressionKind::Call { function, arguments, .. } => {
+                let argument_types: Vec<_> = arguments.iter().map(|arg| self.analyze_expression(arg)).collect();
+
+                let name = match &function.kind {
+                    ExpressionKind::Identifier(name) => name,
+                    ExpressionKind::Path(path) => {
+                        return self.analyze_variant(path, function.span, Some((arguments, &argument_types)), expr.span);
+                    }
+                    _ => {
+                        self.errors.push(Diagnostic::error("Invalid function call", function.span).with_code("E0106"));
+                        return None;
+                    }
+                };
+
+                if let Some((declared_type, id)) = self.functions.get(name).cloned() {
+                    self.symbols.symbols[id].uses.push(function.span);
+                    let func_type = self.instantiate(declared_type.clone(), expr.span);
+
+                    if arguments.len() != func_type.params.len() {
+                        let signature = self.signature(name, &declared_type);
+                        self.errors.push(
+                            Diagnostic::error(
+                                format!(
+                                    "Wrong number of arguments: expected {}, found {}",
+                                    func_type.params.len(), arguments.len()
+                                ),
+                                expr.span,
+                            )
+                            .with_code("E0105")
+                            .with_note(format!("`{}` is declared as {}", name, signature)),
+                        );
+                    }
+
+                    // Unannotated parameters take their types from the arguments
+                    let checked = arguments.iter().zip(&argument_types).zip(&func_type.params);
+                    for ((arg, arg_type), (_, param_type)) in checked {
+                        if let Some(arg_type) = arg_type {
+                            self.expect_type(param_type, arg_type, arg.span, |expected, found| {
+                                Diagnostic::error(
+                                    format!("Argument type mismatch: expected {}, found {}", expected, found),
+                                    arg.span,
+                                )
+                                .with_code("E0103")
+                                .with_primary_label(format!("expected `{}`", expected))
+                            });
+                        }
+                    }
+
+                    Some(func_type.return_type)
+                } else if name == "print" || name == "println" {
+                    // Runtime builtins; codegen picks the overload from the
+                    // argument type, recorded like a type argument
+                    if arguments.len() != 1 {
+                        self.errors.push(
+                            Diagnostic::error(format!("{} expects exactly one argument", name), expr.span)
+                                .with_code("E0105"),
+                        );
+                    } else if let Some(argument_type) = &argument_types[0] {
+                        self.instantiations.insert(expr.span, vec![("T".to_string(), argument_type.clone())]);
+                    }
+                    Some(Type::Int)
+                } else if name == "to_string" {
+                    // Also a runtime builtin, which interpolated strings call
+                    match (arguments.as_slice(), argument_types.as_slice()) {
+                        ([argument], [Some(argument_type)]) => {
+                            let resolved = self.resolve_type(argument_type);
+                            if resolved.is_numeric() || matches!(resolved, Type::Bool | Type::String | Type::Var(_)) {
+                                self.instantiations.insert(expr.span, vec![("T".to_string(), argument_type.clone())]);
+                            } else {
+                                self.errors.push(
+                                    Diagnostic::error(
+                                        format!("Cannot convert a value of type {} to a string", resolved),
+                                        argument.span,
+                                    )
+                                    .with_code("E0104")
+                                    .with_primary_label("expected a number, a bool or a string"),
+                                );
+                            }
+                        }
+                        ([_], [None]) => {}
+                        _ => {
+                            self.errors.push(
+                                Diagnostic::error("to_string expects exactly one argument", expr.span).with_code("E0105"),
+                            );
+                        }
+                    }
+                    Some(Type::String)
+                } else if name == "len" {
+                    // The length of an array or a slice, or of a string in bytes
+                    match (arguments.as_slice(), argument_types.as_slice()) {
+                        ([argument], [Some(argument_type)]) => {
+                            if self.resolve_type(argument_type) != Type::String {
+                                self.element_type(argument_type, argument.span, expr.span, "take the length of")?;
+                            }
+                        }
+                        ([_], [None]) => return None,
+                        _ => {
+                            self.errors.push(
+                                Diagnostic::error("len expects exactly one argument", expr.span).with_code("E0105"),
+                            );
+                        }
+                    }
+                    Some(Type::Usize)
+                } else {
+                    let mut error = Diagnostic::error(format!("Undefined function: {}", name), function.span)
+                        .with_code("E0102")
+                        .with_primary_label("not found in this scope");
+                    if let Some(similar) = similar_name(name, self.functions.keys()) {
+                        error = error.with_suggestion("a function with a similar name exists", function.span, similar);
+                    }
+                    self.errors.push(error);
+                    None
+                }
+            }
+
+            ExpressionKind::StructLiteral { name, fields, .. } => {
+                let name_span = Span::new(expr.span.file_id, expr.span.start, expr.span.start + name.len());
+                let field_types: Vec<_> = fields.iter().map(|(_, value)| self.analyze_expression(value)).collect();
+
+                let (struct_type, id) = match self.structs.get(name).cloned() {
+                    Some(found) => found,
+                    None => {
+                        let mut error = Diagnostic::error(format!("Undefined struct: {}", name), name_span)
+                            .with_code("E0112")
+                            .with_primary_label("not found in this scope");
+                        if let Some(similar) = similar_name(name, self.structs.keys()) {
+                            error = error.with_suggestion("a struct with a similar name exists", name_span, similar);
+                        }
+                        self.errors.push(error);
+                        return None;
+                    }
+                };
+                self.symbols.symbols[id].uses.push(name_span);
+                let arguments = self.instantiate_params(&struct_type.generics, expr.span);
+
+                let mut initialized: Vec<&str> = Vec::new();
+                for ((field, value), value_type) in fields.iter().zip(field_types) {
+                    if initialized.contains(&field.as_str()) {
+                        self.errors.push(
+                            Diagnostic::error(format!("Field `{}` specified more than once", field), value.span)
+                                .with_code("E0115"),
+                        );
+                        continue;
+                    }
+                    initialized.push(field);
+
+                    let Some(field_type) = struct_type.field(field) else {
+                        self.report_unknown_field(name, field, &struct_type, value.span);
+                        continue;
+                    };
+                    if let Some(value_type) = value_type {
+                        let field_type = field_type.substitute(&arguments);
+                        self.expect_type(&field_type, &value_type, value.span, |expected, found| {
+                            Diagnostic::error(format!("Type mismatch: expected {}, found {}", expected, found), value.span)
+                                .with_code("E0103")
+                                .with_primary_label(format!("expected `{}`", expected))
+                        });
+                    }
+                }
+
+                let missing: Vec<_> = struct_type
+                    .fields
+                    .iter()
+                    .map(|(field, _)| field.as_str())
+                    .filter(|field| !initialized.contains(field))
+                    .collect();
+                if !missing.is_empty() {
+                    let list: Vec<_> = missing.iter().map(|field| format!("`{}`", field)).collect();
+                    let noun = if missing.len() == 1 { "field" } else { "fields" };
+                    self.errors.push(
+                        Diagnostic::error(
+                            format!("Missing {} {} in initializer of `{}`", noun, list.join(", "), name),
+                            expr.span,
+                        )
+                        .with_code("E0114")
+                        .with_primary_label(format!("missing {}", list.join(", "))),
+                    );
+                }
+
+                let arguments = struct_type.generics.iter().map(|param| arguments[&param.name].clone()).collect();
+                Some(Type::Custom(name.clone(), arguments))
+            }
+
+            ExpressionKind::FieldAccess { object, field } => {
+                let object_type = self.analyze_expression(object)?;
+                match self.auto_deref(&object_type, expr.span) {
+                    Type::Custom(name, arguments) => {
+                        let (struct_type, _) = self.structs.get(&name).cloned()?;
+                        match struct_type.field(field) {
+                            Some(field_type) => Some(field_type.substitute(&struct_type.arguments(&arguments))),
+                            None => {
+                                self.report_unknown_field(&name, field, &struct_type, expr.span);
+                                None
+                            }
+                        }
+                    }
+                    Type::Var(_) => {
+                        self.errors.push(
+                            Diagnostic::error(format!("Type annotations needed to access field `{}`", field), object.span)
+                                .with_code("E0110")
+                                .with_primary_label("type must be known at this point"),
+                        );
+                        None
+                    }
+                    ty => {
+                        self.errors.push(
+                            Diagnostic::error(format!("No field `{}` on type {}", field, ty), expr.span)
+                                .with_code("E0113"),
+                        );
+                        None
+                    }
+                }
+            }
+
+            ExpressionKind::Path(path) => self.analyze_variant(path, expr.span, None, expr.span),
+
+            ExpressionKind::Borrow { operand, mutable } => {
+                let operand_type = self.analyze_expression(operand)?;
+                if *mutable && self.through_slice(operand) {
+                    self.report_slice_element(operand.span, true);
+                } else if *mutable && self.behind_shared_ref(operand) {
+                    self.errors.push(
+                        Diagnostic::error("Cannot borrow as mutable through a `ref` reference", operand.span)
+                            .with_code("E0128")
+                            .with_primary_label("the place is borrowed with `ref`, so it can't be changed")
+                            .with_note("borrow it with `ref mut` to change it through the reference"),
+                    );
+                } else if *mutable {
+                    self.check_mutable_root(operand, true);
+                }
+                Some(match mutable {
+                    true => Type::RefMut(Box::new(operand_type)),
+                    false => Type::Ref(Box::new(operand_type)),
+                })
+            }
+
+            ExpressionKind::Move(operand) => self.analyze_expression(operand),
+
+            ExpressionKind::Deref { operand, .. } => {
+                let operand_type = self.analyze_expression(operand)?;
+                match self.resolve_type(&operand_type) {
+                    Type::Ref(pointee) => {
+                        self.derefs.insert(expr.span, (*pointee.clone(), false));
+                        Some(*pointee)
+                    }
+                    Type::RefMut(pointee) => {
+                        self.derefs.insert(expr.span, (*pointee.clone(), true));
+                        Some(*pointee)
+                    }
+                    Type::Var(_) => {
+                        self.errors.push(
+                            Diagnostic::error("Type annotations needed to dereference a value", operand.span)
+                                .with_code("E0110")
+                                .with_primary_label("type must be known at this point"),
+                        );
+                        None
+                    }
+                    ty => {
+                        self.errors.push(
+                            Diagnostic::error(format!("Cannot dereference a value of type {}", ty), expr.span)
+                                .with_code("E0104")
+                                .with_primary_label("not a reference"),
+                        );
+                        None
+                    }
+                }
+            }
+
+            ExpressionKind::Match { scrutinee, arms, .. } => {
+                // Patterns are still checked when the scrutinee has an error,
+                // so the bindings they introduce are declared
+                let scrutinee_type = match self.analyze_expression(scrutinee) {
+                    Some(ty) => ty,
+                    None => self.fresh_var(),
+                };
+                self.operand_types.insert(expr.span, scrutinee_type.clone());
+                let result_type = self.fresh_var();
+
+                let errors = self.errors.len();
+                let mut patterns = Vec::new();
+                for arm in arms {
+                    self.push_scope();
+                    patterns.push(self.check_pattern(&arm.pattern, &scrutinee_type));
+                    if let Some(body_type) = self.analyze_expression(&arm.body) {
+                        self.expect_type(&result_type, &body_type, arm.body.span, |expected, found| {
+                            Diagnostic::error(
+                                format!("Match arms have incompatible types: expected {}, found {}", expected, found),
+                                arm.body.span,
+                            )
+                            .with_code("E0103")
+                            .with_primary_label(format!("expected `{}`", expected))
+                        });
+                    }
+                    self.pop_scope();
+                }
+
+                // Patterns with errors would only lead to misleading coverage
+                if self.errors.len() == errors {
+                    self.check_exhaustiveness(scrutinee, &scrutinee_type, arms, &patterns);
+                }
+                Some(result_type)
+            }
+
+            ExpressionKind::If { condition, then_branch, else_branch } => {
+                self.check_condition(condition, "If");
+                let then_type = self.analyze_expression(then_branch);
+                let else_type = self.analyze_expression(else_branch);
+                let (then_type, else_type) = (then_type?, else_type?);
+                self.expect_type(&then_type, &else_type, else_branch.span, |expected, found| {
+                    Diagnostic::error(
+                        format!("`if` and `else` have incompatible types: expected {}, found {}", expected, found),
+                        else_branch.span,
+                    )
+                    .with_code("E0103")
+                    .with_primary_label(format!("expected `{}`", expected))
+                    .with_label(then_branch.span, "expected because of this")
+                });
+                Some(then_type)
+            }
+
+            ExpressionKind::ArrayLiteral(elements) => {
+                // An empty literal gets its element type from how the array is used
+                let element_type = self.fresh_var();
+                for element in elements {
+                    if let Some(found) = self.analyze_expression(element) {
+                        self.expect_type(&element_type, &found, element.span, |expected, found| {
+                            Diagnostic::error(
+                                format!("Array element type mismatch: expected {}, found {}", expected, found),
+                                element.span,
+                            )
+                            .with_code("E0103")
+                            .with_primary_label(format!("expected `{}`", expected))
+                        });
+                    }
+                }
+                Some(Type::Array(Box::new(element_type), elements.len()))
+            }
+
+            ExpressionKind::Index { array, index, .. } => {
+                if let ExpressionKind::Range { start, end } = &index.kind {
+                    return self.analyze_string_slice(array, start, end, expr.span);
+                }
+                let array_type = self.analyze_expression(array);
+                let index_type = self.analyze_expression(index);
+                if let Some(index_type) = index_type {
+                    self.expect_type(&Type::Usize, &index_type, index.span, |_, found| {
+                        Diagnostic::error(format!("Array index must be a usize, found {}", found), index.span)
+                            .with_code("E0103")
+                            .with_primary_label("expected `usize`")
+                    });
+                }
+
+                self.element_type(&array_type?, array.span, expr.span, "index into")
+            }
+
+            ExpressionKind::Range { start, end } => {
+                self.analyze_expression(start);
+                self.analyze_expression(end);
+                self.errors.push(
+                    Diagnostic::error("Ranges can only be used to slice a string", expr.span)
+                        .with_code("E0104")
+                        .with_primary_label("expected `s[start..end]`"),
+                );
+                None
+            }
+
+            ExpressionKind::Cast { operand, target, .. } => {
+                let operand_type = self.analyze_expression(operand)?;
+                let source = self.resolve_type(&operand_type);
+                let castable = match &source {
+                    Type::Bool => target.is_integer(),
+                    Type::Var(_) => false,
+                    source => source.is_numeric() && target.is_numeric(),
+                };
+                if !castable {
+                    self.errors.push(
+                        Diagnostic::error(format!("Cannot cast a value of type {} as {}", source, target), expr.span)
+                            .with_code("E0130")
+                            .with_primary_label("invalid cast")
+                            .with_note("only numbers can be cast, to other numbers, and `bool` to an integer"),
+                    );
+                    return None;
+                }
+                self.operand_types.insert(expr.span, operand_type);
+                Some(target.clone())
+            }
+
+            ExpressionKind::Slice { operand, .. } => {
+                let operand_type = self.analyze_expression(operand)?;
+                let element_type = self.element_type(&operand_type, operand.span, expr.span, "take a slice of")?;
+                Some(Type::Slice(Box::new(element_type)))
+            }
+
+            ExpressionKind::MethodCall { receiver, method, arguments, .. } => {
+                let receiver_type = self.analyze_expression(receiver);
+                let argument_types: Vec<_> = arguments.iter().map(|arg| self.analyze_expression(arg)).collect();
+                let receiver_type = self.auto_deref(&receiver_type?, expr.span);
+                let (trait_name, params, return_type) = self.resolve_method(&receiver_type, method, receiver.span, expr.span)?;
+
+                if arguments.len() + 1 != params.len() {
+                    self.errors.push(
+                        Diagnostic::error(
+                            format!(
+                                "Wrong number of arguments: expected {}, found {}",
+                                params.len() - 1, arguments.len()
+                            ),
+                            expr.span,
+                        )
+                        .with_code("E0105")
+                        .with_note(format!("`{}` is declared in trait `{}`", method, trait_name)),
+                    );
+                }
+
+                let self_type = HashMap::from([("Self".to_string(), receiver_type.clone())]);
+                for ((arg, arg_type), param_type) in arguments.iter().zip(&argument_types).zip(&params[1..]) {
+                    if let Some(arg_type) = arg_type {
+                        self.expect_type(&param_type.substitute(&self_type), arg_type, arg.span, |expected, found| {
+                            Diagnostic::error(
+                                format!("Argument type mismatch: expected {}, found {}", expected, found),
+                                arg.span,
+                            )
+                            .with_code("E0103")
+                            .with_primary_label(format!("expected `{}`", expected))
+                        });
+                    }
+                }
+
+                self.receivers.insert(expr.span, receiver_type);
+                match return_type {
+                    Some(ty) => Some(ty.substitute(&self_type)),
+                    // Like a call to a function that returns nothing
+                    None => Some(self.fresh_var()),
+                }
+            }
+        }
+    }
+
+    // The type reached through the object of the field access or method call
+    // at `span`: the referenced type if `ty` is a reference, else `ty`
+    fn auto_deref(&mut self, ty: &Type, span: Span) -> Type {
+        match self.resolve_type(ty) {
+            Type::Ref(pointee) => {
+                self.auto_derefs.insert(span, (*pointee.clone(), false));
+                *pointee
+            }
+            Type::RefMut(pointee) => {
+                self.auto_derefs.insert(span, (*pointee.clone(), true));
+                *pointee
+            }
+            ty => ty,
+        }
+    }
+
+    // Whether the place `target` may be assigned to, reporting why not
+    fn check_assignable(&mut self, target: &Expression) -> bool {
+        if self.through_slice(target) {
+            self.report_slice_element(target.span, false);
+            return false;
+        }
+        if self.behind_shared_ref(target) {
+            self.errors.push(
+                Diagnostic::error("Cannot assign through a `ref` reference", target.span)
+                    .with_code("E0128")
+                    .with_primary_label("the place is borrowed with `ref`, so it can't be changed")
+                    .with_note("borrow it with `ref mut` to change it through the reference"),
+            );
+            return false;
+        }
+        if let ExpressionKind::Identifier(name) = &target.kind {
+            let id = self.resolve(name).expect("assignment target was resolved");
+            if !self.mutable.contains(&id) {
+                let error = Diagnostic::error(format!("Cannot assign to immutable variable `{}`", name), target.span)
+                    .with_code("E0118")
+                    .with_primary_label("cannot assign twice")
+                    .with_label(self.symbols.get(id).definition, "first assigned here");
+                self.errors.push(self.suggest_mut(error, id));
+                return false;
+            }
+            return true;
+        }
+        self.check_mutable_root(target, false)
+    }
+
+    // Whether the variable that holds the place `expr` is `let mut`, when the
+    // place isn't reached through a reference, for assigning to the place or
+    // for borrowing it with `ref mut`
+    fn check_mutable_root(&mut self, expr: &Expression, borrow: bool) -> bool {
+        let Some(id) = self.place_root(expr) else {
+            return true;
+        };
+        if self.mutable.contains(&id) {
+            return true;
+        }
+        let root = &self.symbols.get(id).name;
+        let (message, label) = if borrow {
+            (format!("Cannot borrow `{}` as mutable, as `{}` is not declared as mutable", place_name(expr), root), "cannot borrow as mutable")
+        } else {
+            (format!("Cannot assign to `{}`, as `{}` is not declared as mutable", place_name(expr), root), "cannot assign")
+        };
+        let error = Diagnostic::error(message, expr.span).with_code("E0118").with_primary_label(label);
+        self.errors.push(self.suggest_mut(error, id));
+        false
+    }
+
+    fn suggest_mut(&self, error: Diagnostic, id: SymbolId) -> Diagnostic {
+        let symbol = self.symbols.get(id);
+        match symbol.kind {
+            SymbolKind::Variable => {
+                error.with_suggestion("make the binding mutable", symbol.definition, format!("mut {}", symbol.name))
+            }
+            _ => error.with_note(format!("parameters can't be changed; copy `{}` into a `let mut` binding", symbol.name)),
+        }
+    }
+
+    // The variable whose value holds the place `expr`, or `None` if the place
+    // is reached through a reference
+    fn place_root(&self, expr: &Expression) -> Option<SymbolId> {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => self.resolve(name),
+            ExpressionKind::FieldAccess { object, .. } if !self.auto_derefs.contains_key(&expr.span) => self.place_root(object),
+            ExpressionKind::Index { array, .. } if !self.through_slice(expr) => self.place_root(array),
+            _ => None,
+        }
+    }
+
+    // Whether the place `expr` is an element of a slice, or a slice of a
+    // string, which are read-only
+    fn through_slice(&self, expr: &Expression) -> bool {
+        match &expr.kind {
+            ExpressionKind::Index { array, .. } => {
+                matches!(self.array_types.get(&expr.span), Some(Type::Slice(_) | Type::String)) || self.through_slice(array)
+            }
+            ExpressionKind::FieldAccess { object, .. } => self.through_slice(object),
+            _ => false,
+        }
+    }
+
+    fn report_slice_element(&mut self, span: Span, borrow: bool) {
+        let message = match borrow {
+            true => "Cannot borrow an element of a slice as mutable",
+            false => "Cannot assign to an element of a slice",
+        };
+        self.errors.push(
+            Diagnostic::error(message, span)
+                .with_code("E0128")
+                .with_primary_label("slices are read-only views of an array")
+                .with_note("change the array the slice was taken from instead"),
+        );
+    }
+
+    // The element type of the array or slice of type `ty` that the
+    // expression at `span` indexes or slices, recording which it is
+    // `s[start..end]`, the bytes of the string `s` from `start` up to `end`
+    fn analyze_string_slice(&mut self, string: &Expression, start: &Expression, end: &Expression, span: Span) -> Option<Type> {
+        let string_type = self.analyze_expression(string);
+        for bound in [start, end] {
+            if let Some(bound_type) = self.analyze_expression(bound) {
+                self.expect_type(&Type::Usize, &bound_type, bound.span, |_, found| {
+                    Diagnostic::error(format!("Range bounds must be usize, found {}", found), bound.span)
+                        .with_code("E0103")
+                        .with_primary_label("expected `usize`")
+                });
+            }
+        }
+
+        let string_type = self.resolve_type(&string_type?);
+        if string_type != Type::String {
+            self.errors.push(
+                Diagnostic::error(format!("Cannot slice a value of type {} with a range", string_type), string.span)
+                    .with_code("E0104")
+                    .with_primary_label("only strings can be sliced with a range"),
+            );
+            return None;
+        }
+        self.array_types.insert(span, Type::String);
+        Some(Type::String)
+    }
+
+    fn element_type(&mut self, ty: &Type, operand_span: Span, span: Span, action: &str) -> Option<Type> {
+        let ty = self.resolve_type(ty);
+        match &ty {
+            Type::Array(element, _) | Type::Slice(element) => {
+                let element = *element.clone();
+                self.array_types.insert(span, ty);
+                Some(element)
+            }
+            Type::Var(_) => {
+                self.errors.push(
+                    Diagnostic::error(format!("Type annotations needed to {} a value", action), operand_span)
+                        .with_code("E0110")
+                        .with_primary_label("type must be known at this point"),
+                );
+                None
+            }
+            ty => {
+                self.errors.push(
+                    Diagnostic::error(format!("Cannot {} a value of type {}", action, ty), operand_span)
+                        .with_code("E0104")
+                        .with_primary_label("not an array or a slice"),
+                );
+                None
+            }
+        }
+    }
+
+    // Whether the place `expr` is reached through a shared reference
+    fn behind_shared_ref(&self, expr: &Expression) -> bool {
+        match &expr.kind {
+            ExpressionKind::FieldAccess { object, .. } => {
+                self.auto_derefs.get(&expr.span).is_some_and(|(_, mutable)| !mutable) || self.behind_shared_ref(object)
+            }
+            ExpressionKind::Deref { .. } => self.derefs.get(&expr.span).is_some_and(|(_, mutable)| !mutable),
+            _ => false,
+        }
+    }
+
+    // The trait that provides `method` for a receiver of type `ty`, with the
+    // method's signature. A type parameter offers the methods of its bounds,
+    // any other type those of the traits implemented for it.
+    fn resolve_method(
+        &mut self,
+        ty: &Type,
+        method: &str,
+        receiver_span: Span,
+        span: Span,
+    ) -> Option<(String, Vec<Type>, Option<Type>)> {
+        let candidates: Vec<String> = match ty {
+            Type::Var(_) => {
+                self.errors.push(
+                    Diagnostic::error(format!("Type annotations needed to call method `{}`", method), receiver_span)
+                        .with_code("E0110")
+                        .with_primary_label("type must be known at this point"),
+                );
+                return None;
+            }
+            Type::Param(name) => self
+                .type_params
+                .iter()
+                .filter(|param| &param.name == name)
+                .flat_map(|param| param.bounds.iter().map(|(bound, _)| bound.clone()))
+                .collect(),
+            ty => self
+                .impls
+                .iter()
+                .filter(|(_, self_type)| self_type == ty)
+                .map(|(trait_name, _)| trait_name.clone())
+                .collect(),
+        };
+
+        let mut found: Vec<_> = candidates
+            .into_iter()
+            .filter_map(|trait_name| {
+                let (trait_type, _) = self.traits.get(&trait_name)?;
+                let (_, params, return_type) = trait_type.method(method)?.clone();
+                Some((trait_name, params, return_type))
+            })
+            .collect();
+        found.dedup_by(|a, b| a.0 == b.0);
+
+        match found.len() {
+            1 => found.pop(),
+            0 => {
+                let mut error = Diagnostic::error(format!("No method named `{}` found for type {}", method, ty), span)
+                    .with_code("E0123")
+                    .with_primary_label("method not found");
+                let mut providers: Vec<_> = self
+                    .traits
+                    .iter()
+                    .filter(|(_, (trait_type, _))| trait_type.method(method).is_some())
+                    .map(|(trait_name, _)| trait_name.clone())
+                    .collect();
+                providers.sort();
+                if let Some(provider) = providers.first() {
+                    let note = match ty {
+                        Type::Param(name) => format!("consider adding `where {}: {}`", name, provider),
+                        _ => format!("`{}` is a method of trait `{}`", method, provider),
+                    };
+                    error = error.with_note(note);
+                }
+                self.errors.push(error);
+                None
+            }
+            _ => {
+                let traits: Vec<_> = found.iter().map(|(trait_name, _, _)| format!("`{}`", trait_name)).collect();
+                self.errors.push(
+                    Diagnostic::error(format!("Multiple applicable methods named `{}` for type {}", method, ty), span)
+                        .with_code("E0123")
+                        .with_primary_label("ambiguous method call")
+                        .with_note(format!("`{}` is a method of {}", method, traits.join(" and "))),
+                );
+                None
+            }
+        }
+    }
+
+    // Every type a bound was placed on must implement the trait, once
+    // inference has settled what it is
+    fn check_obligations(&mut self) {
+        for obligation in std::mem::take(&mut self.obligations) {
+            let ty = self.resolve_type(&obligation.ty);
+            let satisfied = match &ty {
+                // Already reported as needing annotations
+                ty if contains_var(ty) => true,
+                Type::Param(name) => obligation
+                    .type_params
+                    .iter()
+                    .any(|param| &param.name == name && param.bounds.iter().any(|(bound, _)| bound == &obligation.bound)),
+                ty => self
+                    .impls
+                    .iter()
+                    .any(|(trait_name, self_type)| trait_name == &obligation.bound && self_type == ty),
+            };
+            if satisfied {
+                continue;
+            }
+
+            let mut error = Diagnostic::error(
+                format!("The trait `{}` is not implemented for `{}`", obligation.bound, ty),
+                obligation.span,
+            )
+            .with_code("E0122")
+            .with_primary_label(format!("the trait `{}` is not implemented for `{}`", obligation.bound, ty))
+            .with_label(obligation.bound_span, "required by this bound");
+            if let Type::Param(name) = &ty {
+                error = error.with_note(format!("consider adding `where {}: {}`", name, obligation.bound));
+            }
+            self.errors.push(error);
+        }
+    }
+
+    // Every integer literal must fit the type inference settled on, so
+    // `300u8` or a `let x: i8 = 200;` is an error rather than a silent wrap
+    fn check_integer_literals(&mut self) {
+        for (span, value, negated, ty) in std::mem::take(&mut self.integer_literals) {
+            let ty = self.resolve_type(&ty);
+            if !ty.is_integer() {
+                continue;
+            }
+            let (min, max) = integer_range(&ty);
+            let fits = if negated { value <= min.unsigned_abs() } else { value <= max as u128 };
+            if !fits {
+                self.errors.push(
+                    Diagnostic::error(format!("Literal out of range for {}", ty), span)
+                        .with_code("E0131")
+                        .with_primary_label(format!("doesn't fit in `{}`", ty))
+                        .with_note(format!("the range of `{}` is `{}..={}`", ty, min, max)),
+                );
+            }
+        }
+    }
+
+    // `Enum::Variant` on its own, or called with `arguments` for its payload
+    fn analyze_variant(
+        &mut self,
+        path: &[String],
+        path_span: Span,
+        arguments: Option<(&[Expression], &[Option<Type>])>,
+        span: Span,
+    ) -> Option<Type> {
+        let (enum_name, enum_type, index) = self.resolve_variant(path, path_span)?;
+        let field_types = &enum_type.variants[index].1;
+        let (arguments, argument_types) = arguments.unwrap_or((&[], &[]));
+
+        if arguments.len() != field_types.len() {
+            self.errors.push(
+                Diagnostic::error(
+                    format!(
+                        "Variant `{}` has {} {}, but {} {} supplied",
+                        path.join("::"),
+                        field_types.len(),
+                        if field_types.len() == 1 { "field" } else { "fields" },
+                        arguments.len(),
+                        if arguments.len() == 1 { "was" } else { "were" },
+                    ),
+                    span,
+                )
+                .with_code("E0105"),
+            );
+        }
+
+        for ((arg, arg_type), field_type) in arguments.iter().zip(argument_types).zip(field_types) {
+            if let Some(arg_type) = arg_type {
+                self.expect_type(field_type, arg_type, arg.span, |expected, found| {
+                    Diagnostic::error(format!("Type mismatch: expected {}, found {}", expected, found), arg.span)
+                        .with_code("E0103")
+                        .with_primary_label(format!("expected `{}`", expected))
+                });
+            }
+        }
+        Some(Type::Custom(enum_name, Vec::new()))
+    }
+
+    // The enum and variant index that `Enum::Variant` names
+    fn resolve_variant(&mut self, path: &[String], span: Span) -> Option<(String, EnumType, usize)> {
+        let [enum_name, variant] = path else {
+            self.errors.push(
+                Diagnostic::error(format!("Cannot resolve path `{}`", path.join("::")), span)
+                    .with_code("E0119")
+                    .with_primary_label("expected `Enum::Variant`"),
+            );
+            return None;
+        };
+
+        let enum_span = Span::new(span.file_id, span.start, span.start + enum_name.len());
+        let Some((enum_type, id)) = self.enums.get(enum_name).cloned() else {
+            let mut error = Diagnostic::error(format!("Undefined enum: {}", enum_name), enum_span)
+                .with_code("E0112")
+                .with_primary_label("not found in this scope");
+            if let Some(similar) = similar_name(enum_name, self.enums.keys()) {
+                error = error.with_suggestion("an enum with a similar name exists", enum_span, similar);
+            }
+            self.errors.push(error);
+            return None;
+        };
+        self.symbols.symbols[id].uses.push(enum_span);
+
+        let Some(index) = enum_type.variant(variant) else {
+            let available: Vec<_> = enum_type.variants.iter().map(|(name, _)| format!("`{}`", name)).collect();
+            let mut error = Diagnostic::error(format!("Enum `{}` has no variant named `{}`", enum_name, variant), span)
+                .with_code("E0119")
+                .with_primary_label("unknown variant");
+            if !available.is_empty() {
+                error = error.with_note(format!("available variants are: {}", available.join(", ")));
+            }
+            self.errors.push(error);
+            return None;
+        };
+        Some((enum_name.clone(), enum_type, index))
+    }
+
+    // Type-check `pattern` against a scrutinee of type `expected` and declare
+    // its bindings in the current scope
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &Type) -> Pat {
+        match &pattern.kind {
+            PatternKind::Wildcard => Pat::Wild,
+
+            PatternKind::Binding(name) => {
+                if let Some(&previous) = self.scopes.last().unwrap().names.get(name) {
+                    self.errors.push(
+                        Diagnostic::error(format!("Identifier `{}` is bound more than once in the same pattern", name), pattern.span)
+                            .with_code("E0108")
+                            .with_label(self.symbols.get(previous).definition, "first bound here"),
+                    );
+                }
+                self.declare_variable(name, pattern.span, SymbolKind::Variable, Some(expected.clone()));
+                Pat::Wild
+            }
+
+            PatternKind::Integer(value) => {
+                let integer = self.fresh_number_var(true);
+                self.expect_pattern_type(&integer, expected, pattern);
+                Pat::Range(*value, *value)
+            }
+
+            PatternKind::Boolean(value) => {
+                self.expect_pattern_type(&Type::Bool, expected, pattern);
+                Pat::Bool(*value)
+            }
+
+            PatternKind::Range { start, end, inclusive } => {
+                let integer = self.fresh_number_var(true);
+                self.expect_pattern_type(&integer, expected, pattern);
+                let last = if *inclusive { Some(*end) } else { end.checked_sub(1) };
+                match last {
+                    Some(last) if *start <= last => Pat::Range(*start, last),
+                    _ => {
+                        self.errors.push(
+                            Diagnostic::error(format!("Range pattern `{}` matches no values", pattern), pattern.span)
+                                .with_code("E0120")
+                                .with_primary_label("lower bound is past the upper bound"),
+                        );
+                        Pat::Wild
+                    }
+                }
+            }
+
+            PatternKind::Variant { path, fields } => {
+                let resolved = self.resolve_variant(path, pattern.span);
+                let field_types = match resolved {
+                    Some((enum_name, enum_type, index)) => {
+                        let matches = self.expect_pattern_type(&Type::Custom(enum_name, Vec::new()), expected, pattern);
+                        let field_types = enum_type.variants[index].1.clone();
+                        if fields.len() != field_types.len() {
+                            self.errors.push(
+                                Diagnostic::error(
+                                    format!(
+                                        "Pattern has {} {}, but variant `{}` has {}",
+                                        fields.len(),
+                                        if fields.len() == 1 { "field" } else { "fields" },
+                                        path.join("::"),
+                                        field_types.len()
+                                    ),
+                                    pattern.span,
+                                )
+                                .with_code("E0105"),
+                            );
+                        }
+                        (matches && fields.len() == field_types.len()).then_some((index, field_types))
+                    }
+                    None => None,
+                };
+
+                match field_types {
+                    Some((index, field_types)) => {
+                        let fields = fields
+                            .iter()
+                            .zip(&field_types)
+                            .map(|(field, field_type)| self.check_pattern(field, field_type))
+                            .collect();
+                        Pat::Variant(index, fields)
+                    }
+                    // Still declare the bindings, so their uses aren't reported too
+                    None => {
+                        for field in fields {
+                            let field_type = self.fresh_var();
+                            self.check_pattern(field, &field_type);
+                        }
+                        Pat::Wild
+                    }
+                }
+            }
+        }
+    }
+
+    fn expect_pattern_type(&mut self, pattern_type: &Type, expected: &Type, pattern: &Pattern) -> bool {
+        self.expect_type(expected, pattern_type, pattern.span, |expected, found| {
+            Diagnostic::error(format!("Pattern type mismatch: expected {}, found {}", expected, found), pattern.span)
+                .with_code("E0103")
+                .with_primary_label(format!("expected `{}`", expected))
+        })
+    }
+
+    // Warn about arms that earlier arms already cover, and require that some
+    // arm matches every value of the scrutinee
+    fn check_exhaustiveness(&mut self, scrutinee: &Expression, scrutinee_type: &Type, arms: &[MatchArm], patterns: &[Pat]) {
+        let enums = self
+            .enums
+            .iter()
+            .map(|(name, (enum_type, _))| {
+                let variants = enum_type
+                    .variants
+                    .iter()
+                    .map(|(variant, fields)| (variant.clone(), fields.iter().map(|ty| self.pattern_domain(ty)).collect()))
+                    .collect();
+                (name.clone(), variants)
+            })
+            .collect();
+        let checker = patterns::Checker::new(enums);
+        let domain = self.pattern_domain(scrutinee_type);
+        let (reachable, missing) = checker.check(patterns, &domain);
+
+        for (arm, reachable) in arms.iter().zip(reachable) {
+            if !reachable {
+                self.warnings.push(
+                    Diagnostic::warning("unreachable pattern", arm.pattern.span)
+                        .with_primary_label("every value it matches is matched by an earlier arm"),
+                );
+            }
+        }
+
+        if let Some(missing) = missing {
+            let missing = checker.display(&missing, &domain);
+            self.errors.push(
+                Diagnostic::error(format!("Non-exhaustive patterns: `{}` not covered", missing), scrutinee.span)
+                    .with_code("E0121")
+                    .with_primary_label(format!("pattern `{}` not covered", missing))
+                    .with_note("add an arm that matches it, or a `_` arm for every remaining value"),
+            );
+        }
+    }
+
+    fn pattern_domain(&self, ty: &Type) -> Domain {
+        let ty = self.resolve_type(ty);
+        match ty {
+            // Wider integers can hold values that patterns can't be written for
+            Type::I8 | Type::I16 | Type::Int | Type::I64 | Type::U8 | Type::U16 | Type::U32 => {
+                let (min, max) = integer_range(&ty);
+                Domain::Int(ty.to_string(), min as i64, max as i64)
+            }
+            Type::Bool => Domain::Bool,
+            Type::Custom(name, _) if self.enums.contains_key(&name) => Domain::Enum(name),
+            _ => Domain::Other,
+        }
+    }
+
+    fn report_unknown_field(&mut self, struct_name: &str, field: &str, struct_type: &StructType, span: Span) {
+        let available: Vec<_> = struct_type.fields.iter().map(|(name, _)| format!("`{}`", name)).collect();
+        let mut error = Diagnostic::error(format!("Struct `{}` has no field named `{}`", struct_name, field), span)
+            .with_code("E0113")
+            .with_primary_label("unknown field");
+        if !available.is_empty() {
+            error = error.with_note(format!("available fields are: {}", available.join(", ")));
+        }
+        self.errors.push(error);
+    }
+
+    fn report_undefined_variable(&mut self, name: &str, span: Span) {
+        let mut error = Diagnostic::error(format!("Undefined variable: {}", name), span)
+            .with_code("E0101")
+            .with_primary_label("not found in this scope");
+        let visible = self.scopes.iter().flat_map(|scope| scope.names.keys());
+        if let Some(similar) = similar_name(name, visible) {
+            error = error.with_suggestion("a variable with a similar name exists", span, similar);
+        }
+
+        self.unresolved.push(UnresolvedUse {
+            name: name.to_string(),
+            span,
+            error: self.errors.len(),
+            scopes: self.scopes.iter().map(|scope| scope.id).collect(),
+        });
+        self.errors.push(error);
+    }
+
+    fn fresh_var(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    // The type of an unsuffixed integer or float literal
+    fn fresh_number_var(&mut self, integer: bool) -> Type {
+        self.bindings.push(None);
+        let var = self.bindings.len() - 1;
+        match integer {
+            true => self.integer_vars.insert(var),
+            false => self.float_vars.insert(var),
+        };
+        Type::Var(var)
+    }
+
+    // Follow variable bindings until reaching a concrete type or an unbound variable
+    fn shallow_resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty;
+        while let Type::Var(var) = ty {
+            match &self.bindings[*var] {
+                Some((bound, _)) => ty = bound,
+                None => break,
+            }
+        }
+        ty.clone()
+    }
+
+    // Substitute every bound variable in `ty`, however deeply nested. The
+    // type of a literal that nothing decided is `i32` or `f64`.
+    fn resolve_type(&self, ty: &Type) -> Type {
+        match self.shallow_resolve(ty) {
+            Type::Var(var) if self.integer_vars.contains(&var) => Type::Int,
+            Type::Var(var) if self.float_vars.contains(&var) => Type::Float,
+            Type::Custom(name, arguments) => {
+                Type::Custom(name, arguments.iter().map(|argument| self.resolve_type(argument)).collect())
+            }
+            Type::Array(element, len) => Type::Array(Box::new(self.resolve_type(&element)), len),
+            Type::Slice(element) => Type::Slice(Box::new(self.resolve_type(&element))),
+            Type::Ref(pointee) => Type::Ref(Box::new(self.resolve_type(&pointee))),
+            Type::RefMut(pointee) => Type::RefMut(Box::new(self.resolve_type(&pointee))),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|param| self.resolve_type(param)).collect(),
+                Box::new(self.resolve_type(&ret)),
+            ),
+            ty => ty,
+        }
+    }
+
+    fn occurs(&self, var: usize, ty: &Type) -> bool {
+        match self.shallow_resolve(ty) {
+            Type::Var(other) => other == var,
+            Type::Custom(_, arguments) => arguments.iter().any(|argument| self.occurs(var, argument)),
+            Type::Array(element, _) | Type::Slice(element) | Type::Ref(element) | Type::RefMut(element) => {
+                self.occurs(var, &element)
+            }
+            Type::Function(params, ret) => {
+                params.iter().any(|param| self.occurs(var, param)) || self.occurs(var, &ret)
+            }
+            _ => false,
+        }
+    }
+
+    // Make `a` and `b` the same type by binding variables, remembering `span`
+    // as the reason for each binding
+    fn unify(&mut self, a: &Type, b: &Type, span: Span) -> bool {
+        let (a, b) = (self.shallow_resolve(a), self.shallow_resolve(b));
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => true,
+            (Type::Var(var), other) | (other, Type::Var(var)) => {
+                if self.occurs(*var, other) {
+                    return false;
+                }
+                // A literal's variable passes on what kind of number it is
+                let (integer, float) = (self.integer_vars.contains(var), self.float_vars.contains(var));
+                match other {
+                    Type::Var(other) if integer && self.float_vars.contains(other) => return false,
+                    Type::Var(other) if float && self.integer_vars.contains(other) => return false,
+                    Type::Var(other) if integer => {
+                        self.integer_vars.insert(*other);
+                    }
+                    Type::Var(other) if float => {
+                        self.float_vars.insert(*other);
+                    }
+                    other if (integer && !other.is_integer()) || (float && !other.is_float()) => return false,
+                    _ => {}
+                }
+                self.bindings[*var] = Some((other.clone(), span));
+                true
+            }
+
+            (Type::String, Type::String) |
+            (Type::Bool, Type::Bool) => true,
+            (a, b) if a.is_numeric() => a == b,
+
+            (Type::Custom(name1, args1), Type::Custom(name2, args2)) => {
+                name1 == name2 &&
+                args1.len() == args2.len() &&
+                args1.iter().zip(args2).all(|(a1, a2)| self.unify(a1, a2, span))
+            }
+
+            // Inside a generic function its type parameters are opaque
+            (Type::Param(name1), Type::Param(name2)) => name1 == name2,
+
+            (Type::Array(inner1, len1), Type::Array(inner2, len2)) => len1 == len2 && self.unify(inner1, inner2, span),
+
+            (Type::Slice(inner1), Type::Slice(inner2)) |
+            (Type::Ref(inner1), Type::Ref(inner2)) |
+            (Type::RefMut(inner1), Type::RefMut(inner2)) => self.unify(inner1, inner2, span),
+
+            (Type::Function(params1, ret1), Type::Function(params2, ret2)) => {
+                params1.len() == params2.len() &&
+                params1.iter().zip(params2).all(|(p1, p2)| self.unify(p1, p2, span)) &&
+                self.unify(ret1, ret2, span)
+            }
+
+            _ => false,
+        }
+    }
+
+    // Where the variable behind `ty` was last bound, following it to a concrete
+    // type. A literal's variable that nothing decided has its default type
+    // from wherever it was last unified.
+    fn origin(&self, ty: &Type) -> Option<Span> {
+        let mut ty = ty;
+        let mut origin = None;
+        while let Type::Var(var) = ty {
+            match &self.bindings[*var] {
+                Some((bound, span)) => {
+                    origin = Some(*span);
+                    ty = bound;
+                }
+                None if self.integer_vars.contains(var) || self.float_vars.contains(var) => break,
+                None => return None,
+            }
+        }
+        origin
+    }
+
+    // Unify `found`, the type of the code at `span`, with `expected`. When they
+    // conflict, the error built by `error` from the resolved types also points
+    // at wherever an inferred side got its type.
+    fn expect_type(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        span: Span,
+        error: impl FnOnce(&Type, &Type) -> Diagnostic,
+    ) -> bool {
+        if self.unify(expected, found, span) {
+            return true;
+        }
+
+        let resolved = [self.resolve_type(expected), self.resolve_type(found)];
+        let mut error = error(&resolved[0], &resolved[1]);
+        for (ty, resolved) in [expected, found].into_iter().zip(&resolved) {
+            if let Some(origin) = self.origin(ty) {
+                if origin != span && !error.labels.iter().any(|label| label.span == origin) {
+                    error = error.with_label(origin, format!("`{}` inferred here", resolved));
+                }
+            }
+        }
+        self.errors.push(error);
+        false
+    }
+
+    // Replace the type variables in the symbol table with what they were
+    // unified with. A variable or parameter whose type is still unknown needs
+    // an annotation, unless an earlier error is why it couldn't be inferred.
+    fn finish_inference(&mut self) {
+        let report = self.errors.is_empty();
+        if report {
+            let mut instantiations: Vec<_> = self.instantiations.iter().collect();
+            instantiations.sort_by_key(|(span, _)| span.start);
+            let mut errors = Vec::new();
+            for (span, arguments) in instantiations {
+                if let Some((name, _)) = arguments.iter().find(|(_, ty)| contains_var(&self.resolve_type(ty))) {
+                    errors.push(
+                        Diagnostic::error(format!("Type annotations needed: cannot infer type parameter `{}`", name), *span)
+                            .with_code("E0110")
+                            .with_primary_label(format!("cannot infer type for `{}`", name)),
+                    );
+                }
+            }
+            self.errors.extend(errors);
+        }
+
+        for id in 0..self.symbols.symbols.len() {
+            let ty = match &self.symbols.get(id).ty {
+                Some(ty) => self.resolve_type(ty),
+                None => continue,
+            };
+
+            let symbol = &mut self.symbols.symbols[id];
+            if symbol.kind == SymbolKind::Function {
+                // A return type that nothing fixed means the function returns nothing
+                symbol.ty = match ty {
+                    Type::Function(_, ret) if matches!(*ret, Type::Var(_)) => None,
+                    ty => Some(ty),
+                };
+                continue;
+            }
+
+            if report && contains_var(&ty) {
+                let label = match ty {
+                    Type::Var(_) => "cannot infer type".to_string(),
+                    _ => format!("cannot infer type: `{}`", ty),
+                };
+                self.errors.push(
+                    Diagnostic::error(format!("Type annotations needed for `{}`", symbol.name), symbol.definition)
+                        .with_code("E0110")
+                        .with_primary_label(label)
+                        .with_note(format!("consider giving `{}` an explicit type", symbol.name)),
+                );
+            }
+            symbol.ty = Some(ty);
+        }
+    }
+
+    fn check_control_flow(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Function { name, name_span, body, .. } => {
+                    self.check_function_flow(name, *name_span, body, statement.span);
+                    self.check_control_flow(body);
+                }
+                StatementKind::Block(body) | StatementKind::While { body, .. } => self.check_control_flow(body),
+                StatementKind::Impl(impl_decl) => self.check_control_flow(&impl_decl.methods),
+                StatementKind::If { then_branch, else_branch, .. } => {
+                    self.check_control_flow(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.check_control_flow(else_branch);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_function_flow(&mut self, name: &str, name_span: Span, body: &[Statement], span: Span) {
+        let graph = ControlFlowGraph::build(body);
+        let reachable = graph.reachable();
+        self.report_unreachable(&graph, &reachable, body);
+
+        for statement in graph.blocks.iter().flat_map(|block| &block.statements) {
+            let StatementKind::While { condition, body } = &statement.kind else {
+                continue;
+            };
+            // `while true` with a `break` is the usual way to write a loop that tests in the middle
+            if let Some(value) = cfg::constant_condition(condition).filter(|value| !value || !cfg::breaks_out(body)) {
+                let label = if value {
+                    "this loop only exits through `return`"
+                } else {
+                    "the loop body never runs"
+                };
+                self.warnings.push(
+                    Diagnostic::warning(format!("`while` condition is always {}", value), condition.span)
+                        .with_primary_label(label),
+                );
+            }
+        }
+
+        // Without a fixed return type the function returns nothing, and may
+        // leave however it likes
+        let return_type = match self.function_type_at(name, name_span) {
+            Some(function_type) => self.resolve_type(&function_type.return_type),
+            None => return,
+        };
+        if let Type::Var(_) = return_type {
+            return;
+        }
+
+        for statement in graph.blocks.iter().flat_map(|block| &block.statements) {
+            if let StatementKind::Return(None) = statement.kind {
+                self.errors.push(
+                    Diagnostic::error(
+                        format!("`return` without a value in `{}`, which returns {}", name, return_type),
+                        statement.span,
+                    )
+                    .with_code("E0111")
+                    .with_primary_label(format!("expected a value of type `{}`", return_type)),
+                );
+            }
+        }
+
+        if reachable[graph.end] {
+            // Point at the closing brace
+            let end = Span::new(span.file_id, span.end.saturating_sub(1), span.end);
+            self.errors.push(
+                Diagnostic::error(format!("`{}` can reach the end of its body without returning a value", name), end)
+                    .with_code("E0111")
+                    .with_primary_label("control reaches here on some path")
+                    .with_label(name_span, format!("`{}` returns `{}`", name, return_type)),
+            );
+        }
+    }
+
+    // Warn about the first statement of each statement list that control can
+    // never reach. Lists that are unreachable from their very first statement
+    // are covered by a warning about whatever encloses them.
+    fn report_unreachable(&mut self, graph: &ControlFlowGraph, reachable: &[bool], statements: &[Statement]) {
+        let mut previous: Option<&Statement> = None;
+        for statement in statements {
+            // Declarations don't run, so they are never unreachable
+            if let StatementKind::Function { .. }
+            | StatementKind::Struct { .. }
+            | StatementKind::Enum(_)
+            | StatementKind::Trait(_)
+            | StatementKind::Impl(_) = statement.kind
+            {
+                continue;
+            }
+
+            if graph.block_of(statement).is_some_and(|block| !reachable[block]) {
+                if let Some(previous) = previous {
+                    self.warnings.push(
+                        Diagnostic::warning("unreachable statement", statement.span)
+                            .with_primary_label("unreachable statement")
+                            .with_label(previous.span, "any code following this is unreachable"),
+                    );
+                }
+                return;
+            }
+
+            match &statement.kind {
+                StatementKind::Block(body) | StatementKind::While { body, .. } => {
+                    self.report_unreachable(graph, reachable, body);
+                }
+                StatementKind::If { then_branch, else_branch, .. } => {
+                    self.report_unreachable(graph, reachable, then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.report_unreachable(graph, reachable, else_branch);
+                    }
+                }
+                _ => {}
+            }
+            previous = Some(statement);
+        }
+    }
+
+    // `fn name(a: i32, b: f64) -> i32`, for notes that point at a declaration
+    fn signature(&self, name: &str, function: &FunctionType) -> String {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|(param_name, param_type)| format!("{}: {}", param_name, self.resolve_type(param_type)))
+            .collect();
+        match self.resolve_type(&function.return_type) {
+            Type::Var(_) => format!("`fn {}({})`", name, params.join(", ")),
+            return_type => format!("`fn {}({}) -> {}`", name, params.join(", "), return_type),
+        }
+    }
+}
+
+// A variable or a field of one, which an assignment can write to
+fn is_place(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Identifier(_) => true,
+        ExpressionKind::FieldAccess { object, .. } => is_place(object),
+        ExpressionKind::Index { array, .. } => is_place(array),
+        ExpressionKind::Deref { .. } => true,
+        _ => false,
+    }
+}
+
+// The smallest and largest values of an integer type
+fn integer_range(ty: &Type) -> (i128, i128) {
+    match ty.integer_kind() {
+        Some((true, 128)) => (i128::MIN, i128::MAX),
+        Some((true, bits)) => (-(1 << (bits - 1)), (1 << (bits - 1)) - 1),
+        Some((false, bits)) => (0, (1 << bits) - 1),
+        None => (0, 0),
+    }
+}
+
+fn contains_var(ty: &Type) -> bool {
+    match ty {
+        Type::Var(_) => true,
+        Type::Custom(_, arguments) => arguments.iter().any(contains_var),
+        Type::Array(element, _) | Type::Slice(element) | Type::Ref(element) | Type::RefMut(element) => {
+            contains_var(element)
+        }
+        Type::Function(params, ret) => params.iter().any(contains_var) || contains_var(ret),
+        _ => false,
+    }
+}
+
+fn contains_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Ref(_) | Type::RefMut(_) | Type::Slice(_) => true,
+        Type::Custom(_, arguments) => arguments.iter().any(contains_ref),
+        Type::Array(element, _) => contains_ref(element),
+        _ => false,
+    }
+}
+
+// The type of a binding or parameter declared `own T`, which is just `T`
+fn strip_own(ty: &Type) -> &Type {
+    match ty {
+        Type::Own(ty) => ty,
+        ty => ty,
+    }
+}
+
+// The candidate closest to `name`, if it is close enough to be a likely typo
+fn similar_name<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<&'a String> {
+    let threshold = name.chars().count().div_ceil(3);
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        // Ties go to the alphabetically first name so the result doesn't depend on hash order
+        .min_by_key(|(distance, candidate)| (*distance, *candidate))
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
         }
     }
+    row[b.len()]
 }
@@ -1,54 +1,399 @@
+use std::collections::HashMap;
 use std::fmt;
 
-#[derive(Debug, Clone)]
+pub use super::span::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
+    // `i32` and `f64`, the types of unsuffixed literals nothing else decides
     Int,
     Float,
+    I8,
+    I16,
+    I64,
+    I128,
+    U8,
+    U16,
+    U32,
+    U64,
+    Usize,
+    F32,
     String,
     Bool,
-    Custom(String),
-    Array(Box<Type>),
+    // A struct or enum, with the type arguments of a generic struct
+    Custom(String, Vec<Type>),
+    // `[T; N]`, N elements stored inline
+    Array(Box<Type>, usize),
+    // `&[T]`, a shared view of an array's elements: a pointer to the first
+    // and the length
+    Slice(Box<Type>),
     Function(Vec<Type>, Box<Type>),
+    // `ref T` and `ref mut T`, borrowed from a place that outlives them
+    Ref(Box<Type>),
+    RefMut(Box<Type>),
+    // `own T` on a binding or parameter that owns its value, which moves
+    // instead of being copied and is dropped at the end of its scope
+    Own(Box<Type>),
+    // A type parameter in scope, or `Self` inside a trait or impl
+    Param(String),
+    // A type the analyzer hasn't inferred yet; never written in source
+    Var(usize),
+}
+
+impl Type {
+    // The type a primitive type name stands for
+    pub fn primitive(name: &str) -> Option<Type> {
+        Some(match name {
+            "i8" => Type::I8,
+            "i16" => Type::I16,
+            "i32" => Type::Int,
+            "i64" => Type::I64,
+            "i128" => Type::I128,
+            "u8" => Type::U8,
+            "u16" => Type::U16,
+            "u32" => Type::U32,
+            "u64" => Type::U64,
+            "usize" => Type::Usize,
+            "f32" => Type::F32,
+            "f64" => Type::Float,
+            "string" => Type::String,
+            "bool" => Type::Bool,
+            _ => return None,
+        })
+    }
+
+    // Whether an integer type is signed, and its width in bits
+    pub fn integer_kind(&self) -> Option<(bool, u32)> {
+        Some(match self {
+            Type::I8 => (true, 8),
+            Type::I16 => (true, 16),
+            Type::Int => (true, 32),
+            Type::I64 => (true, 64),
+            Type::I128 => (true, 128),
+            Type::U8 => (false, 8),
+            Type::U16 => (false, 16),
+            Type::U32 => (false, 32),
+            Type::U64 | Type::Usize => (false, 64),
+            _ => return None,
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.integer_kind().is_some()
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::F32)
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    // Replace the type parameters named in `arguments` with their types
+    pub fn substitute(&self, arguments: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Param(name) => arguments.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::Custom(name, args) => {
+                Type::Custom(name.clone(), args.iter().map(|arg| arg.substitute(arguments)).collect())
+            }
+            Type::Array(element, len) => Type::Array(Box::new(element.substitute(arguments)), *len),
+            Type::Slice(element) => Type::Slice(Box::new(element.substitute(arguments))),
+            Type::Ref(inner) => Type::Ref(Box::new(inner.substitute(arguments))),
+            Type::RefMut(inner) => Type::RefMut(Box::new(inner.substitute(arguments))),
+            Type::Own(inner) => Type::Own(Box::new(inner.substitute(arguments))),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|param| param.substitute(arguments)).collect(),
+                Box::new(ret.substitute(arguments)),
+            ),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "i32"),
+            Type::Float => write!(f, "f64"),
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I64 => write!(f, "i64"),
+            Type::I128 => write!(f, "i128"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::Usize => write!(f, "usize"),
+            Type::F32 => write!(f, "f32"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Custom(name, args) => {
+                write!(f, "{}", name)?;
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", arg)?;
+                    }
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Type::Array(element, len) => write!(f, "[{}; {}]", element, len),
+            Type::Slice(element) => write!(f, "&[{}]", element),
+            Type::Function(params, ret) => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ") -> {}", ret)
+            }
+            Type::Ref(inner) => write!(f, "ref {}", inner),
+            Type::RefMut(inner) => write!(f, "ref mut {}", inner),
+            Type::Own(inner) => write!(f, "own {}", inner),
+            Type::Param(name) => write!(f, "{}", name),
+            Type::Var(_) => write!(f, "_"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Expression {
-    Integer(i64),
-    Float(f64),
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExpressionKind {
+    // A number's type is its suffix, like `255u8`, if it has one; the
+    // analyzer fills in the type it inferred for the rest
+    Integer(u128, Option<Type>),
+    Float(f64, Option<Type>),
     String(String),
+    // `"{name} is {age}"`, which the parser desugars into `to_string` calls
+    // joined with `+`. The wrapper lets the formatter print it back.
+    Interpolation(Box<Expression>),
     Boolean(bool),
     Identifier(String),
     Binary {
         left: Box<Expression>,
         operator: BinaryOp,
         right: Box<Expression>,
+        // Filled in by the analyzer; the type of both operands, which picks
+        // integer or floating-point instructions
+        operand_type: Option<Type>,
+    },
+    Unary {
+        operator: UnaryOp,
+        operand: Box<Expression>,
+        // Filled in by the analyzer
+        operand_type: Option<Type>,
     },
     Call {
         function: Box<Expression>,
         arguments: Vec<Expression>,
+        // What the analyzer inferred for a generic function's type parameters
+        type_arguments: Vec<Type>,
+    },
+    MethodCall {
+        receiver: Box<Expression>,
+        method: String,
+        arguments: Vec<Expression>,
+        // Filled in by the analyzer; picks the impl the method comes from
+        receiver_type: Option<Type>,
+    },
+    FieldAccess {
+        object: Box<Expression>,
+        field: String,
     },
+    Index {
+        array: Box<Expression>,
+        index: Box<Expression>,
+        // Filled in by the analyzer; the array or slice being indexed
+        array_type: Option<Type>,
+    },
+    // `a..b`, which only slices a string: `s[a..b]`
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+    },
+    // `Point::new`, `Color::Red`
+    Path(Vec<String>),
     ArrayLiteral(Vec<Expression>),
     StructLiteral {
         name: String,
         fields: Vec<(String, Expression)>,
+        // What the analyzer inferred for a generic struct's type parameters
+        type_arguments: Vec<Type>,
+    },
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<MatchArm>,
+        // Filled in by the analyzer; integer ranges compare signed or unsigned
+        scrutinee_type: Option<Type>,
+    },
+    // `if c { a } else { b }` used for its value. Like a match arm, each
+    // branch is a single expression; `else if` nests another `If`.
+    If {
+        condition: Box<Expression>,
+        then_branch: Box<Expression>,
+        else_branch: Box<Expression>,
+    },
+    // `ref place` or `ref mut place`
+    Borrow {
+        operand: Box<Expression>,
+        mutable: bool,
+    },
+    // `x as u8`
+    Cast {
+        operand: Box<Expression>,
+        target: Type,
+        // Filled in by the analyzer
+        operand_type: Option<Type>,
+    },
+    // `&a`, a slice of every element of the array or slice `a`
+    Slice {
+        operand: Box<Expression>,
+        // Filled in by the analyzer
+        array_type: Option<Type>,
+    },
+    // `move x`. The analyzer also wraps the implicit moves of owned bindings
+    // in one, so codegen knows which bindings are left to drop.
+    Move(Box<Expression>),
+    // `*r`, or a reference the analyzer dereferences implicitly to reach a
+    // field or method
+    Deref {
+        operand: Box<Expression>,
+        // Filled in by the analyzer
+        pointee: Option<Type>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expression,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+impl Pattern {
+    pub fn new(kind: PatternKind, span: Span) -> Self {
+        Pattern { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    // `_`
+    Wildcard,
+    // A bare name matches anything and binds it
+    Binding(String),
+    Integer(i64),
+    Boolean(bool),
+    // `1..5` or `1..=5`
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+    // `Shape::Circle(r)`, or `Color::Red` without a payload
+    Variant {
+        path: Vec<String>,
+        fields: Vec<Pattern>,
     },
 }
 
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            PatternKind::Wildcard => write!(f, "_"),
+            PatternKind::Binding(name) => write!(f, "{}", name),
+            PatternKind::Integer(value) => write!(f, "{}", value),
+            PatternKind::Boolean(value) => write!(f, "{}", value),
+            PatternKind::Range { start, end, inclusive } => {
+                write!(f, "{}{}{}", start, if *inclusive { "..=" } else { ".." }, end)
+            }
+            PatternKind::Variant { path, fields } => {
+                write!(f, "{}", path.join("::"))?;
+                if !fields.is_empty() {
+                    write!(f, "(")?;
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", field)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
     Let {
         name: String,
+        name_span: Span,
+        // `let mut`
+        mutable: bool,
         type_annotation: Option<Type>,
         initializer: Expression,
     },
     Function {
         name: String,
-        params: Vec<(String, Type)>,
+        name_span: Span,
+        // `<T: Display>`, with the bounds of any `where` clause merged in
+        generics: Vec<GenericParam>,
+        params: Vec<Param>,
         return_type: Option<Type>,
         body: Vec<Statement>,
+        // `fn f(a, b) => a * b;`, whose body is the single `return`
+        expression_body: bool,
     },
     Return(Option<Expression>),
     Expression(Expression),
+    // `x = e`, `p.x = e` or `a[i] = e`; `x += e` and the other compound
+    // assignments carry their operator
+    Assign {
+        target: Expression,
+        operator: Option<BinaryOp>,
+        value: Expression,
+        // Filled in by the analyzer for a compound assignment
+        operand_type: Option<Type>,
+    },
+    Block(Vec<Statement>),
     If {
         condition: Expression,
         then_branch: Vec<Statement>,
@@ -58,10 +403,80 @@ pub enum Statement {
         condition: Expression,
         body: Vec<Statement>,
     },
+    // Leave the innermost loop, or go on to its next iteration
+    Break,
+    Continue,
     Struct {
         name: String,
+        name_span: Span,
+        generics: Vec<GenericParam>,
         fields: Vec<(String, Type)>,
     },
+    Enum(Enum),
+    Trait(Trait),
+    Impl(Impl),
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericParam {
+    pub name: String,
+    pub span: Span,
+    // The traits the type must implement, with where each is named
+    pub bounds: Vec<(String, Span)>,
+}
+
+// `trait Display { fn show(self) -> string; }`
+#[derive(Debug, Clone)]
+pub struct Trait {
+    pub name: String,
+    pub name_span: Span,
+    pub methods: Vec<TraitMethod>,
+}
+
+// A method signature; the first parameter is `self`, of type `Self`
+#[derive(Debug, Clone)]
+pub struct TraitMethod {
+    pub name: String,
+    pub name_span: Span,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+}
+
+// `impl Display for Point { ... }`
+#[derive(Debug, Clone)]
+pub struct Impl {
+    pub trait_name: String,
+    pub trait_span: Span,
+    pub self_type: Type,
+    pub type_span: Span,
+    // `Function` statements, which may refer to the implementing type as `Self`
+    pub methods: Vec<Statement>,
+}
+
+// `enum Shape { Circle(radius: f64), Rectangle(f64, f64), Empty }`
+#[derive(Debug, Clone)]
+pub struct Enum {
+    pub name: String,
+    pub name_span: Span,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    // The variant's name
+    pub span: Span,
+    // The payload, in order; fields may be named for documentation
+    pub fields: Vec<(Option<String>, Type)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    // `None` until the analyzer infers it
+    pub ty: Option<Type>,
+    // The parameter's name
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -70,12 +485,32 @@ pub enum BinaryOp {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    ShiftLeft,
+    ShiftRight,
     Equal,
     NotEqual,
     Greater,
     Less,
     GreaterEqual,
     LessEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    // Binding strength; higher binds tighter. All binary operators are left-associative.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater | BinaryOp::Less |
+            BinaryOp::GreaterEqual | BinaryOp::LessEqual => 3,
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => 4,
+            BinaryOp::Add | BinaryOp::Subtract => 5,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 6,
+        }
+    }
 }
 
 impl fmt::Display for BinaryOp {
@@ -85,12 +520,32 @@ impl fmt::Display for BinaryOp {
             BinaryOp::Subtract => write!(f, "-"),
             BinaryOp::Multiply => write!(f, "*"),
             BinaryOp::Divide => write!(f, "/"),
+            BinaryOp::Modulo => write!(f, "%"),
+            BinaryOp::ShiftLeft => write!(f, "<<"),
+            BinaryOp::ShiftRight => write!(f, ">>"),
             BinaryOp::Equal => write!(f, "=="),
             BinaryOp::NotEqual => write!(f, "!="),
             BinaryOp::Greater => write!(f, ">"),
             BinaryOp::Less => write!(f, "<"),
             BinaryOp::GreaterEqual => write!(f, ">="),
             BinaryOp::LessEqual => write!(f, "<="),
+            BinaryOp::And => write!(f, "&&"),
+            BinaryOp::Or => write!(f, "||"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
         }
     }
 }
@@ -0,0 +1,875 @@
+// Ownership and borrow checking, over an AST that has passed the analyzer.
+//
+// A binding owns its value when it's declared `own T`, or when it's
+// initialized by moving an owned value. Using an owned binding by value moves
+// it: as a `let` initializer, as the argument for an `own` parameter, as the
+// value of a struct literal's field, as the value being returned or assigned
+// to another owned binding, or explicitly with `move`. Any other use only
+// reads it. A binding can't be used once it's moved, and it must be moved on
+// every path or on none, so codegen knows whether to drop it at the end of
+// its scope.
+//
+// `ref place` and `ref mut place` borrow a variable or one of its fields,
+// and so does slicing a string held by an owned binding, `s[a..b]`. A borrow
+// in a `let` initializer lasts until the end of that binding's scope; any
+// other lasts until the end of its statement. A borrow stored in a binding,
+// by `let` or by assigning it, is held by that binding, and so is one copied
+// from another binding that holds it. The place has to outlive every binding
+// holding a borrow of it, and a value returned from a function can't borrow
+// from its owned bindings. While a place is mutably borrowed it can't be used
+// or borrowed again, and while it's borrowed at all it can't be moved or
+// assigned to.
+
+use std::collections::{HashMap, HashSet};
+
+use super::analyzer::{SymbolId, SymbolTable};
+use super::ast::*;
+use super::cfg;
+use super::diagnostic::Diagnostic;
+
+// A variable or a path of fields into one, like `line.start.x`. Any element
+// of an array is `[]`, since which one isn't known until run time.
+#[derive(Debug, Clone, PartialEq)]
+struct Place {
+    root: SymbolId,
+    fields: Vec<String>,
+}
+
+impl Place {
+    // Whether one place contains the other
+    fn overlaps(&self, other: &Place) -> bool {
+        self.root == other.root && self.fields.iter().zip(&other.fields).all(|(a, b)| a == b)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Loan {
+    place: Place,
+    // How the place is written in source, for messages
+    name: String,
+    mutable: bool,
+    span: Span,
+    // The depth of the scope whose end releases the loan; `None` for a
+    // borrow that only lasts until the end of its statement
+    scope: Option<usize>,
+    // The binding the borrow is stored in
+    holder: Option<SymbolId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MoveSite {
+    span: Span,
+    // Moved by an earlier iteration of the loop being checked again
+    previous_iteration: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct State {
+    moved: HashMap<SymbolId, MoveSite>,
+    loans: Vec<Loan>,
+}
+
+// The states a loop is left or restarted in by `break` and `continue`
+struct LoopExits {
+    // How many scopes enclose the loop; the bindings of deeper ones are gone
+    // once control leaves the body
+    depth: usize,
+    breaks: Vec<State>,
+    continues: Vec<State>,
+}
+
+pub struct BorrowChecker<'a> {
+    symbols: &'a SymbolTable,
+    // The declaration each name refers to, by the span of the reference
+    resolved: HashMap<Span, SymbolId>,
+    definitions: HashMap<Span, SymbolId>,
+    // For each function, which of its parameters take ownership
+    own_params: HashMap<String, Vec<bool>>,
+    owned: HashSet<SymbolId>,
+    state: State,
+    // The bindings declared in each enclosing block of the current function
+    scopes: Vec<Vec<SymbolId>>,
+    // The loops enclosing the statement being checked, innermost last
+    loops: Vec<LoopExits>,
+    // The return type of the function being checked, if it's known
+    return_type: Option<Type>,
+    // Checking a loop body a second time, for moves made by the first pass;
+    // only errors about those are new
+    recheck: bool,
+    moves: HashSet<Span>,
+    owned_bindings: HashSet<Span>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> BorrowChecker<'a> {
+    pub fn new(symbols: &'a SymbolTable) -> Self {
+        let mut resolved = HashMap::new();
+        let mut definitions = HashMap::new();
+        for (id, symbol) in symbols.iter().enumerate() {
+            definitions.insert(symbol.definition, id);
+            for span in &symbol.uses {
+                resolved.insert(*span, id);
+            }
+        }
+
+        BorrowChecker {
+            symbols,
+            resolved,
+            definitions,
+            own_params: HashMap::new(),
+            owned: HashSet::new(),
+            state: State::default(),
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            return_type: None,
+            recheck: false,
+            moves: HashSet::new(),
+            owned_bindings: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self, program: &Program) {
+        self.collect_signatures(&program.statements);
+        self.check_items(&program.statements);
+    }
+
+    // The spans of the identifiers whose use moves an owned binding
+    pub fn moves(&self) -> &HashSet<Span> {
+        &self.moves
+    }
+
+    // The `let` bindings that own their value, by the span of their names
+    pub fn owned_bindings(&self) -> &HashSet<Span> {
+        &self.owned_bindings
+    }
+
+    pub fn errors(&self) -> &[Diagnostic] {
+        &self.errors
+    }
+
+    fn collect_signatures(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Function { name, params, body, .. } => {
+                    let owns = params.iter().map(|param| matches!(param.ty, Some(Type::Own(_)))).collect();
+                    self.own_params.insert(name.clone(), owns);
+                    self.collect_signatures(body);
+                }
+                StatementKind::Block(body) | StatementKind::While { body, .. } => self.collect_signatures(body),
+                StatementKind::If { then_branch, else_branch, .. } => {
+                    self.collect_signatures(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.collect_signatures(else_branch);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Functions are checked on their own, wherever they're declared
+    fn check_items(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Function { name_span, params, body, .. } => self.check_function(*name_span, params, body),
+                StatementKind::Impl(impl_decl) => self.check_items(&impl_decl.methods),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_function(&mut self, name_span: Span, params: &[Param], body: &[Statement]) {
+        let state = std::mem::take(&mut self.state);
+        let scopes = std::mem::replace(&mut self.scopes, vec![Vec::new()]);
+        let loops = std::mem::take(&mut self.loops);
+        let return_type = match self.definitions.get(&name_span).and_then(|&id| self.symbols.get(id).ty.as_ref()) {
+            Some(Type::Function(_, return_type)) => Some((**return_type).clone()),
+            _ => None,
+        };
+        let return_type = std::mem::replace(&mut self.return_type, return_type);
+
+        for param in params {
+            if let Some(&id) = self.definitions.get(&param.span) {
+                if let Some(Type::Own(_)) = param.ty {
+                    self.owned.insert(id);
+                }
+                self.scopes[0].push(id);
+            }
+        }
+        self.check_statements(body);
+
+        self.state = state;
+        self.scopes = scopes;
+        self.loops = loops;
+        self.return_type = return_type;
+    }
+
+    fn check_block(&mut self, statements: &[Statement]) {
+        self.scopes.push(Vec::new());
+        self.check_statements(statements);
+        self.end_scope();
+    }
+
+    fn check_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.check_statement(statement);
+            // Borrows that weren't bound to anything end with their statement
+            self.state.loans.retain(|loan| loan.scope.is_some());
+        }
+    }
+
+    // Leaving a block ends the borrows held by its bindings, and its moved
+    // bindings are gone. A borrow of one of them still held by a binding of
+    // an enclosing block would dangle.
+    fn end_scope(&mut self) {
+        let depth = self.scopes.len() - 1;
+        let ending = self.scopes.pop().unwrap_or_default();
+        self.state.loans.retain(|loan| loan.scope.is_some_and(|scope| scope < depth));
+
+        let mut errors: Vec<Diagnostic> = Vec::new();
+        for loan in &self.state.loans {
+            let Some(holder) = loan.holder else {
+                continue;
+            };
+            if !ending.contains(&loan.place.root) || errors.iter().any(|error| error.primary_span == Some(loan.span)) {
+                continue;
+            }
+            let root = self.symbols.get(loan.place.root);
+            errors.push(
+                Diagnostic::error(format!("`{}` does not live long enough", loan.name), loan.span)
+                    .with_code("E0206")
+                    .with_primary_label(format!("`{}` is borrowed here", loan.name))
+                    .with_label(root.definition, format!("`{}` is dropped at the end of its block", root.name))
+                    .with_note(format!("the borrow is still held by `{}`, which lives longer", self.symbols.get(holder).name)),
+            );
+        }
+        for error in errors {
+            self.report(error);
+        }
+        self.state.loans.retain(|loan| !ending.contains(&loan.place.root));
+
+        for id in ending {
+            self.state.moved.remove(&id);
+        }
+    }
+
+    fn check_statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Let { name_span, type_annotation, initializer, .. } => {
+                let loans = self.state.loans.len();
+                let moved = self.check_expression(initializer, true);
+                let held = self.held_loans(initializer);
+                let depth = self.scopes.len() - 1;
+                for loan in &mut self.state.loans[loans..] {
+                    loan.scope = Some(depth);
+                }
+
+                if let Some(&id) = self.definitions.get(name_span) {
+                    if moved || matches!(type_annotation, Some(Type::Own(_))) {
+                        self.owned.insert(id);
+                        self.owned_bindings.insert(*name_span);
+                    }
+                    self.scopes.last_mut().unwrap().push(id);
+                    self.hold(id, held);
+                }
+            }
+            StatementKind::Assign { target, operator, value, .. } => {
+                let owned_target = self.binding(target).is_some_and(|id| self.owned.contains(&id));
+                self.check_expression(value, owned_target && operator.is_none());
+                // `x += e` reads `x` first
+                if operator.is_some() {
+                    self.check_expression(target, false);
+                }
+                self.check_assignment(target);
+
+                // Assigning a whole binding replaces the borrows it held
+                if let Some(place) = place_of(target, &self.resolved).filter(|_| operator.is_none()) {
+                    let held = self.held_loans(value);
+                    if place.fields.is_empty() {
+                        self.state.loans.retain(|loan| loan.holder != Some(place.root));
+                    }
+                    self.hold(place.root, held);
+                }
+            }
+            StatementKind::Return(Some(expr)) => {
+                self.check_expression(expr, true);
+                // Owned bindings are dropped as the function returns, so
+                // a string or struct returned can't point into one
+                if matches!(&self.return_type, Some(ty) if ty.is_numeric() || *ty == Type::Bool) {
+                    return;
+                }
+                let borrowed = self.held_loans(expr).into_iter().find(|loan| self.owned.contains(&loan.place.root));
+                if let Some(loan) = borrowed {
+                    let mut error = Diagnostic::error(format!("Cannot return a value borrowing from `{}`", loan.name), expr.span)
+                        .with_code("E0206")
+                        .with_primary_label("returned here");
+                    if loan.span != expr.span {
+                        error = error.with_label(loan.span, format!("`{}` is borrowed here", loan.name));
+                    }
+                    self.report(error.with_note(format!("`{}` is dropped when the function returns", loan.name)));
+                }
+            }
+            StatementKind::Expression(expr) => {
+                self.check_expression(expr, false);
+            }
+            StatementKind::Block(body) => self.check_block(body),
+            StatementKind::If { condition, then_branch, else_branch } => {
+                self.check_expression(condition, false);
+                let before = self.state.clone();
+                self.check_block(then_branch);
+                let mut branches = vec![(std::mem::replace(&mut self.state, before.clone()), diverges(then_branch))];
+                match else_branch {
+                    Some(else_branch) => {
+                        self.check_block(else_branch);
+                        branches.push((std::mem::replace(&mut self.state, before.clone()), diverges(else_branch)));
+                    }
+                    None => branches.push((before.clone(), false)),
+                }
+                self.merge(before, branches);
+            }
+            StatementKind::While { condition, body } => self.check_loop(condition, body),
+            StatementKind::Break | StatementKind::Continue => {
+                let Some(exits) = self.loops.last() else {
+                    return;
+                };
+                // Only the bindings that outlive the loop body matter where control goes
+                let mut state = self.state.clone();
+                let outer: HashSet<SymbolId> = self.scopes[..exits.depth].iter().flatten().copied().collect();
+                state.moved.retain(|id, _| outer.contains(id));
+                let exits = self.loops.last_mut().unwrap();
+                match statement.kind {
+                    StatementKind::Break => exits.breaks.push(state),
+                    _ => exits.continues.push(state),
+                }
+            }
+            StatementKind::Function { name_span, params, body, .. } => self.check_function(*name_span, params, body),
+            _ => {}
+        }
+    }
+
+    fn check_loop(&mut self, condition: &Expression, body: &[Statement]) {
+        let before = self.state.clone();
+        self.loops.push(LoopExits { depth: self.scopes.len(), breaks: Vec::new(), continues: Vec::new() });
+        self.check_expression(condition, false);
+        self.check_block(body);
+        let exits = self.loops.pop().unwrap();
+
+        // An iteration ends at the end of the body or at a `continue`, and
+        // what it moved is gone by the time the next one runs
+        let mut ends = exits.continues;
+        let after = std::mem::replace(&mut self.state, before.clone());
+        if !diverges(body) {
+            ends.push(after);
+        }
+        let moved: Vec<_> = ends
+            .iter()
+            .flat_map(|end| &end.moved)
+            .filter(|(id, _)| !before.moved.contains_key(id))
+            .map(|(id, site)| (*id, site.span))
+            .collect();
+        if !moved.is_empty() {
+            for (id, span) in moved {
+                self.state.moved.insert(id, MoveSite { span, previous_iteration: true });
+            }
+            let recheck = std::mem::replace(&mut self.recheck, true);
+            self.loops.push(LoopExits { depth: self.scopes.len(), breaks: Vec::new(), continues: Vec::new() });
+            self.check_expression(condition, false);
+            self.check_block(body);
+            self.loops.pop();
+            self.recheck = recheck;
+        }
+
+        // The loop may run any number of times before its condition fails,
+        // or be left by a `break`
+        let mut finished = before.clone();
+        for end in ends {
+            finished.moved.extend(end.moved);
+            keep_held_loans(&mut finished.loans, &end.loans);
+        }
+        let mut branches: Vec<_> = exits.breaks.into_iter().map(|state| (state, false)).collect();
+        if cfg::constant_condition(condition) != Some(true) || branches.is_empty() {
+            branches.push((finished, false));
+        }
+        self.merge(before, branches);
+    }
+
+    // Continue after a branch with the moves of the branches that fall
+    // through, which have to agree on what they moved, and the borrows any
+    // of them stored
+    fn merge(&mut self, before: State, branches: Vec<(State, bool)>) {
+        let live: Vec<State> = branches
+            .into_iter()
+            .filter(|(_, diverges)| !diverges)
+            .map(|(state, _)| state)
+            .collect();
+
+        self.state = before;
+        let mut conditional: Vec<(SymbolId, MoveSite)> = Vec::new();
+        for state in &live {
+            keep_held_loans(&mut self.state.loans, &state.loans);
+            for (id, site) in &state.moved {
+                if self.state.moved.contains_key(id) {
+                    continue;
+                }
+                if !live.iter().all(|other| other.moved.contains_key(id)) && self.owned.contains(id) {
+                    conditional.push((*id, *site));
+                }
+                self.state.moved.insert(*id, *site);
+            }
+        }
+
+        conditional.sort_by_key(|(_, site)| site.span.start);
+        for (id, site) in conditional {
+            let name = self.symbols.get(id).name.clone();
+            self.report(
+                Diagnostic::error(format!("`{}` is moved on some paths but not others", name), site.span)
+                    .with_code("E0202")
+                    .with_primary_label(format!("`{}` moved here", name))
+                    .with_note("an owned value must be moved on every path or on none, so it's known whether to drop it"),
+            );
+        }
+    }
+
+    // Returns whether the expression moved an owned binding. `owning` is
+    // whether its value is taken over by whatever uses it.
+    fn check_expression(&mut self, expr: &Expression, owning: bool) -> bool {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => {
+                let Some(id) = self.resolved.get(&expr.span).copied() else {
+                    return false;
+                };
+                let place = Place { root: id, fields: Vec::new() };
+                if !self.check_use(&place, name, expr.span) {
+                    return false;
+                }
+                if owning && self.owned.contains(&id) {
+                    if let Some(loan) = self.conflicting_loan(&place, false) {
+                        let (loan_name, loan_span) = (loan.name.clone(), loan.span);
+                        self.report(
+                            Diagnostic::error(format!("Cannot move out of `{}` because it is borrowed", name), expr.span)
+                                .with_code("E0204")
+                                .with_primary_label(format!("move out of `{}` occurs here", name))
+                                .with_label(loan_span, format!("`{}` is borrowed here", loan_name)),
+                        );
+                    }
+                    self.state.moved.insert(id, MoveSite { span: expr.span, previous_iteration: false });
+                    self.moves.insert(expr.span);
+                    return true;
+                }
+                false
+            }
+            ExpressionKind::Move(operand) => match self.binding(operand) {
+                Some(id) if self.owned.contains(&id) => self.check_expression(operand, true),
+                Some(id) => {
+                    let name = self.symbols.get(id).name.clone();
+                    self.report(
+                        Diagnostic::error(format!("Cannot move `{}`, which doesn't own its value", name), operand.span)
+                            .with_code("E0205")
+                            .with_primary_label("not an owned binding")
+                            .with_label(self.symbols.get(id).definition, format!("consider declaring `{}` with `own`", name)),
+                    );
+                    false
+                }
+                None => {
+                    self.report(
+                        Diagnostic::error("Only a variable can be moved", operand.span)
+                            .with_code("E0205")
+                            .with_primary_label("expected the name of an owned binding"),
+                    );
+                    false
+                }
+            },
+            ExpressionKind::Borrow { operand, mutable } => {
+                match place_of(operand, &self.resolved) {
+                    Some(place) => {
+                        self.check_indices(operand);
+                        self.check_borrow(place, operand, *mutable, expr.span);
+                    }
+                    // Borrowing through a reference; the reference was
+                    // checked where it was made
+                    None if matches!(operand.kind, ExpressionKind::Deref { .. }) => {
+                        self.check_expression(operand, false);
+                    }
+                    None => {
+                        self.check_expression(operand, false);
+                        self.report(
+                            Diagnostic::error("Cannot borrow a temporary value", operand.span)
+                                .with_code("E0205")
+                                .with_primary_label("not a variable or a field of one")
+                                .with_note("bind the value with `let` first, then borrow the binding"),
+                        );
+                    }
+                }
+                false
+            }
+            // A slice borrows the array it views; a temporary array lives
+            // for the rest of the function
+            ExpressionKind::Slice { operand, .. } => {
+                match place_of(operand, &self.resolved) {
+                    Some(place) => {
+                        self.check_indices(operand);
+                        self.check_borrow(place, operand, false, expr.span);
+                    }
+                    None => {
+                        self.check_expression(operand, false);
+                    }
+                }
+                false
+            }
+            ExpressionKind::FieldAccess { object, .. } => {
+                match place_of(expr, &self.resolved) {
+                    Some(place) => {
+                        let name = place_name(expr);
+                        self.check_use(&place, &name, expr.span);
+                    }
+                    None => {
+                        self.check_expression(object, false);
+                    }
+                }
+                false
+            }
+            ExpressionKind::Call { function, arguments, .. } => {
+                let owns = match &function.kind {
+                    ExpressionKind::Identifier(name) => self.own_params.get(name).cloned().unwrap_or_default(),
+                    _ => Vec::new(),
+                };
+                for (i, argument) in arguments.iter().enumerate() {
+                    self.check_expression(argument, owns.get(i).copied().unwrap_or(false));
+                }
+                false
+            }
+            ExpressionKind::MethodCall { receiver, arguments, .. } => {
+                self.check_expression(receiver, false);
+                for argument in arguments {
+                    self.check_expression(argument, false);
+                }
+                false
+            }
+            ExpressionKind::Binary { left, right, .. } => {
+                self.check_expression(left, false);
+                self.check_expression(right, false);
+                false
+            }
+            ExpressionKind::Unary { operand, .. } | ExpressionKind::Deref { operand, .. } | ExpressionKind::Cast { operand, .. } => {
+                self.check_expression(operand, false);
+                false
+            }
+            // A string slice points into the string's bytes, so slicing one
+            // held by an owned binding borrows it
+            ExpressionKind::Index { array, index, .. } => {
+                let owned_place = place_of(array, &self.resolved).filter(|place| self.owned.contains(&place.root));
+                match owned_place {
+                    Some(place) if matches!(index.kind, ExpressionKind::Range { .. }) => {
+                        self.check_indices(array);
+                        self.check_expression(index, false);
+                        self.check_borrow(place, array, false, expr.span);
+                    }
+                    _ => {
+                        self.check_expression(array, false);
+                        self.check_expression(index, false);
+                    }
+                }
+                false
+            }
+            ExpressionKind::Range { start, end } => {
+                self.check_expression(start, false);
+                self.check_expression(end, false);
+                false
+            }
+            ExpressionKind::Interpolation(desugared) => self.check_expression(desugared, false),
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements {
+                    self.check_expression(element, false);
+                }
+                false
+            }
+            // The struct takes over what its fields are initialized with
+            ExpressionKind::StructLiteral { fields, .. } => {
+                let mut moved = false;
+                for (_, value) in fields {
+                    moved |= self.check_expression(value, true);
+                }
+                moved
+            }
+            ExpressionKind::If { condition, then_branch, else_branch } => {
+                self.check_expression(condition, false);
+                let before = self.state.clone();
+                let then_moved = self.check_expression(then_branch, owning);
+                let then_state = std::mem::replace(&mut self.state, before.clone());
+                let else_moved = self.check_expression(else_branch, owning);
+                let else_state = std::mem::replace(&mut self.state, before.clone());
+                self.merge(before, vec![(then_state, false), (else_state, false)]);
+                then_moved || else_moved
+            }
+            ExpressionKind::Match { scrutinee, arms, .. } => {
+                self.check_expression(scrutinee, false);
+                let before = self.state.clone();
+                let mut branches = Vec::new();
+                for arm in arms {
+                    self.check_expression(&arm.body, false);
+                    branches.push((std::mem::replace(&mut self.state, before.clone()), false));
+                }
+                self.merge(before, branches);
+                false
+            }
+            ExpressionKind::Integer(..)
+            | ExpressionKind::Float(..)
+            | ExpressionKind::String(_)
+            | ExpressionKind::Boolean(_)
+            | ExpressionKind::Path(_) => false,
+        }
+    }
+
+    // Reading `place`, which must not have been moved or be mutably
+    // borrowed. Returns whether it's usable.
+    fn check_use(&mut self, place: &Place, name: &str, span: Span) -> bool {
+        if let Some(site) = self.state.moved.get(&place.root).copied() {
+            self.report_moved(place.root, name, site, span, "used");
+            return false;
+        }
+        if let Some(loan) = self.conflicting_loan(place, true) {
+            let (loan_name, loan_span) = (loan.name.clone(), loan.span);
+            self.report(
+                Diagnostic::error(format!("Cannot use `{}` because it is mutably borrowed", name), span)
+                    .with_code("E0204")
+                    .with_primary_label(format!("use of borrowed `{}`", loan_name))
+                    .with_label(loan_span, format!("`{}` is borrowed as mutable here", loan_name)),
+            );
+        }
+        true
+    }
+
+    fn check_borrow(&mut self, place: Place, operand: &Expression, mutable: bool, span: Span) {
+        let name = place_name(operand);
+        if let Some(site) = self.state.moved.get(&place.root).copied() {
+            self.report_moved(place.root, &name, site, operand.span, "borrowed");
+            return;
+        }
+
+        let kind = |mutable| if mutable { "mutable" } else { "immutable" };
+        if let Some(loan) = self.conflicting_loan(&place, !mutable) {
+            let (loan_name, loan_span, loan_mutable) = (loan.name.clone(), loan.span, loan.mutable);
+            let message = if mutable && loan_mutable {
+                format!("Cannot borrow `{}` as mutable more than once at a time", name)
+            } else {
+                format!(
+                    "Cannot borrow `{}` as {} because it is also borrowed as {}",
+                    name,
+                    kind(mutable),
+                    kind(loan_mutable)
+                )
+            };
+            self.report(
+                Diagnostic::error(message, span)
+                    .with_code("E0203")
+                    .with_primary_label(format!("{} borrow occurs here", kind(mutable)))
+                    .with_label(loan_span, format!("`{}` is first borrowed as {} here", loan_name, kind(loan_mutable))),
+            );
+        }
+
+        self.state.loans.push(Loan { place, name, mutable, span, scope: None, holder: None });
+    }
+
+    // The borrows that the value of `expr` is or points into: those it makes
+    // and those held by the bindings it copies
+    fn held_loans(&self, expr: &Expression) -> Vec<Loan> {
+        let (mut bindings, mut borrows) = (Vec::new(), Vec::new());
+        value_sources(expr, &self.resolved, &mut bindings, &mut borrows);
+        self.state
+            .loans
+            .iter()
+            .filter(|loan| borrows.contains(&loan.span) || loan.holder.is_some_and(|holder| bindings.contains(&holder)))
+            .cloned()
+            .collect()
+    }
+
+    // Store `loans` in the binding `id`, so they last as long as it does
+    fn hold(&mut self, id: SymbolId, loans: Vec<Loan>) {
+        let depth = self.scopes.iter().rposition(|scope| scope.contains(&id)).unwrap_or(0);
+        for loan in loans {
+            self.state.loans.push(Loan { scope: Some(depth), holder: Some(id), ..loan });
+        }
+    }
+
+    fn check_assignment(&mut self, target: &Expression) {
+        let Some(place) = place_of(target, &self.resolved) else {
+            // Through a reference
+            self.check_expression(target, false);
+            return;
+        };
+        let name = place_name(target);
+        self.check_indices(target);
+
+        match self.state.moved.get(&place.root).copied() {
+            // Assigning a whole owned binding gives it a value again
+            Some(_) if place.fields.is_empty() => {
+                self.state.moved.remove(&place.root);
+            }
+            Some(site) => {
+                self.report_moved(place.root, &name, site, target.span, "assigned to");
+                return;
+            }
+            None => {}
+        }
+
+        if let Some(loan) = self.conflicting_loan(&place, false) {
+            let (loan_name, loan_span) = (loan.name.clone(), loan.span);
+            self.report(
+                Diagnostic::error(format!("Cannot assign to `{}` because it is borrowed", name), target.span)
+                    .with_code("E0204")
+                    .with_primary_label(format!("assignment to borrowed `{}` occurs here", name))
+                    .with_label(loan_span, format!("`{}` is borrowed here", loan_name)),
+            );
+        }
+    }
+
+    // The indices used in the path to a place are read like any expression
+    fn check_indices(&mut self, place: &Expression) {
+        match &place.kind {
+            ExpressionKind::FieldAccess { object, .. } => self.check_indices(object),
+            ExpressionKind::Index { array, index, .. } => {
+                self.check_indices(array);
+                self.check_expression(index, false);
+            }
+            _ => {}
+        }
+    }
+
+    // A live borrow of a place overlapping `place`; only mutable ones when
+    // `mutable_only`
+    fn conflicting_loan(&self, place: &Place, mutable_only: bool) -> Option<&Loan> {
+        self.state
+            .loans
+            .iter()
+            .find(|loan| (loan.mutable || !mutable_only) && loan.place.overlaps(place))
+    }
+
+    fn report_moved(&mut self, id: SymbolId, name: &str, site: MoveSite, span: Span, action: &str) {
+        // A second pass over a loop only adds errors about the first pass's moves
+        if self.recheck && !site.previous_iteration {
+            return;
+        }
+        let moved = &self.symbols.get(id).name;
+        let label = if site.previous_iteration {
+            format!("`{}` moved here, in a previous iteration of the loop", moved)
+        } else {
+            format!("`{}` moved here", moved)
+        };
+        let error = Diagnostic::error(format!("Use of moved value: `{}`", moved), span).with_code("E0201");
+        // A loop can move a value at the same place it's used
+        let error = if site.span == span {
+            error.with_primary_label(label)
+        } else {
+            error.with_primary_label(format!("`{}` {} here after the move", name, action)).with_label(site.span, label)
+        };
+        if !self.errors.iter().any(|other| other.message == error.message && other.primary_span == error.primary_span) {
+            self.errors.push(error);
+        }
+    }
+
+    fn report(&mut self, error: Diagnostic) {
+        if !self.recheck {
+            self.errors.push(error);
+        }
+    }
+
+    // The binding `expr` names, if it's just a name
+    fn binding(&self, expr: &Expression) -> Option<SymbolId> {
+        match &expr.kind {
+            ExpressionKind::Identifier(_) => self.resolved.get(&expr.span).copied(),
+            _ => None,
+        }
+    }
+}
+
+// The place `expr` refers to, if it's a variable or a field path into one
+fn place_of(expr: &Expression, resolved: &HashMap<Span, SymbolId>) -> Option<Place> {
+    match &expr.kind {
+        ExpressionKind::Identifier(_) => resolved.get(&expr.span).map(|&root| Place { root, fields: Vec::new() }),
+        ExpressionKind::FieldAccess { object, field } => {
+            let mut place = place_of(object, resolved)?;
+            place.fields.push(field.clone());
+            Some(place)
+        }
+        ExpressionKind::Index { array, .. } => {
+            let mut place = place_of(array, resolved)?;
+            place.fields.push("[]".to_string());
+            Some(place)
+        }
+        _ => None,
+    }
+}
+
+// Add the borrows stored in bindings from `from` that `loans` doesn't have yet
+fn keep_held_loans(loans: &mut Vec<Loan>, from: &[Loan]) {
+    for loan in from {
+        if loan.holder.is_some() && !loans.iter().any(|other| other.span == loan.span && other.holder == loan.holder) {
+            loans.push(loan.clone());
+        }
+    }
+}
+
+// The bindings whose value `expr`'s value may be a copy of, and the borrows
+// it may be, by their spans
+fn value_sources(expr: &Expression, resolved: &HashMap<Span, SymbolId>, bindings: &mut Vec<SymbolId>, borrows: &mut Vec<Span>) {
+    match &expr.kind {
+        ExpressionKind::Identifier(_) => bindings.extend(resolved.get(&expr.span)),
+        // `ref *r` borrows what `r` does
+        ExpressionKind::Borrow { operand, .. } => match &operand.kind {
+            ExpressionKind::Deref { operand, .. } => value_sources(operand, resolved, bindings, borrows),
+            _ => borrows.push(expr.span),
+        },
+        ExpressionKind::Slice { .. } => borrows.push(expr.span),
+        ExpressionKind::Move(operand) => value_sources(operand, resolved, bindings, borrows),
+        ExpressionKind::FieldAccess { object, .. } => value_sources(object, resolved, bindings, borrows),
+        ExpressionKind::Index { array, index, .. } => {
+            if matches!(index.kind, ExpressionKind::Range { .. }) {
+                borrows.push(expr.span);
+            }
+            value_sources(array, resolved, bindings, borrows);
+        }
+        ExpressionKind::If { then_branch, else_branch, .. } => {
+            value_sources(then_branch, resolved, bindings, borrows);
+            value_sources(else_branch, resolved, bindings, borrows);
+        }
+        ExpressionKind::Match { arms, .. } => {
+            for arm in arms {
+                value_sources(&arm.body, resolved, bindings, borrows);
+            }
+        }
+        ExpressionKind::StructLiteral { fields, .. } => {
+            for (_, value) in fields {
+                value_sources(value, resolved, bindings, borrows);
+            }
+        }
+        ExpressionKind::ArrayLiteral(elements) => {
+            for element in elements {
+                value_sources(element, resolved, bindings, borrows);
+            }
+        }
+        _ => {}
+    }
+}
+
+// How a place is written in source, for messages
+pub fn place_name(expr: &Expression) -> String {
+    match &expr.kind {
+        ExpressionKind::Identifier(name) => name.clone(),
+        ExpressionKind::FieldAccess { object, field } => format!("{}.{}", place_name(object), field),
+        ExpressionKind::Index { array, .. } => format!("{}[..]", place_name(array)),
+        _ => "value".to_string(),
+    }
+}
+
+// Whether control can't reach the end of `statements`
+fn diverges(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Return(_) | StatementKind::Break | StatementKind::Continue => true,
+        StatementKind::Block(body) => diverges(body),
+        StatementKind::If { then_branch, else_branch: Some(else_branch), .. } => {
+            diverges(then_branch) && diverges(else_branch)
+        }
+        _ => false,
+    })
+}
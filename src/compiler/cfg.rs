@@ -0,0 +1,196 @@
+use std::ptr;
+
+use super::ast::*;
+
+pub type BlockId = usize;
+
+// A straight-line run of statements. Compound statements (`if`, `while`,
+// blocks) sit in the block where control reaches them; their bodies get
+// blocks of their own.
+#[derive(Debug, Default)]
+pub struct BasicBlock<'a> {
+    pub statements: Vec<&'a Statement>,
+    pub successors: Vec<BlockId>,
+    // Ends in a `return`, so control leaves the function instead of
+    // following `successors`
+    pub returns: bool,
+}
+
+// The control flow graph of one function body. Nested function declarations
+// are statements of the body, but their own bodies aren't part of the graph.
+#[derive(Debug)]
+pub struct ControlFlowGraph<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+    pub entry: BlockId,
+    // Where control ends up when it runs off the end of the body
+    pub end: BlockId,
+    // The header and exit of each loop enclosing the statement being lowered
+    loops: Vec<(BlockId, BlockId)>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn build(body: &'a [Statement]) -> Self {
+        let mut graph = ControlFlowGraph {
+            blocks: Vec::new(),
+            entry: 0,
+            end: 0,
+            loops: Vec::new(),
+        };
+        graph.entry = graph.new_block();
+        graph.end = graph.lower_statements(body, graph.entry);
+        graph
+    }
+
+    // Which blocks control can reach from the entry
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut worklist = vec![self.entry];
+        while let Some(block) = worklist.pop() {
+            if reachable[block] {
+                continue;
+            }
+            reachable[block] = true;
+            worklist.extend(&self.blocks[block].successors);
+        }
+        reachable
+    }
+
+    pub fn block_of(&self, statement: &Statement) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .position(|block| block.statements.iter().any(|s| ptr::eq(*s, statement)))
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock::default());
+        self.blocks.len() - 1
+    }
+
+    fn add_edge(&mut self, from: BlockId, to: BlockId) {
+        self.blocks[from].successors.push(to);
+    }
+
+    fn lower_statements(&mut self, statements: &'a [Statement], mut current: BlockId) -> BlockId {
+        for statement in statements {
+            current = self.lower_statement(statement, current);
+        }
+        current
+    }
+
+    // Add `statement` to the graph and return the block control continues in
+    fn lower_statement(&mut self, statement: &'a Statement, current: BlockId) -> BlockId {
+        self.blocks[current].statements.push(statement);
+        match &statement.kind {
+            StatementKind::Return(_) => {
+                self.blocks[current].returns = true;
+                // Whatever follows starts a block nothing jumps to
+                self.new_block()
+            }
+
+            StatementKind::Block(statements) => self.lower_statements(statements, current),
+
+            // Outside a loop, which the analyzer reports, they do nothing
+            StatementKind::Break | StatementKind::Continue => match self.loops.last() {
+                Some(&(header, exit)) => {
+                    let target = if let StatementKind::Break = statement.kind { exit } else { header };
+                    self.add_edge(current, target);
+                    self.new_block()
+                }
+                None => current,
+            },
+
+            StatementKind::If { then_branch, else_branch, .. } => {
+                let then_block = self.new_block();
+                self.add_edge(current, then_block);
+                let then_end = self.lower_statements(then_branch, then_block);
+
+                let join = self.new_block();
+                self.add_edge(then_end, join);
+                match else_branch {
+                    Some(else_branch) => {
+                        let else_block = self.new_block();
+                        self.add_edge(current, else_block);
+                        let else_end = self.lower_statements(else_branch, else_block);
+                        self.add_edge(else_end, join);
+                    }
+                    None => self.add_edge(current, join),
+                }
+                join
+            }
+
+            StatementKind::While { condition, body } => {
+                // The condition is tested in a header block of its own that the
+                // body loops back to
+                let header = self.new_block();
+                self.add_edge(current, header);
+
+                let constant = constant_condition(condition);
+                let body_block = self.new_block();
+                if constant != Some(false) {
+                    self.add_edge(header, body_block);
+                }
+                let exit = self.new_block();
+                if constant != Some(true) {
+                    self.add_edge(header, exit);
+                }
+
+                self.loops.push((header, exit));
+                let body_end = self.lower_statements(body, body_block);
+                self.loops.pop();
+                self.add_edge(body_end, header);
+                exit
+            }
+
+            _ => current,
+        }
+    }
+}
+
+// Whether a loop with this body can be left with `break`
+pub fn breaks_out(body: &[Statement]) -> bool {
+    body.iter().any(|statement| match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::Block(body) => breaks_out(body),
+        StatementKind::If { then_branch, else_branch, .. } => {
+            breaks_out(then_branch) || else_branch.as_deref().is_some_and(breaks_out)
+        }
+        // A `break` in a nested loop leaves that loop
+        _ => false,
+    })
+}
+
+// The value of a condition that can't depend on anything at runtime, such as
+// `true` or `1 < 2`
+pub fn constant_condition(condition: &Expression) -> Option<bool> {
+    match &condition.kind {
+        ExpressionKind::Boolean(value) => Some(*value),
+        ExpressionKind::Unary { operator: UnaryOp::Not, operand, .. } => constant_condition(operand).map(|value| !value),
+        ExpressionKind::Binary { left, operator, right, .. } => match operator {
+            BinaryOp::And => match (constant_condition(left), constant_condition(right)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            BinaryOp::Or => match (constant_condition(left), constant_condition(right)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            _ => {
+                let (ExpressionKind::Integer(left, _), ExpressionKind::Integer(right, _)) = (&left.kind, &right.kind) else {
+                    return None;
+                };
+                match operator {
+                    BinaryOp::Equal => Some(left == right),
+                    BinaryOp::NotEqual => Some(left != right),
+                    BinaryOp::Greater => Some(left > right),
+                    BinaryOp::Less => Some(left < right),
+                    BinaryOp::GreaterEqual => Some(left >= right),
+                    BinaryOp::LessEqual => Some(left <= right),
+                    _ => None,
+                }
+            }
+        },
+        _ => None,
+    }
+}
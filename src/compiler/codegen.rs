@@ -1,22 +1,119 @@
+use llvm_sys::analysis::*;
 use llvm_sys::core::*;
 use llvm_sys::prelude::*;
-use llvm_sys::{LLVMContext, LLVMModule, LLVMBuilder};
+use llvm_sys::{
+    LLVMAttributeFunctionIndex, LLVMContext, LLVMModule, LLVMBuilder, LLVMIntPredicate, LLVMLinkage, LLVMModuleFlagBehavior, LLVMRealPredicate, LLVMTypeKind,
+    LLVMUnnamedAddr,
+};
 use llvm_sys::debuginfo::*;
+use llvm_sys::target::{
+    LLVMABIAlignmentOfType, LLVMGetModuleDataLayout, LLVMOffsetOfElement, LLVMSizeOfTypeInBits, LLVMStoreSizeOfType,
+};
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::ffi::{c_char, CStr, CString};
 use super::ast::*;
+use super::cfg;
+use super::diagnostic::{Diagnostic, Severity};
+use super::span::LineIndex;
+
+// DWARF base type encodings (`DW_ATE_*`)
+const DW_ATE_BOOLEAN: LLVMDWARFTypeEncoding = 0x02;
+const DW_ATE_FLOAT: LLVMDWARFTypeEncoding = 0x04;
+const DW_ATE_SIGNED: LLVMDWARFTypeEncoding = 0x05;
+const DW_ATE_SIGNED_CHAR: LLVMDWARFTypeEncoding = 0x06;
+const DW_ATE_UNSIGNED: LLVMDWARFTypeEncoding = 0x08;
 
 pub struct CodeGenerator {
     context: *mut LLVMContext,
     module: *mut LLVMModule,
     builder: *mut LLVMBuilder,
-    di_builder: *mut LLVMDIBuilder,
+    di_builder: LLVMDIBuilderRef,
+    // The stack slot of each local in scope
     named_values: HashMap<String, LLVMValueRef>,
+    structs: HashMap<String, StructLayout>,
+    enums: HashMap<String, EnumLayout>,
+    // Generic structs get a layout per list of type arguments, in `structs`
+    // under names like `Pair<i32, f64>`, when a type first uses them
+    generic_structs: HashMap<String, GenericStruct>,
+    // Generic functions are generated once per list of type arguments,
+    // when first called with them
+    generic_functions: HashMap<String, Statement>,
+    instances: HashMap<(String, Vec<Type>), LLVMValueRef>,
+    // What the type parameters of the instance being generated stand for
+    type_arguments: HashMap<String, Type>,
+    // Each impl method by its implementing type and name
+    methods: HashMap<(Type, String), LLVMValueRef>,
+    // The `drop` method of each type that implements `Drop`
+    drops: HashMap<Type, LLVMValueRef>,
+    // String literals, by contents, as constant strings borrowing the bytes
+    // of a global
+    strings: HashMap<String, LLVMValueRef>,
+    // The owned bindings of each block of the function being generated,
+    // innermost last, which are dropped when the block ends
+    owned: Vec<Vec<OwnedBinding>>,
+    // The loops enclosing the statement being generated, innermost last
+    loops: Vec<Loop>,
+    // Whether indexing checks the index against the length first
+    bounds_checks: bool,
+    // Whether integer arithmetic traps on overflow rather than wrapping
+    overflow_checks: bool,
     debug_info: DebugInfo,
+    // Turns AST spans into the line/column numbers debug info needs
+    line_index: LineIndex,
+}
+
+// A struct's LLVM type and its fields in declaration order, which is the
+// order of the LLVM type's elements
+struct StructLayout {
+    llvm_type: LLVMTypeRef,
+    fields: Vec<(String, Type)>,
+    line: u32,
+    // Built on first use, since it needs the debug types of the fields
+    debug_type: LLVMMetadataRef,
+}
+
+struct GenericStruct {
+    generics: Vec<String>,
+    fields: Vec<(String, Type)>,
+    line: u32,
+}
+
+// An enum is a tagged union `{ i32, [N x i64] }`: the tag is the variant's
+// position, and the array is storage big enough for the largest payload.
+// Payloads are read and written through a pointer to the variant's own
+// payload struct.
+struct EnumLayout {
+    llvm_type: LLVMTypeRef,
+    // Each variant's name, payload struct type and payload field types
+    variants: Vec<(String, LLVMTypeRef, Vec<Type>)>,
+}
+
+struct OwnedBinding {
+    slot: LLVMValueRef,
+    ty: Type,
+    // The borrow checker makes sure a binding is moved on every path or none
+    moved: bool,
+}
+
+// Where `continue` and `break` go in a loop. `depth` is how many blocks of
+// owned bindings enclose the loop, which are left alone when jumping.
+struct Loop {
+    header: LLVMBasicBlockRef,
+    exit: LLVMBasicBlockRef,
+    depth: usize,
+    // What had been moved at each `break`
+    breaks: Vec<Vec<bool>>,
 }
 
+// `LLVMBuildAdd` and the other two-operand instruction builders
+type BuildBinary = unsafe extern "C" fn(LLVMBuilderRef, LLVMValueRef, LLVMValueRef, *const c_char) -> LLVMValueRef;
+
+// The block a `match` arm's body starts in, with the values its pattern binds
+type ArmBody = (LLVMBasicBlockRef, Vec<(String, LLVMValueRef)>);
+
 struct DebugInfo {
     compile_unit: LLVMMetadataRef,
+    file: LLVMMetadataRef,
     current_scope: LLVMMetadataRef,
     current_location: LLVMMetadataRef,
 }
@@ -43,40 +140,97 @@ impl CodeGenerator {
             
             let compile_unit = LLVMDIBuilderCreateCompileUnit(
                 di_builder,
-                DW_LANG_C,  // Using C as base language
+                LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,  // Using C as base language
                 file,
                 "Swift++ Compiler".as_ptr() as *const _,
                 15,
                 0,  // Not optimized
                 "".as_ptr() as *const _,
-                0,
-                1,  // Debug version
                 0,  // No flags
                 0,  // Runtime version
+                "".as_ptr() as *const _,
                 0,  // No split name
-                0,  // No kind
+                LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
                 0,  // DWO id
                 1,  // Split debug inlining
                 0,  // Debug info for profiling
+                "".as_ptr() as *const _,
                 0,  // No sys root
+                "".as_ptr() as *const _,
                 0,  // No SDK
             );
 
+            // Without this flag LLVM drops the debug info when the module is read back
+            let debug_version = CString::new("Debug Info Version").unwrap();
+            LLVMAddModuleFlag(
+                module,
+                LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+                debug_version.as_ptr(),
+                debug_version.as_bytes().len(),
+                LLVMValueAsMetadata(LLVMConstInt(
+                    LLVMInt32TypeInContext(context),
+                    LLVMDebugMetadataVersion() as u64,
+                    0,
+                )),
+            );
+
             CodeGenerator {
                 context,
                 module,
                 builder,
                 di_builder,
                 named_values: HashMap::new(),
+                structs: HashMap::new(),
+                enums: HashMap::new(),
+                generic_structs: HashMap::new(),
+                generic_functions: HashMap::new(),
+                instances: HashMap::new(),
+                type_arguments: HashMap::new(),
+                methods: HashMap::new(),
+                drops: HashMap::new(),
+                strings: HashMap::new(),
+                owned: Vec::new(),
+                loops: Vec::new(),
+                bounds_checks: true,
+                overflow_checks: true,
                 debug_info: DebugInfo {
                     compile_unit,
+                    file,
                     current_scope: file,
                     current_location: std::ptr::null_mut(),
                 },
+                line_index: LineIndex::default(),
             }
         }
     }
 
+    // Like `new`, but with the source text so debug locations get real line numbers
+    pub fn with_source(module_name: &str, source: &str) -> Self {
+        let mut generator = Self::new(module_name);
+        generator.line_index = LineIndex::new(source);
+        generator
+    }
+
+    pub fn set_bounds_checks(&mut self, enabled: bool) {
+        self.bounds_checks = enabled;
+    }
+
+    pub fn set_overflow_checks(&mut self, enabled: bool) {
+        self.overflow_checks = enabled;
+    }
+
+    fn set_debug_location(&mut self, span: Span) {
+        let (line, column) = self.line_index.line_col(span.start);
+        self.create_debug_location(line as u32, column as u32);
+    }
+
+    fn clear_debug_location(&mut self) {
+        unsafe {
+            self.debug_info.current_location = std::ptr::null_mut();
+            LLVMSetCurrentDebugLocation2(self.builder, std::ptr::null_mut());
+        }
+    }
+
     fn create_debug_location(&mut self, line: u32, column: u32) {
         unsafe {
             self.debug_info.current_location = LLVMDIBuilderCreateDebugLocation(
@@ -100,118 +254,397 @@ impl CodeGenerator {
                 0,
             );
 
+            let linkage_name = symbol_name(name);
             LLVMDIBuilderCreateFunction(
                 self.di_builder,
-                self.debug_info.current_scope,
-                name.as_ptr() as *const _,
-                name.len(),
+                self.debug_info.file,
                 name.as_ptr() as *const _,
                 name.len(),
-                self.debug_info.compile_unit,
+                linkage_name.as_ptr() as *const _,
+                linkage_name.len(),
+                self.debug_info.file,
                 line,
                 function_type,
                 0,  // Not local to unit
                 1,  // Is definition
                 line,
-                LLVMDIFlagPrototyped as u32,
+                LLVMDIFlagPrototyped,
                 0,  // Not optimized
             )
         }
     }
 
-    fn create_variable_debug_info(&mut self, name: &str, ty: LLVMMetadataRef, line: u32, alloca: LLVMValueRef) {
-        unsafe {
-            let variable = LLVMDIBuilderCreateAutoVariable(
-                self.di_builder,
-                self.debug_info.current_scope,
-                name.as_ptr() as *const _,
-                name.len(),
-                self.debug_info.compile_unit,
-                line,
-                ty,
-                0,  // Not optimized
-                0,  // No flags
-                0,  // Alignment in bits
-            );
+    pub fn generate(&mut self, program: &Program) -> Result<(), Box<Diagnostic>> {
+        self.declare_types(program);
+        self.declare_functions(program)?;
+        for statement in &program.statements {
+            // Code only runs inside a function
+            if !is_declaration(statement) {
+                return Err(Diagnostic::error("Statements outside of a function are not supported", statement.span)
+                    .with_code("E0301")
+                    .with_primary_label("move this into `main`").into());
+            }
+            self.generate_statement(statement)?;
+        }
 
-            LLVMDIBuilderInsertDeclareAtEnd(
-                self.di_builder,
-                alloca,
-                variable,
-                LLVMDIBuilderCreateExpression(self.di_builder, std::ptr::null_mut(), 0),
-                self.debug_info.current_location,
-                LLVMGetInsertBlock(self.builder),
-            );
+        unsafe {
+            LLVMDIBuilderFinalize(self.di_builder);
         }
+
+        self.verify().map_err(|message| Diagnostic::from(message).with_code("E0302").into())
     }
 
-    pub fn generate(&mut self, program: &Program) -> Result<(), String> {
-        for statement in &program.statements {
-            self.generate_statement(statement)?;
+    pub fn module(&self) -> LLVMModuleRef {
+        self.module
+    }
+
+    pub fn verify(&self) -> Result<(), String> {
+        unsafe {
+            let mut message = std::ptr::null_mut();
+            let failed = LLVMVerifyModule(
+                self.module,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            ) != 0;
+
+            let result = if failed {
+                Err(format!(
+                    "Module verification failed: {}",
+                    CStr::from_ptr(message).to_string_lossy()
+                ))
+            } else {
+                Ok(())
+            };
+
+            if !message.is_null() {
+                LLVMDisposeMessage(message);
+            }
+            result
         }
-        Ok(())
     }
 
-    fn generate_statement(&mut self, statement: &Statement) -> Result<LLVMValueRef, String> {
-        match statement {
-            Statement::Function { name, params, return_type, body } => {
-                self.generate_function(name, params, return_type, body)
+    fn generate_statement(&mut self, statement: &Statement) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        // Inside a function body, attribute instructions to the statement they come from
+        if self.debug_info.current_scope != self.debug_info.file {
+            self.set_debug_location(statement.span);
+        }
+
+        match &statement.kind {
+            // Generated when `instantiate_function` is asked for an instance
+            StatementKind::Function { generics, .. } if !generics.is_empty() => Ok(std::ptr::null_mut()),
+            StatementKind::Function { name, params, return_type, body, .. } => {
+                // Functions nested in a method can still name `Self`
+                let type_arguments = self.type_arguments.clone();
+                self.in_new_function(type_arguments, |this| {
+                    this.generate_function(name, params, return_type, body, statement.span)
+                })
             }
-            Statement::Let { name, type_annotation: _, initializer } => {
+            StatementKind::Impl(impl_decl) => {
+                let type_arguments = HashMap::from([("Self".to_string(), impl_decl.self_type.clone())]);
+                for method in &impl_decl.methods {
+                    if let StatementKind::Function { name, params, return_type, body, .. } = &method.kind {
+                        let name = method_name(impl_decl, name);
+                        self.in_new_function(type_arguments.clone(), |this| {
+                            this.generate_function(&name, params, return_type, body, method.span)
+                        })?;
+                    }
+                }
+                Ok(std::ptr::null_mut())
+            }
+            StatementKind::Let { name, name_span, type_annotation, initializer, .. } => {
                 let value = self.generate_expression(initializer)?;
-                self.named_values.insert(name.clone(), value);
+                let slot = match type_annotation {
+                    Some(Type::Own(ty)) => self.bind_owned(name, ty, value),
+                    _ => self.bind_variable(name, value),
+                };
+                if let Some(ty) = type_annotation {
+                    self.declare_debug_value(name, ty, slot, *name_span, None);
+                }
                 Ok(value)
             }
-            Statement::Return(expr) => {
+            StatementKind::Assign { target, operator, value: value_expr, operand_type } => {
+                let value = self.generate_expression(value_expr)?;
+                let operator = operator.map(|operator| (operator, operand_type.clone().unwrap_or(Type::Int)));
+                // `s += t` copies `t`, so it's done with once appended
+                let appended = operator.is_some();
+                self.assign_place(target, operator, value, statement.span)?;
+                if appended {
+                    self.free_temporary(value_expr, value);
+                }
+                Ok(value)
+            }
+            // Laid out up front by `declare_types`
+            StatementKind::Struct { .. } | StatementKind::Enum(_) | StatementKind::Trait(_) => Ok(std::ptr::null_mut()),
+            StatementKind::Return(expr) => {
                 if let Some(expr) = expr {
                     let value = self.generate_expression(expr)?;
+                    self.drop_owned(0);
                     unsafe {
                         Ok(LLVMBuildRet(self.builder, value))
                     }
                 } else {
+                    self.drop_owned(0);
                     unsafe {
                         Ok(LLVMBuildRetVoid(self.builder))
                     }
                 }
             }
-            Statement::Expression(expr) => self.generate_expression(expr),
-            _ => Err("Unsupported statement".to_string()),
+            StatementKind::Expression(expr) => self.generate_expression(expr),
+            StatementKind::Block(statements) => self.generate_block(statements),
+            StatementKind::If { condition, then_branch, else_branch } => {
+                let condition = self.generate_expression(condition)?;
+                let then_block = self.append_block("if.then");
+                let else_block = else_branch.as_ref().map(|_| self.append_block("if.else"));
+                let merge = self.append_block("if.end");
+                unsafe {
+                    LLVMBuildCondBr(self.builder, condition, then_block, else_block.unwrap_or(merge));
+                }
+
+                // Each branch starts from what was moved before the `if`
+                let before = self.moved_flags();
+                let mut reaching = Vec::new();
+                for (block, branch) in [(Some(then_block), Some(then_branch)), (else_block, else_branch.as_ref())] {
+                    let (Some(block), Some(branch)) = (block, branch) else {
+                        reaching.push(before.clone());
+                        continue;
+                    };
+                    self.set_moved_flags(&before);
+                    unsafe { LLVMPositionBuilderAtEnd(self.builder, block) };
+                    self.generate_block(branch)?;
+                    if !self.is_terminated() {
+                        reaching.push(self.moved_flags());
+                        unsafe { LLVMBuildBr(self.builder, merge) };
+                    }
+                }
+
+                // The borrow checker makes sure the branches that reach the
+                // merge block agree on what they moved
+                unsafe { LLVMPositionBuilderAtEnd(self.builder, merge) };
+                match reaching.first() {
+                    Some(flags) => self.set_moved_flags(flags),
+                    None => unsafe {
+                        LLVMBuildUnreachable(self.builder);
+                    },
+                }
+                Ok(std::ptr::null_mut())
+            }
+            StatementKind::While { condition, body } => {
+                let header = self.append_block("while.cond");
+                let body_block = self.append_block("while.body");
+                let exit = self.append_block("while.end");
+                unsafe {
+                    LLVMBuildBr(self.builder, header);
+                    LLVMPositionBuilderAtEnd(self.builder, header);
+                }
+                let condition_value = self.generate_expression(condition)?;
+                unsafe {
+                    LLVMBuildCondBr(self.builder, condition_value, body_block, exit);
+                    LLVMPositionBuilderAtEnd(self.builder, body_block);
+                }
+
+                // An iteration gives back whatever it moves, or the borrow checker would object
+                let before = self.moved_flags();
+                self.loops.push(Loop { header, exit, depth: self.owned.len(), breaks: Vec::new() });
+                let result = self.generate_block(body);
+                let lowered = self.loops.pop().expect("loop was pushed");
+                result?;
+                if !self.is_terminated() {
+                    unsafe { LLVMBuildBr(self.builder, header) };
+                }
+
+                // `while true` is only left by a `break`
+                unsafe { LLVMPositionBuilderAtEnd(self.builder, exit) };
+                let endless = cfg::constant_condition(condition) == Some(true);
+                match lowered.breaks.first() {
+                    Some(flags) if endless => self.set_moved_flags(flags),
+                    None if endless => unsafe {
+                        LLVMBuildUnreachable(self.builder);
+                    },
+                    _ => self.set_moved_flags(&before),
+                }
+                Ok(std::ptr::null_mut())
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                let Some(target) = self.loops.last() else {
+                    return Err(Diagnostic::error("`break` or `continue` outside of a loop", statement.span).with_code("E0301").into());
+                };
+                let (depth, destination) = match statement.kind {
+                    StatementKind::Break => (target.depth, target.exit),
+                    _ => (target.depth, target.header),
+                };
+                // Leaving the body ends the owned bindings of its blocks
+                self.drop_owned(depth);
+                if let StatementKind::Break = statement.kind {
+                    let flags = self.moved_flags();
+                    self.loops.last_mut().unwrap().breaks.push(flags);
+                }
+                unsafe { Ok(LLVMBuildBr(self.builder, destination)) }
+            }
         }
     }
 
-    fn generate_function(
-        &mut self,
-        name: &str,
-        params: &[(String, Type)],
-        return_type: &Option<Type>,
-        body: &[Statement]
-    ) -> Result<LLVMValueRef, String> {
+    // A block's statements, with its owned bindings dropped at its end
+    fn generate_block(&mut self, statements: &[Statement]) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        self.owned.push(Vec::new());
+        let last = self.generate_statements(statements);
+        if last.is_ok() && !self.is_terminated() {
+            self.drop_owned(self.owned.len() - 1);
+        }
+        self.owned.pop();
+        last
+    }
+
+    // Statements after a `return`, `break` or `continue` can never run, so
+    // only the declarations among them are generated
+    fn generate_statements(&mut self, statements: &[Statement]) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let mut last = std::ptr::null_mut();
+        for statement in statements {
+            if is_declaration(statement) || !self.is_terminated() {
+                last = self.generate_statement(statement)?;
+            }
+        }
+        Ok(last)
+    }
+
+    // Declare the function `name` unless it already is, with the current
+    // type arguments substituted into its signature
+    fn declare_function(&mut self, name: &str, params: &[Param], return_type: &Option<Type>) -> Result<LLVMValueRef, Box<Diagnostic>> {
         unsafe {
-            // Create function type
-            let param_types: Vec<LLVMTypeRef> = params
-                .iter()
-                .map(|(_, ty)| self.type_to_llvm(ty))
-                .collect();
-            
-            let return_type = if let Some(ty) = return_type {
-                self.type_to_llvm(ty)
-            } else {
-                LLVMVoidTypeInContext(self.context)
+            let symbol = CString::new(symbol_name(name)).unwrap();
+            let function = LLVMGetNamedFunction(self.module, symbol.as_ptr());
+            if !function.is_null() {
+                return Ok(function);
+            }
+
+            // The analyzer fills in the types of unannotated parameters
+            let mut param_types = Vec::new();
+            for param in params {
+                let ty = param.ty.as_ref().ok_or_else(|| {
+                    Diagnostic::error(format!("Type of parameter `{}` is unknown", param.name), param.span)
+                        .with_code("E0301")
+                })?;
+                param_types.push(self.type_to_llvm(ty));
+            }
+
+            let return_type = match return_type {
+                Some(ty) => self.type_to_llvm(ty),
+                None => LLVMVoidTypeInContext(self.context),
             };
 
             let function_type = LLVMFunctionType(
                 return_type,
-                param_types.as_ptr(),
+                param_types.as_mut_ptr(),
                 param_types.len() as u32,
                 0
             );
+            Ok(LLVMAddFunction(self.module, symbol.as_ptr(), function_type))
+        }
+    }
 
-            // Create function
-            let function = LLVMAddFunction(
-                self.module,
-                CString::new(name).unwrap().as_ptr(),
-                function_type
-            );
+    // Declare every function and method before generating any, so calls
+    // can come before the functions they call
+    fn declare_functions(&mut self, program: &Program) -> Result<(), Box<Diagnostic>> {
+        // A program's own `Drop` trait is an ordinary one
+        let builtin_drop = !program
+            .statements
+            .iter()
+            .any(|statement| matches!(&statement.kind, StatementKind::Trait(Trait { name, .. }) if name == "Drop"));
+        for statement in &program.statements {
+            match &statement.kind {
+                StatementKind::Function { name, generics, .. } if !generics.is_empty() => {
+                    self.generic_functions.insert(name.clone(), statement.clone());
+                }
+                StatementKind::Function { name, params, return_type, .. } => {
+                    self.declare_function(name, params, return_type)?;
+                }
+                StatementKind::Impl(impl_decl) => {
+                    self.type_arguments = HashMap::from([("Self".to_string(), impl_decl.self_type.clone())]);
+                    for method in &impl_decl.methods {
+                        if let StatementKind::Function { name, params, return_type, .. } = &method.kind {
+                            let function = self.declare_function(&method_name(impl_decl, name), params, return_type)?;
+                            self.methods.insert((impl_decl.self_type.clone(), name.clone()), function);
+                            if builtin_drop && impl_decl.trait_name == "Drop" {
+                                self.drops.insert(impl_decl.self_type.clone(), function);
+                            }
+                        }
+                    }
+                    self.type_arguments.clear();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // The instance of generic function `name` for `type_arguments`,
+    // generated on first use
+    fn instantiate_function(&mut self, name: &str, type_arguments: Vec<Type>, span: Span) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let key = (name.to_string(), type_arguments);
+        if let Some(function) = self.instances.get(&key) {
+            return Ok(*function);
+        }
+
+        let statement = self.generic_functions.get(name).cloned().ok_or_else(|| {
+            Diagnostic::error(format!("Unknown function: {}", name), span).with_code("E0304")
+        })?;
+        let StatementKind::Function { generics, params, return_type, body, .. } = &statement.kind else {
+            unreachable!("only functions are registered as generic functions");
+        };
+
+        let arguments: Vec<_> = key.1.iter().map(|ty| ty.to_string()).collect();
+        let instance_name = format!("{}<{}>", name, arguments.join(", "));
+        let substitution = generics.iter().map(|param| param.name.clone()).zip(key.1.iter().cloned()).collect();
+
+        // Cached before the body is generated, so a recursive call finds it
+        let function = self.in_new_function(substitution, |this| {
+            let function = this.declare_function(&instance_name, params, return_type)?;
+            this.instances.insert(key.clone(), function);
+            this.generate_function(&instance_name, params, return_type, body, statement.span)
+        })?;
+        Ok(function)
+    }
+
+    // Run `generate` with a fresh set of local values and `type_arguments`
+    // in place, then go back to generating the enclosing function, if any
+    fn in_new_function<T>(&mut self, type_arguments: HashMap<String, Type>, generate: impl FnOnce(&mut Self) -> T) -> T {
+        let block = unsafe { LLVMGetInsertBlock(self.builder) };
+        let named_values = std::mem::take(&mut self.named_values);
+        let owned = std::mem::take(&mut self.owned);
+        let loops = std::mem::take(&mut self.loops);
+        let type_arguments = std::mem::replace(&mut self.type_arguments, type_arguments);
+        let location = self.debug_info.current_location;
+
+        let result = generate(self);
+
+        self.named_values = named_values;
+        self.owned = owned;
+        self.loops = loops;
+        self.type_arguments = type_arguments;
+        self.debug_info.current_location = location;
+        unsafe {
+            if !block.is_null() {
+                LLVMPositionBuilderAtEnd(self.builder, block);
+            }
+            LLVMSetCurrentDebugLocation2(self.builder, location);
+        }
+        result
+    }
+
+    fn generate_function(
+        &mut self,
+        name: &str,
+        params: &[Param],
+        return_type: &Option<Type>,
+        body: &[Statement],
+        span: Span,
+    ) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let function = self.declare_function(name, params, return_type)?;
+        unsafe {
+            // A function nested in a generic one was generated with its first instance
+            if LLVMCountBasicBlocks(function) != 0 {
+                return Ok(function);
+            }
+            let return_type = LLVMGetReturnType(LLVMGlobalGetValueType(function));
 
             // Create entry block
             let bb = LLVMAppendBasicBlockInContext(
@@ -221,88 +654,150 @@ impl CodeGenerator {
             );
             LLVMPositionBuilderAtEnd(self.builder, bb);
 
-            // Add parameters to symbol table
-            self.named_values.clear();
-            for (i, (name, _)) in params.iter().enumerate() {
-                let param = LLVMGetParam(function, i as u32);
-                self.named_values.insert(name.clone(), param);
-            }
+            // Create function debug info and make it the scope of the body's locations
+            let (line, _) = self.line_index.line_col(span.start);
+            let function_debug_info = self.create_function_debug_info(name, line as u32);
+            LLVMSetSubprogram(function, function_debug_info);
+            let enclosing_scope = std::mem::replace(&mut self.debug_info.current_scope, function_debug_info);
+            // Spilling the parameters belongs to the declaration
+            self.set_debug_location(span);
 
-            // Create function debug info
-            let function_debug_info = self.create_function_debug_info(name, 1);
+            // Spill the parameters to stack slots, like any other local
+            self.owned.push(Vec::new());
+            for (i, param) in params.iter().enumerate() {
+                let value = LLVMGetParam(function, i as u32);
+                let slot = match &param.ty {
+                    Some(Type::Own(ty)) => self.bind_owned(&param.name, ty, value),
+                    _ => self.bind_variable(&param.name, value),
+                };
+                if let Some(ty) = &param.ty {
+                    self.declare_debug_value(&param.name, ty, slot, param.span, Some(i as u32 + 1));
+                }
+            }
 
             // Generate function body
-            for statement in body {
-                self.generate_statement(statement)?;
+            let result = self.generate_statements(body);
+            self.debug_info.current_scope = enclosing_scope;
+            if let Err(e) = result {
+                self.clear_debug_location();
+                return Err(e);
+            }
+
+            // Void functions may fall off the end of their body. The analyzer
+            // makes sure other functions can't, so the end is unreachable.
+            if !self.is_terminated() {
+                if return_type == LLVMVoidTypeInContext(self.context) {
+                    self.drop_owned(0);
+                    LLVMBuildRetVoid(self.builder);
+                } else {
+                    LLVMBuildUnreachable(self.builder);
+                }
             }
+            self.owned.pop();
+
+            self.clear_debug_location();
+            LLVMDIBuilderFinalizeSubprogram(self.di_builder, function_debug_info);
 
             // Verify function
             if LLVMVerifyFunction(function, LLVMVerifierFailureAction::LLVMPrintMessageAction) == 1 {
-                return Err("Function verification failed".to_string());
+                return Err(Diagnostic::error("Function verification failed", span).with_code("E0302").into());
             }
 
             Ok(function)
         }
     }
 
-    fn generate_expression(&mut self, expr: &Expression) -> Result<LLVMValueRef, String> {
-        match expr {
-            Expression::Integer(value) => unsafe {
-                Ok(LLVMConstInt(LLVMInt64TypeInContext(self.context), *value as u64, 0))
+    fn generate_expression(&mut self, expr: &Expression) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        match &expr.kind {
+            ExpressionKind::Integer(value, ty) => {
+                let ty = self.type_to_llvm(ty.as_ref().unwrap_or(&Type::Int));
+                Ok(integer_constant(ty, *value))
+            },
+            ExpressionKind::Float(value, ty) => {
+                let ty = self.type_to_llvm(ty.as_ref().unwrap_or(&Type::Float));
+                unsafe { Ok(LLVMConstReal(ty, *value)) }
             },
-            Expression::Float(value) => unsafe {
-                Ok(LLVMConstReal(LLVMDoubleTypeInContext(self.context), *value))
+            ExpressionKind::String(value) => Ok(self.string_constant(value)),
+            ExpressionKind::Interpolation(desugared) => self.generate_expression(desugared),
+            ExpressionKind::Boolean(value) => unsafe {
+                Ok(LLVMConstInt(LLVMInt1TypeInContext(self.context), *value as u64, 0))
             },
-            Expression::Identifier(name) => {
-                self.named_values
-                    .get(name)
-                    .copied()
-                    .ok_or_else(|| format!("Unknown variable: {}", name))
+            ExpressionKind::Identifier(name) => {
+                let (slot, ty) = self.place_pointer(expr)?;
+                unsafe { Ok(LLVMBuildLoad2(self.builder, ty, slot, CString::new(name.as_str()).unwrap().as_ptr())) }
             },
-            Expression::Binary { left, operator, right } => {
+            ExpressionKind::Binary { left, operator: operator @ (BinaryOp::And | BinaryOp::Or), right, .. } => {
+                self.generate_logical(*operator, left, right)
+            },
+            ExpressionKind::Binary { left, operator, right, operand_type } => {
                 let l = self.generate_expression(left)?;
                 let r = self.generate_expression(right)?;
+                let operand_type = operand_type.clone().unwrap_or(Type::Int);
+                let result = self.build_binary(*operator, l, r, &operand_type, expr.span)?;
+                self.free_temporary(left, l);
+                self.free_temporary(right, r);
+                Ok(result)
+            },
+            ExpressionKind::Unary { operator, operand, operand_type } => {
+                let value = self.generate_expression(operand)?;
+                let operand_type = operand_type.as_ref().map(|ty| ty.substitute(&self.type_arguments)).unwrap_or(Type::Int);
+                let float = operand_type.is_float();
 
                 unsafe {
                     match operator {
-                        BinaryOp::Add => Ok(LLVMBuildAdd(
-                            self.builder,
-                            l,
-                            r,
-                            CString::new("addtmp").unwrap().as_ptr()
-                        )),
-                        BinaryOp::Subtract => Ok(LLVMBuildSub(
+                        // The analyzer checked a negated literal fits, so `-128i8` can't overflow
+                        UnaryOp::Negate if matches!(operand.kind, ExpressionKind::Integer(..)) => Ok(LLVMConstNeg(value)),
+                        UnaryOp::Negate if float => Ok(LLVMBuildFNeg(
                             self.builder,
-                            l,
-                            r,
-                            CString::new("subtmp").unwrap().as_ptr()
+                            value,
+                            CString::new("negtmp").unwrap().as_ptr()
                         )),
-                        BinaryOp::Multiply => Ok(LLVMBuildMul(
+                        // Negating the smallest value overflows, like `0 - x`
+                        UnaryOp::Negate if self.overflow_checks => {
+                            let zero = LLVMConstInt(LLVMTypeOf(value), 0, 0);
+                            Ok(self.build_checked_arithmetic(BinaryOp::Subtract, true, zero, value, expr.span))
+                        }
+                        UnaryOp::Negate => Ok(LLVMBuildNeg(
                             self.builder,
-                            l,
-                            r,
-                            CString::new("multmp").unwrap().as_ptr()
+                            value,
+                            CString::new("negtmp").unwrap().as_ptr()
                         )),
-                        BinaryOp::Divide => Ok(LLVMBuildSDiv(
+                        UnaryOp::Not => Ok(LLVMBuildNot(
                             self.builder,
-                            l,
-                            r,
-                            CString::new("divtmp").unwrap().as_ptr()
+                            value,
+                            CString::new("nottmp").unwrap().as_ptr()
                         )),
-                        _ => Err("Unsupported binary operator".to_string()),
                     }
                 }
             },
-            Expression::Call { function, arguments } => {
-                if let Expression::Identifier(name) = &**function {
+            ExpressionKind::Path(path) => self.generate_variant(path, &[], expr.span),
+            ExpressionKind::Call { function, arguments, type_arguments } => {
+                if let ExpressionKind::Path(path) = &function.kind {
+                    return self.generate_variant(path, arguments, expr.span);
+                }
+                if let ExpressionKind::Identifier(name) = &function.kind {
+                    let function_span = function.span;
                     unsafe {
-                        let function = LLVMGetNamedFunction(
-                            self.module,
-                            CString::new(name.as_str()).unwrap().as_ptr()
-                        );
-                        
+                        // Builtins take the type of their argument as a type argument
+                        let function = if type_arguments.is_empty() || !self.generic_functions.contains_key(name) {
+                            LLVMGetNamedFunction(
+                                self.module,
+                                CString::new(symbol_name(name)).unwrap().as_ptr()
+                            )
+                        } else {
+                            let type_arguments = type_arguments.iter().map(|ty| ty.substitute(&self.type_arguments)).collect();
+                            self.instantiate_function(name, type_arguments, function_span)?
+                        };
+
+
                         if function.is_null() {
-                            return Err(format!("Unknown function: {}", name));
+                            if let Some(result) = self.generate_builtin_call(name, arguments, type_arguments, expr.span) {
+                                return result;
+                            }
+                            return Err(
+                                Diagnostic::error(format!("Unknown function: {}", name), function_span)
+                                    .with_code("E0304").into(),
+                            );
                         }
 
                         let mut args: Vec<LLVMValueRef> = Vec::new();
@@ -310,46 +805,1962 @@ impl CodeGenerator {
                             args.push(self.generate_expression(arg)?);
                         }
 
-                        Ok(LLVMBuildCall2(
-                            self.builder,
-                            LLVMTypeOf(function),
-                            function,
-                            args.as_mut_ptr(),
-                            args.len() as u32,
-                            CString::new("calltmp").unwrap().as_ptr()
-                        ))
+                        Ok(self.build_call(function, &mut args))
                     }
                 } else {
-                    Err("Invalid function call".to_string())
+                    Err(Diagnostic::error("Invalid function call", function.span).with_code("E0301").into())
+                }
+            },
+            ExpressionKind::MethodCall { receiver, method, arguments, receiver_type } => {
+                let receiver_type = receiver_type.as_ref().map(|ty| ty.substitute(&self.type_arguments));
+                let function = receiver_type
+                    .and_then(|ty| self.methods.get(&(ty, method.clone())).copied())
+                    .ok_or_else(|| Diagnostic::error(format!("Unknown method: {}", method), expr.span).with_code("E0304"))?;
+
+                let mut args = vec![self.generate_expression(receiver)?];
+                for arg in arguments {
+                    args.push(self.generate_expression(arg)?);
+                }
+                Ok(self.build_call(function, &mut args))
+            },
+            ExpressionKind::StructLiteral { name, fields, type_arguments } => {
+                // An instance of a generic struct is laid out under its full name
+                let ty = Type::Custom(name.clone(), type_arguments.clone()).substitute(&self.type_arguments);
+                self.type_to_llvm(&ty);
+                let (llvm_type, layout_fields) = match self.structs.get(&ty.to_string()) {
+                    Some(layout) => (layout.llvm_type, layout.fields.clone()),
+                    None => {
+                        return Err(Diagnostic::error(format!("Unknown struct: {}", name), expr.span).with_code("E0304").into())
+                    }
+                };
+
+                // Fields are evaluated in the order they're written, then placed in layout order
+                let mut values = HashMap::new();
+                for (field, value) in fields {
+                    values.insert(field.as_str(), self.generate_expression(value)?);
+                }
+
+                let mut aggregate = unsafe { LLVMGetUndef(llvm_type) };
+                for (index, (field, _)) in layout_fields.iter().enumerate() {
+                    let value = values.get(field.as_str()).ok_or_else(|| {
+                        Diagnostic::error(format!("Missing field `{}` in `{}`", field, name), expr.span).with_code("E0304")
+                    })?;
+                    aggregate = unsafe {
+                        LLVMBuildInsertValue(
+                            self.builder,
+                            aggregate,
+                            *value,
+                            index as u32,
+                            CString::new(name.as_str()).unwrap().as_ptr()
+                        )
+                    };
+                }
+                Ok(aggregate)
+            },
+            ExpressionKind::FieldAccess { object, field } => {
+                let aggregate = self.generate_expression(object)?;
+                let index = self.field_index(aggregate, field, expr.span)?;
+                unsafe {
+                    Ok(LLVMBuildExtractValue(
+                        self.builder,
+                        aggregate,
+                        index,
+                        CString::new(field.as_str()).unwrap().as_ptr()
+                    ))
+                }
+            },
+            ExpressionKind::Match { scrutinee, arms, scrutinee_type } => {
+                let scrutinee_type = scrutinee_type.as_ref().map(|ty| ty.substitute(&self.type_arguments)).unwrap_or(Type::Int);
+                self.generate_match(scrutinee, &scrutinee_type, arms)
+            },
+            ExpressionKind::If { condition, then_branch, else_branch } => {
+                self.generate_if_expression(condition, then_branch, else_branch)
+            },
+            ExpressionKind::ArrayLiteral(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    values.push(self.generate_expression(element)?);
+                }
+                // An empty literal's element type doesn't matter
+                let element_type = match values.first() {
+                    Some(value) => unsafe { LLVMTypeOf(*value) },
+                    None => unsafe { LLVMInt64TypeInContext(self.context) },
+                };
+                let mut aggregate = unsafe { LLVMGetUndef(LLVMArrayType(element_type, values.len() as u32)) };
+                for (index, value) in values.into_iter().enumerate() {
+                    aggregate = unsafe {
+                        LLVMBuildInsertValue(self.builder, aggregate, value, index as u32, CString::new("array").unwrap().as_ptr())
+                    };
+                }
+                Ok(aggregate)
+            },
+            ExpressionKind::Index { array, index, array_type: Some(Type::String) } => {
+                let ExpressionKind::Range { start, end } = &index.kind else {
+                    return Err(Diagnostic::error("Strings can only be indexed with a range", index.span).with_code("E0301").into());
+                };
+                let string = self.generate_expression(array)?;
+                let start = self.generate_expression(start)?;
+                let end = self.generate_expression(end)?;
+                Ok(self.build_string_slice(string, start, end, expr.span))
+            },
+            ExpressionKind::Range { .. } => {
+                Err(Diagnostic::error("Ranges can only be used to slice a string", expr.span).with_code("E0301").into())
+            },
+            ExpressionKind::Index { .. } => {
+                let (pointer, ty) = self.place_pointer(expr)?;
+                unsafe { Ok(LLVMBuildLoad2(self.builder, ty, pointer, CString::new("element").unwrap().as_ptr())) }
+            },
+            ExpressionKind::Slice { operand, array_type } => {
+                match array_type.as_ref().map(|ty| ty.substitute(&self.type_arguments)) {
+                    Some(Type::Array(element, len)) => {
+                        let (pointer, array_type) = self.array_pointer(operand)?;
+                        unsafe {
+                            let zero = LLVMConstInt(LLVMInt64TypeInContext(self.context), 0, 0);
+                            let mut indices = [zero, zero];
+                            let data = LLVMBuildGEP2(
+                                self.builder,
+                                array_type,
+                                pointer,
+                                indices.as_mut_ptr(),
+                                2,
+                                CString::new("data").unwrap().as_ptr()
+                            );
+                            let len = LLVMConstInt(LLVMInt64TypeInContext(self.context), len as u64, 0);
+                            let slice_type = self.type_to_llvm(&Type::Slice(element));
+                            let slice = LLVMBuildInsertValue(self.builder, LLVMGetUndef(slice_type), data, 0, CString::new("slice").unwrap().as_ptr());
+                            Ok(LLVMBuildInsertValue(self.builder, slice, len, 1, CString::new("slice").unwrap().as_ptr()))
+                        }
+                    }
+                    // Slicing a slice views the same elements
+                    Some(Type::Slice(_)) => self.generate_expression(operand),
+                    _ => Err(Diagnostic::error("Cannot take a slice of this expression", expr.span).with_code("E0301").into()),
+                }
+            },
+            ExpressionKind::Cast { operand, target, operand_type } => {
+                let value = self.generate_expression(operand)?;
+                let source = operand_type.as_ref().map(|ty| ty.substitute(&self.type_arguments)).unwrap_or(Type::Int);
+                Ok(self.build_cast(value, &source, target))
+            },
+            ExpressionKind::Borrow { operand, .. } => Ok(self.place_pointer(operand)?.0),
+            ExpressionKind::Deref { .. } => {
+                let (pointer, ty) = self.place_pointer(expr)?;
+                unsafe { Ok(LLVMBuildLoad2(self.builder, ty, pointer, CString::new("deref").unwrap().as_ptr())) }
+            },
+            ExpressionKind::Move(operand) => {
+                let value = self.generate_expression(operand)?;
+                // What's moved out is no longer this binding's to drop
+                if let ExpressionKind::Identifier(name) = &operand.kind {
+                    let slot = self.named_values.get(name).copied();
+                    let binding = self.owned.iter_mut().flatten().rev().find(|binding| Some(binding.slot) == slot);
+                    if let Some(binding) = binding {
+                        binding.moved = true;
+                    }
                 }
+                Ok(value)
             },
-            _ => Err("Unsupported expression".to_string()),
         }
     }
 
-    fn type_to_llvm(&self, ty: &Type) -> LLVMTypeRef {
+    // Combine `l` and `r`, both of type `operand_type`, with a binary
+    // operator, for an expression or a compound assignment
+    fn build_binary(
+        &mut self,
+        operator: BinaryOp,
+        l: LLVMValueRef,
+        r: LLVMValueRef,
+        operand_type: &Type,
+        span: Span,
+    ) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let operand_type = operand_type.substitute(&self.type_arguments);
+        let float = operand_type.is_float();
+        // Division, remainder, comparison and `>>` depend on the signedness
+        let signed = operand_type.integer_kind().is_some_and(|(signed, _)| signed);
+        let (build, name): (BuildBinary, &str) = match operator {
+            // Bools can only be compared for equality
+            BinaryOp::Equal | BinaryOp::NotEqual if operand_type == Type::Bool => {
+                return Ok(self.build_comparison(operator, false, false, l, r));
+            }
+            _ if operand_type == Type::String => return Ok(self.build_string_binary(operator, l, r)),
+            _ if !operand_type.is_numeric() && !matches!(operand_type, Type::Var(_)) => {
+                return Err(Diagnostic::error(
+                    format!("Unsupported binary operator `{}` on {}", operator, operand_type),
+                    span,
+                )
+                .with_code("E0301").into());
+            }
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply if !float && self.overflow_checks => {
+                return Ok(self.build_checked_arithmetic(operator, signed, l, r, span));
+            }
+            BinaryOp::Divide | BinaryOp::Modulo if !float => {
+                return Ok(self.build_checked_division(operator, signed, l, r, span));
+            }
+            BinaryOp::Add if float => (LLVMBuildFAdd, "addtmp"),
+            BinaryOp::Add => (LLVMBuildAdd, "addtmp"),
+            BinaryOp::Subtract if float => (LLVMBuildFSub, "subtmp"),
+            BinaryOp::Subtract => (LLVMBuildSub, "subtmp"),
+            BinaryOp::Multiply if float => (LLVMBuildFMul, "multmp"),
+            BinaryOp::Multiply => (LLVMBuildMul, "multmp"),
+            BinaryOp::Divide => (LLVMBuildFDiv, "divtmp"),
+            BinaryOp::Modulo => (LLVMBuildFRem, "remtmp"),
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => return Ok(self.build_shift(operator, signed, l, r, span)),
+            _ => return Ok(self.build_comparison(operator, float, signed, l, r)),
+        };
+        unsafe { Ok(build(self.builder, l, r, CString::new(name).unwrap().as_ptr())) }
+    }
+
+    // `&&` and `||` only evaluate `right` when `left` doesn't already decide
+    // the result, which is then the constant the short-circuit edge brings
+    fn generate_logical(
+        &mut self,
+        operator: BinaryOp,
+        left: &Expression,
+        right: &Expression,
+    ) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let l = self.generate_expression(left)?;
+        let rhs_block = self.append_block("logical.rhs");
+        let merge = self.append_block("logical.end");
+
         unsafe {
-            match ty {
-                Type::Int => LLVMInt64TypeInContext(self.context),
-                Type::Float => LLVMDoubleTypeInContext(self.context),
-                Type::Bool => LLVMInt1TypeInContext(self.context),
-                Type::String => LLVMPointerType(LLVMInt8TypeInContext(self.context), 0),
-                Type::Array(inner) => {
-                    LLVMArrayType(self.type_to_llvm(inner), 0)
-                },
-                _ => LLVMVoidTypeInContext(self.context),
+            let left_block = LLVMGetInsertBlock(self.builder);
+            let short_circuit = matches!(operator, BinaryOp::Or);
+            if short_circuit {
+                LLVMBuildCondBr(self.builder, l, merge, rhs_block);
+            } else {
+                LLVMBuildCondBr(self.builder, l, rhs_block, merge);
             }
+
+            LLVMPositionBuilderAtEnd(self.builder, rhs_block);
+            let r = self.generate_expression(right)?;
+            let right_block = LLVMGetInsertBlock(self.builder);
+            LLVMBuildBr(self.builder, merge);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge);
+            let bool_type = LLVMInt1TypeInContext(self.context);
+            let phi = LLVMBuildPhi(self.builder, bool_type, CString::new("logical").unwrap().as_ptr());
+            let mut incoming_values = [LLVMConstInt(bool_type, short_circuit as u64, 0), r];
+            let mut incoming_blocks = [left_block, right_block];
+            LLVMAddIncoming(phi, incoming_values.as_mut_ptr(), incoming_blocks.as_mut_ptr(), 2);
+            Ok(phi)
         }
     }
-}
 
-impl Drop for CodeGenerator {
-    fn drop(&mut self) {
+    // `l operator r` for `+`, `-` or `*` on integers, trapping instead of
+    // wrapping when the result doesn't fit
+    fn build_checked_arithmetic(
+        &mut self,
+        operator: BinaryOp,
+        signed: bool,
+        l: LLVMValueRef,
+        r: LLVMValueRef,
+        span: Span,
+    ) -> LLVMValueRef {
+        let intrinsic = match (operator, signed) {
+            (BinaryOp::Add, true) => "llvm.sadd.with.overflow",
+            (BinaryOp::Add, false) => "llvm.uadd.with.overflow",
+            (BinaryOp::Subtract, true) => "llvm.ssub.with.overflow",
+            (BinaryOp::Subtract, false) => "llvm.usub.with.overflow",
+            (_, true) => "llvm.smul.with.overflow",
+            (_, false) => "llvm.umul.with.overflow",
+        };
         unsafe {
-            LLVMDisposeDIBuilder(self.di_builder);
-            LLVMDisposeBuilder(self.builder);
-            LLVMDisposeModule(self.module);
-            LLVMContextDispose(self.context);
+            let function = self.intrinsic(intrinsic, &[LLVMTypeOf(l)]);
+            let mut args = [l, r];
+            let result = LLVMBuildCall2(
+                self.builder,
+                LLVMGlobalGetValueType(function),
+                function,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                CString::new("checked").unwrap().as_ptr()
+            );
+            let overflow = LLVMBuildExtractValue(self.builder, result, 1, CString::new("overflow").unwrap().as_ptr());
+            let no_overflow = LLVMBuildNot(self.builder, overflow, CString::new("nooverflow").unwrap().as_ptr());
+            self.trap_unless(no_overflow, "swiftpp_arithmetic_overflow", &[], span);
+            LLVMBuildExtractValue(self.builder, result, 0, CString::new("result").unwrap().as_ptr())
+        }
+    }
+
+    // `l / r` or `l % r` on integers. Dividing by zero always traps. On signed
+    // types `MIN / -1` and `MIN % -1` overflow: with overflow checks that
+    // traps; without, they wrap to `MIN` and `0` like the other operators.
+    fn build_checked_division(
+        &mut self,
+        operator: BinaryOp,
+        signed: bool,
+        l: LLVMValueRef,
+        mut r: LLVMValueRef,
+        span: Span,
+    ) -> LLVMValueRef {
+        unsafe {
+            let ty = LLVMTypeOf(l);
+            let nonzero = LLVMBuildICmp(
+                self.builder,
+                LLVMIntPredicate::LLVMIntNE,
+                r,
+                LLVMConstNull(ty),
+                CString::new("nonzero").unwrap().as_ptr()
+            );
+            self.trap_unless(nonzero, "swiftpp_division_by_zero", &[], span);
+            if signed {
+                let width = LLVMGetIntTypeWidth(ty) as u64;
+                let min = LLVMConstShl(LLVMConstInt(ty, 1, 0), LLVMConstInt(ty, width - 1, 0));
+                let is_min = LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntEQ, l, min, CString::new("ismin").unwrap().as_ptr());
+                let is_minus_one = LLVMBuildICmp(
+                    self.builder,
+                    LLVMIntPredicate::LLVMIntEQ,
+                    r,
+                    LLVMConstAllOnes(ty),
+                    CString::new("isminusone").unwrap().as_ptr()
+                );
+                let overflow = LLVMBuildAnd(self.builder, is_min, is_minus_one, CString::new("overflow").unwrap().as_ptr());
+                if self.overflow_checks {
+                    let no_overflow = LLVMBuildNot(self.builder, overflow, CString::new("nooverflow").unwrap().as_ptr());
+                    self.trap_unless(no_overflow, "swiftpp_arithmetic_overflow", &[], span);
+                } else {
+                    // Dividing by 1 instead gives the wrapped results
+                    r = LLVMBuildSelect(self.builder, overflow, LLVMConstInt(ty, 1, 0), r, CString::new("divisor").unwrap().as_ptr());
+                }
+            }
+            let (build, name): (BuildBinary, &str) = match (operator, signed) {
+                (BinaryOp::Divide, true) => (LLVMBuildSDiv, "divtmp"),
+                (BinaryOp::Divide, false) => (LLVMBuildUDiv, "divtmp"),
+                (_, true) => (LLVMBuildSRem, "remtmp"),
+                (_, false) => (LLVMBuildURem, "remtmp"),
+            };
+            build(self.builder, l, r, CString::new(name).unwrap().as_ptr())
+        }
+    }
+
+    // Shifting by the width of the type or more overflows. With overflow
+    // checks that traps; without, the amount wraps around the width, so
+    // `x << 33` on an `i32` is `x << 1`.
+    fn build_shift(&mut self, operator: BinaryOp, signed: bool, l: LLVMValueRef, r: LLVMValueRef, span: Span) -> LLVMValueRef {
+        unsafe {
+            let ty = LLVMTypeOf(l);
+            let width = LLVMGetIntTypeWidth(ty) as u64;
+            if self.overflow_checks {
+                let in_range = LLVMBuildICmp(
+                    self.builder,
+                    LLVMIntPredicate::LLVMIntULT,
+                    r,
+                    LLVMConstInt(ty, width, 0),
+                    CString::new("inrange").unwrap().as_ptr()
+                );
+                self.trap_unless(in_range, "swiftpp_arithmetic_overflow", &[], span);
+            }
+            let amount = LLVMBuildAnd(self.builder, r, LLVMConstInt(ty, width - 1, 0), CString::new("amount").unwrap().as_ptr());
+            let (build, name): (BuildBinary, &str) = match operator {
+                BinaryOp::ShiftLeft => (LLVMBuildShl, "shltmp"),
+                _ if signed => (LLVMBuildAShr, "shrtmp"),
+                _ => (LLVMBuildLShr, "shrtmp"),
+            };
+            build(self.builder, l, amount, CString::new(name).unwrap().as_ptr())
+        }
+    }
+
+    // Convert `value` from `source` to `target` the way `as` does: integers
+    // are truncated or extended by the source's signedness, and floats become
+    // integers rounding toward zero, saturating at the target's limits
+    fn build_cast(&mut self, value: LLVMValueRef, source: &Type, target: &Type) -> LLVMValueRef {
+        let target_type = self.type_to_llvm(target);
+        let source_signed = source.integer_kind().is_some_and(|(signed, _)| signed);
+        let target_signed = target.integer_kind().is_some_and(|(signed, _)| signed);
+        let name = CString::new("cast").unwrap();
+        unsafe {
+            match (source.is_float(), target.is_float()) {
+                (true, true) => LLVMBuildFPCast(self.builder, value, target_type, name.as_ptr()),
+                (false, true) if source_signed => LLVMBuildSIToFP(self.builder, value, target_type, name.as_ptr()),
+                (false, true) => LLVMBuildUIToFP(self.builder, value, target_type, name.as_ptr()),
+                (true, false) => {
+                    let intrinsic = if target_signed { "llvm.fptosi.sat" } else { "llvm.fptoui.sat" };
+                    let function = self.intrinsic(intrinsic, &[target_type, LLVMTypeOf(value)]);
+                    let mut args = [value];
+                    LLVMBuildCall2(
+                        self.builder,
+                        LLVMGlobalGetValueType(function),
+                        function,
+                        args.as_mut_ptr(),
+                        args.len() as u32,
+                        name.as_ptr()
+                    )
+                }
+                // A `bool` is unsigned, so `true as i32` is 1
+                (false, false) => LLVMBuildIntCast2(self.builder, value, target_type, source_signed as i32, name.as_ptr()),
+            }
+        }
+    }
+
+    // The declaration of the overloaded LLVM intrinsic `name` for `types`
+    fn intrinsic(&mut self, name: &str, types: &[LLVMTypeRef]) -> LLVMValueRef {
+        let mut types = types.to_vec();
+        unsafe {
+            let id = LLVMLookupIntrinsicID(name.as_ptr() as *const _, name.len());
+            LLVMGetIntrinsicDeclaration(self.module, id, types.as_mut_ptr(), types.len())
         }
     }
+
+    // Compare `l` and `r` with one of the comparison operators, giving an `i1`
+    fn build_comparison(&mut self, operator: BinaryOp, float: bool, signed: bool, l: LLVMValueRef, r: LLVMValueRef) -> LLVMValueRef {
+        let name = CString::new("cmptmp").unwrap();
+        unsafe {
+            if float {
+                // Ordered, so every comparison with NaN is false except `!=`
+                let predicate = match operator {
+                    BinaryOp::Equal => LLVMRealPredicate::LLVMRealOEQ,
+                    BinaryOp::NotEqual => LLVMRealPredicate::LLVMRealUNE,
+                    BinaryOp::Greater => LLVMRealPredicate::LLVMRealOGT,
+                    BinaryOp::Less => LLVMRealPredicate::LLVMRealOLT,
+                    BinaryOp::GreaterEqual => LLVMRealPredicate::LLVMRealOGE,
+                    _ => LLVMRealPredicate::LLVMRealOLE,
+                };
+                LLVMBuildFCmp(self.builder, predicate, l, r, name.as_ptr())
+            } else {
+                let predicate = match operator {
+                    BinaryOp::Equal => LLVMIntPredicate::LLVMIntEQ,
+                    BinaryOp::NotEqual => LLVMIntPredicate::LLVMIntNE,
+                    _ => ordering_predicate(operator, signed),
+                };
+                LLVMBuildICmp(self.builder, predicate, l, r, name.as_ptr())
+            }
+        }
+    }
+
+    // A string literal: a pointer to its bytes, in a private constant shared
+    // by every literal with the same contents, and their length. It borrows
+    // the constant, so its capacity is 0.
+    fn string_constant(&mut self, value: &str) -> LLVMValueRef {
+        if let Some(&string) = self.strings.get(value) {
+            return string;
+        }
+        unsafe {
+            // Strings carry their length, so there's no NUL terminator
+            let initializer = LLVMConstStringInContext(self.context, value.as_ptr() as *const _, value.len() as u32, 1);
+            let array_type = LLVMTypeOf(initializer);
+            let global = LLVMAddGlobal(self.module, array_type, CString::new("str").unwrap().as_ptr());
+            LLVMSetInitializer(global, initializer);
+            LLVMSetGlobalConstant(global, 1);
+            LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+            LLVMSetUnnamedAddress(global, LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+            LLVMSetAlignment(global, 1);
+
+            let i64_type = LLVMInt64TypeInContext(self.context);
+            let zero = LLVMConstInt(i64_type, 0, 0);
+            let mut indices = [zero, zero];
+            let pointer = LLVMConstInBoundsGEP2(array_type, global, indices.as_mut_ptr(), indices.len() as u32);
+            let mut fields = [pointer, LLVMConstInt(i64_type, value.len() as u64, 0), zero];
+            let string = LLVMConstStructInContext(self.context, fields.as_mut_ptr(), fields.len() as u32, 0);
+            self.strings.insert(value.to_string(), string);
+            string
+        }
+    }
+
+    // The bytes and length of `string`, which is how runtime functions take it
+    fn string_parts(&mut self, string: LLVMValueRef) -> [LLVMValueRef; 2] {
+        unsafe {
+            [
+                LLVMBuildExtractValue(self.builder, string, 0, CString::new("bytes").unwrap().as_ptr()),
+                LLVMBuildExtractValue(self.builder, string, 1, CString::new("len").unwrap().as_ptr()),
+            ]
+        }
+    }
+
+    // Call the runtime function `name` with `args`, followed by a pointer to
+    // a stack slot it stores the string it makes in
+    fn build_string_call(&mut self, name: &str, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let string_type = self.type_to_llvm(&Type::String);
+        let slot = self.entry_alloca(string_type, "string");
+        let mut args = args.to_vec();
+        args.push(slot);
+        unsafe {
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function(name, LLVMVoidTypeInContext(self.context), &param_types);
+            self.build_call(function, &mut args);
+            LLVMBuildLoad2(self.builder, string_type, slot, CString::new("string").unwrap().as_ptr())
+        }
+    }
+
+    // Free the buffer of `string`, if it owns one
+    fn build_string_free(&mut self, string: LLVMValueRef) {
+        unsafe {
+            let bytes = LLVMBuildExtractValue(self.builder, string, 0, CString::new("bytes").unwrap().as_ptr());
+            let cap = LLVMBuildExtractValue(self.builder, string, 2, CString::new("cap").unwrap().as_ptr());
+            let mut args = [bytes, cap];
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function("swiftpp_string_free", LLVMVoidTypeInContext(self.context), &param_types);
+            self.build_call(function, &mut args);
+        }
+    }
+
+    // Free `value`, the string `expr` evaluated to, if `expr` made it and
+    // nothing else can refer to it: a join, which includes an interpolated
+    // string, or a `to_string` call
+    fn free_temporary(&mut self, expr: &Expression, value: LLVMValueRef) {
+        let temporary = match &expr.kind {
+            ExpressionKind::Binary { operator: BinaryOp::Add, operand_type: Some(Type::String), .. }
+            | ExpressionKind::Interpolation(_) => true,
+            ExpressionKind::Call { function, .. } => {
+                matches!(&function.kind, ExpressionKind::Identifier(name) if name == "to_string" && self.is_builtin(name))
+            }
+            _ => false,
+        };
+        if temporary {
+            self.build_string_free(value);
+        }
+    }
+
+    // `+` joins two strings into a new one; the comparisons compare their
+    // bytes in the runtime, which gives -1, 0 or 1 to compare with 0
+    fn build_string_binary(&mut self, operator: BinaryOp, l: LLVMValueRef, r: LLVMValueRef) -> LLVMValueRef {
+        let [left, left_len] = self.string_parts(l);
+        let [right, right_len] = self.string_parts(r);
+        let mut args = [left, left_len, right, right_len];
+        if let BinaryOp::Add = operator {
+            return self.build_string_call("swiftpp_string_concat", &args);
+        }
+        unsafe {
+            let i32_type = LLVMInt32TypeInContext(self.context);
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function("swiftpp_string_compare", i32_type, &param_types);
+            let ordering = self.build_call(function, &mut args);
+            self.build_comparison(operator, false, true, ordering, LLVMConstInt(i32_type, 0, 0))
+        }
+    }
+
+    // `string[start..end]`, which borrows the bytes of `string`. The runtime
+    // checks the range, reporting a bad one at `span`.
+    fn build_string_slice(&mut self, string: LLVMValueRef, start: LLVMValueRef, end: LLVMValueRef, span: Span) -> LLVMValueRef {
+        let [bytes, len] = self.string_parts(string);
+        let (line, column) = self.line_index.line_col(span.start);
+        unsafe {
+            let i32_type = LLVMInt32TypeInContext(self.context);
+            let mut args = [
+                bytes,
+                len,
+                start,
+                end,
+                LLVMConstInt(i32_type, line as u64, 0),
+                LLVMConstInt(i32_type, column as u64, 0),
+            ];
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function("swiftpp_string_slice", LLVMTypeOf(bytes), &param_types);
+            let slice_bytes = self.build_call(function, &mut args);
+            let slice_len = LLVMBuildSub(self.builder, end, start, CString::new("len").unwrap().as_ptr());
+
+            let string_type = self.type_to_llvm(&Type::String);
+            let name = CString::new("slice").unwrap();
+            let slice = LLVMBuildInsertValue(self.builder, LLVMGetUndef(string_type), slice_bytes, 0, name.as_ptr());
+            let slice = LLVMBuildInsertValue(self.builder, slice, slice_len, 1, name.as_ptr());
+            LLVMBuildInsertValue(self.builder, slice, LLVMConstInt(LLVMInt64TypeInContext(self.context), 0, 0), 2, name.as_ptr())
+        }
+    }
+
+    fn generate_variant(&mut self, path: &[String], arguments: &[Expression], span: Span) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let (enum_name, index) = self.variant_index(path, span)?;
+        let (llvm_type, payload_type) = {
+            let layout = &self.enums[&enum_name];
+            (layout.llvm_type, layout.variants[index].1)
+        };
+
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.generate_expression(argument)?);
+        }
+
+        unsafe {
+            let tag = LLVMConstInt(LLVMInt32TypeInContext(self.context), index as u64, 0);
+            if values.is_empty() {
+                let mut elements = [tag, LLVMGetUndef(LLVMStructGetTypeAtIndex(llvm_type, 1))];
+                return Ok(LLVMConstNamedStruct(llvm_type, elements.as_mut_ptr(), elements.len() as u32));
+            }
+
+            let mut payload = LLVMGetUndef(payload_type);
+            for (i, value) in values.into_iter().enumerate() {
+                payload = LLVMBuildInsertValue(self.builder, payload, value, i as u32, CString::new("").unwrap().as_ptr());
+            }
+
+            let slot = self.entry_alloca(llvm_type, &enum_name);
+            let tag_pointer = LLVMBuildStructGEP2(self.builder, llvm_type, slot, 0, CString::new("tag").unwrap().as_ptr());
+            LLVMBuildStore(self.builder, tag, tag_pointer);
+            let payload_pointer = self.payload_pointer(slot, llvm_type, payload_type);
+            LLVMBuildStore(self.builder, payload, payload_pointer);
+            Ok(LLVMBuildLoad2(self.builder, llvm_type, slot, CString::new(path.join("::")).unwrap().as_ptr()))
+        }
+    }
+
+    // The enum a `Enum::Variant` path names and the variant's position in it
+    fn variant_index(&self, path: &[String], span: Span) -> Result<(String, usize), Box<Diagnostic>> {
+        let found = match path {
+            [enum_name, variant] => self.enums.get(enum_name).and_then(|layout| {
+                let index = layout.variants.iter().position(|(name, _, _)| name == variant)?;
+                Some((enum_name.clone(), index))
+            }),
+            _ => None,
+        };
+        found.ok_or_else(|| {
+            Diagnostic::error(format!("Unknown enum variant: {}", path.join("::")), span).with_code("E0304").into()
+        })
+    }
+
+    // Arms are tested in order, but a `switch` on the tag (for enums) or the
+    // value (for integers and booleans) first jumps to a block that only
+    // tests the arms that can match it. Arms with no fixed value, such as
+    // ranges and wildcards, are tested from the switch's default block.
+    fn generate_match(&mut self, scrutinee: &Expression, scrutinee_type: &Type, arms: &[MatchArm]) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let value = self.generate_expression(scrutinee)?;
+
+        unsafe {
+            let value_type = LLVMTypeOf(value);
+            let discriminant = match LLVMGetTypeKind(value_type) {
+                LLVMTypeKind::LLVMIntegerTypeKind => Some(value),
+                LLVMTypeKind::LLVMStructTypeKind if self.is_enum_type(value_type) => Some(LLVMBuildExtractValue(
+                    self.builder,
+                    value,
+                    0,
+                    CString::new("tag").unwrap().as_ptr(),
+                )),
+                _ => None,
+            };
+
+            let mut keys = Vec::new();
+            for arm in arms {
+                keys.push(self.pattern_key(&arm.pattern)?);
+            }
+
+            // The body block of each arm that some path reaches, with what its pattern binds
+            let mut bodies: Vec<Option<ArmBody>> = vec![None; arms.len()];
+            let default_block = self.append_block("match.default");
+            match discriminant {
+                Some(discriminant) if keys.iter().any(Option::is_some) => {
+                    let mut cases: Vec<i64> = Vec::new();
+                    for key in keys.iter().flatten() {
+                        if !cases.contains(key) {
+                            cases.push(*key);
+                        }
+                    }
+                    let switch = LLVMBuildSwitch(self.builder, discriminant, default_block, cases.len() as u32);
+                    for case in cases {
+                        let block = self.append_block("match.case");
+                        LLVMAddCase(switch, LLVMConstInt(LLVMTypeOf(discriminant), case as u64, 1), block);
+                        LLVMPositionBuilderAtEnd(self.builder, block);
+
+                        // Every arm that can match this case, with its own value already known to match
+                        let candidates: Vec<(usize, bool)> = arms
+                            .iter()
+                            .zip(&keys)
+                            .enumerate()
+                            .filter(|(_, (arm, key))| match key {
+                                Some(key) => *key == case,
+                                None => pattern_contains(&arm.pattern, case),
+                            })
+                            .map(|(i, _)| (i, true))
+                            .collect();
+
+                        // The arms for a variant share one load of its payload
+                        let payload = match LLVMGetTypeKind(value_type) {
+                            LLVMTypeKind::LLVMStructTypeKind => {
+                                let variant = candidates.iter().find_map(|(i, _)| match &arms[*i].pattern.kind {
+                                    PatternKind::Variant { path, fields } if !fields.is_empty() => Some(path),
+                                    _ => None,
+                                });
+                                match variant {
+                                    Some(path) => {
+                                        let (enum_name, index) = self.variant_index(path, scrutinee.span)?;
+                                        Some(self.variant_fields(value, &enum_name, index))
+                                    }
+                                    None => None,
+                                }
+                            }
+                            _ => None,
+                        };
+                        self.generate_arm_tests(value, scrutinee_type, payload.as_deref(), arms, &candidates, &mut bodies)?;
+                    }
+                }
+                _ => {
+                    LLVMBuildBr(self.builder, default_block);
+                }
+            }
+
+            LLVMPositionBuilderAtEnd(self.builder, default_block);
+            let candidates: Vec<_> = keys
+                .iter()
+                .enumerate()
+                .filter(|(_, key)| key.is_none())
+                .map(|(i, _)| (i, false))
+                .collect();
+            self.generate_arm_tests(value, scrutinee_type, None, arms, &candidates, &mut bodies)?;
+
+            let merge = self.append_block("match.end");
+            let mut incoming_values = Vec::new();
+            let mut incoming_blocks = Vec::new();
+            for (arm, body) in arms.iter().zip(bodies) {
+                // Arms that an earlier arm shadows completely have no body
+                let Some((block, bindings)) = body else {
+                    continue;
+                };
+                LLVMPositionBuilderAtEnd(self.builder, block);
+                let outer = self.named_values.clone();
+                for (name, value) in bindings {
+                    self.bind_variable(&name, value);
+                }
+                let result = self.generate_expression(&arm.body);
+                self.named_values = outer;
+                incoming_values.push(result?);
+                incoming_blocks.push(LLVMGetInsertBlock(self.builder));
+                LLVMBuildBr(self.builder, merge);
+            }
+
+            LLVMPositionBuilderAtEnd(self.builder, merge);
+            let Some(&first) = incoming_values.first() else {
+                return Ok(std::ptr::null_mut());
+            };
+            let result_type = LLVMTypeOf(first);
+            if LLVMGetTypeKind(result_type) == LLVMTypeKind::LLVMVoidTypeKind {
+                return Ok(first);
+            }
+            let phi = LLVMBuildPhi(self.builder, result_type, CString::new("match").unwrap().as_ptr());
+            LLVMAddIncoming(
+                phi,
+                incoming_values.as_mut_ptr(),
+                incoming_blocks.as_mut_ptr(),
+                incoming_values.len() as u32,
+            );
+            Ok(phi)
+        }
+    }
+
+    // The branches' values meet in a phi in the merge block
+    fn generate_if_expression(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Expression,
+        else_branch: &Expression,
+    ) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let condition = self.generate_expression(condition)?;
+        let then_block = self.append_block("if.then");
+        let else_block = self.append_block("if.else");
+        let merge = self.append_block("if.end");
+
+        unsafe {
+            LLVMBuildCondBr(self.builder, condition, then_block, else_block);
+            let before = self.moved_flags();
+            let mut incoming_values = Vec::new();
+            let mut incoming_blocks = Vec::new();
+            let mut after = Vec::new();
+            for (block, branch) in [(then_block, then_branch), (else_block, else_branch)] {
+                self.set_moved_flags(&before);
+                LLVMPositionBuilderAtEnd(self.builder, block);
+                incoming_values.push(self.generate_expression(branch)?);
+                incoming_blocks.push(LLVMGetInsertBlock(self.builder));
+                LLVMBuildBr(self.builder, merge);
+                after = self.moved_flags();
+            }
+            // Both branches moved the same bindings, or the borrow checker would object
+            self.set_moved_flags(&after);
+
+            LLVMPositionBuilderAtEnd(self.builder, merge);
+            let result_type = LLVMTypeOf(incoming_values[0]);
+            if LLVMGetTypeKind(result_type) == LLVMTypeKind::LLVMVoidTypeKind {
+                return Ok(incoming_values[0]);
+            }
+            let phi = LLVMBuildPhi(self.builder, result_type, CString::new("if").unwrap().as_ptr());
+            LLVMAddIncoming(phi, incoming_values.as_mut_ptr(), incoming_blocks.as_mut_ptr(), incoming_values.len() as u32);
+            Ok(phi)
+        }
+    }
+
+    // Test the `candidates` arms in order, branching to the body of the first
+    // one that matches. `true` marks an arm whose top-level pattern is already
+    // known to match, so only its fields are tested, from `payload` if given.
+    fn generate_arm_tests(
+        &mut self,
+        value: LLVMValueRef,
+        ty: &Type,
+        payload: Option<&[LLVMValueRef]>,
+        arms: &[MatchArm],
+        candidates: &[(usize, bool)],
+        bodies: &mut [Option<ArmBody>],
+    ) -> Result<(), Box<Diagnostic>> {
+        for &(i, matched) in candidates {
+            let next = self.append_block("match.next");
+            let mut bindings = Vec::new();
+            let pattern = &arms[i].pattern;
+            match (payload, &pattern.kind) {
+                (Some(payload), PatternKind::Variant { path, fields }) if matched => {
+                    let field_types = self.variant_field_types(path, pattern.span)?;
+                    for ((field, field_value), field_type) in fields.iter().zip(payload).zip(&field_types) {
+                        self.generate_pattern_test(field, *field_value, field_type, false, next, &mut bindings)?;
+                    }
+                }
+                _ => self.generate_pattern_test(pattern, value, ty, matched, next, &mut bindings)?,
+            }
+
+            // A body reached from several switch cases binds the same values
+            // on each path: only the scrutinee itself
+            let body = match &bodies[i] {
+                Some((block, _)) => *block,
+                None => {
+                    let block = self.append_block("match.arm");
+                    bodies[i] = Some((block, bindings));
+                    block
+                }
+            };
+            unsafe {
+                LLVMBuildBr(self.builder, body);
+                // Nothing failed over to `next`, so this arm always matches
+                // and the arms after it can't
+                if LLVMGetFirstUse(LLVMBasicBlockAsValue(next)).is_null() {
+                    LLVMDeleteBasicBlock(next);
+                    return Ok(());
+                }
+                LLVMPositionBuilderAtEnd(self.builder, next);
+            }
+        }
+
+        // The analyzer checked that the arms cover every value
+        unsafe {
+            LLVMBuildUnreachable(self.builder);
+        }
+        Ok(())
+    }
+
+    // Branch to `fail` unless `value`, of type `ty`, matches `pattern`,
+    // collecting the names the pattern binds. With `matched`, the pattern's
+    // own value (but not those of its fields) is known to match.
+    fn generate_pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        value: LLVMValueRef,
+        ty: &Type,
+        matched: bool,
+        fail: LLVMBasicBlockRef,
+        bindings: &mut Vec<(String, LLVMValueRef)>,
+    ) -> Result<(), Box<Diagnostic>> {
+        unsafe {
+            let value_type = LLVMTypeOf(value);
+            match &pattern.kind {
+                PatternKind::Wildcard => {}
+                PatternKind::Binding(name) => bindings.push((name.clone(), value)),
+                PatternKind::Integer(expected) if !matched => {
+                    let expected = LLVMConstInt(value_type, *expected as u64, 1);
+                    self.branch_unless(LLVMIntPredicate::LLVMIntEQ, value, expected, fail);
+                }
+                PatternKind::Boolean(expected) if !matched => {
+                    let expected = LLVMConstInt(value_type, *expected as u64, 0);
+                    self.branch_unless(LLVMIntPredicate::LLVMIntEQ, value, expected, fail);
+                }
+                PatternKind::Range { start, end, inclusive } if !matched => {
+                    let signed = !matches!(ty.integer_kind(), Some((false, _)));
+                    let start = LLVMConstInt(value_type, *start as u64, 1);
+                    self.branch_unless(ordering_predicate(BinaryOp::GreaterEqual, signed), value, start, fail);
+                    let end_operator = if *inclusive { BinaryOp::LessEqual } else { BinaryOp::Less };
+                    let end = LLVMConstInt(value_type, *end as u64, 1);
+                    self.branch_unless(ordering_predicate(end_operator, signed), value, end, fail);
+                }
+                PatternKind::Variant { path, fields } => {
+                    let (enum_name, index) = self.variant_index(path, pattern.span)?;
+                    if !matched {
+                        let tag = LLVMBuildExtractValue(self.builder, value, 0, CString::new("tag").unwrap().as_ptr());
+                        let expected = LLVMConstInt(LLVMTypeOf(tag), index as u64, 0);
+                        self.branch_unless(LLVMIntPredicate::LLVMIntEQ, tag, expected, fail);
+                    }
+                    if !fields.is_empty() {
+                        let field_values = self.variant_fields(value, &enum_name, index);
+                        let field_types = self.enums[&enum_name].variants[index].2.clone();
+                        for ((field, field_value), field_type) in fields.iter().zip(field_values).zip(&field_types) {
+                            self.generate_pattern_test(field, field_value, field_type, false, fail, bindings)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Continue in a new block if `left predicate right` holds, else go to `fail`
+    fn branch_unless(&mut self, predicate: LLVMIntPredicate, left: LLVMValueRef, right: LLVMValueRef, fail: LLVMBasicBlockRef) {
+        unsafe {
+            let condition = LLVMBuildICmp(self.builder, predicate, left, right, CString::new("test").unwrap().as_ptr());
+            let pass = self.append_block("match.test");
+            LLVMBuildCondBr(self.builder, condition, pass, fail);
+            LLVMPositionBuilderAtEnd(self.builder, pass);
+        }
+    }
+
+    // The declared types of the payload fields of the variant `path` names
+    fn variant_field_types(&self, path: &[String], span: Span) -> Result<Vec<Type>, Box<Diagnostic>> {
+        let (enum_name, index) = self.variant_index(path, span)?;
+        Ok(self.enums[&enum_name].variants[index].2.clone())
+    }
+
+    // The payload of `value`, an enum value of variant `index`, one value per field
+    fn variant_fields(&mut self, value: LLVMValueRef, enum_name: &str, index: usize) -> Vec<LLVMValueRef> {
+        let layout = &self.enums[enum_name];
+        let (llvm_type, (variant, payload_type, _)) = (layout.llvm_type, layout.variants[index].clone());
+        unsafe {
+            let slot = self.entry_alloca(llvm_type, enum_name);
+            LLVMBuildStore(self.builder, value, slot);
+            let payload_pointer = self.payload_pointer(slot, llvm_type, payload_type);
+            let payload = LLVMBuildLoad2(
+                self.builder,
+                payload_type,
+                payload_pointer,
+                CString::new(variant).unwrap().as_ptr(),
+            );
+            (0..LLVMCountStructElementTypes(payload_type))
+                .map(|i| LLVMBuildExtractValue(self.builder, payload, i, CString::new("").unwrap().as_ptr()))
+                .collect()
+        }
+    }
+
+    // The payload storage of the enum at `slot`, as a pointer to one variant's payload struct
+    fn payload_pointer(&mut self, slot: LLVMValueRef, llvm_type: LLVMTypeRef, payload_type: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            let storage = LLVMBuildStructGEP2(self.builder, llvm_type, slot, 1, CString::new("payload").unwrap().as_ptr());
+            LLVMBuildBitCast(
+                self.builder,
+                storage,
+                LLVMPointerType(payload_type, 0),
+                CString::new("fields").unwrap().as_ptr(),
+            )
+        }
+    }
+
+    // The value a switch on the scrutinee jumps on for `pattern`, if it has one
+    fn pattern_key(&self, pattern: &Pattern) -> Result<Option<i64>, Box<Diagnostic>> {
+        Ok(match &pattern.kind {
+            PatternKind::Integer(value) => Some(*value),
+            PatternKind::Boolean(value) => Some(*value as i64),
+            PatternKind::Variant { path, .. } => Some(self.variant_index(path, pattern.span)?.1 as i64),
+            _ => None,
+        })
+    }
+
+    // Whether a call to `name` goes to a builtin rather than to one of the
+    // program's functions
+    fn is_builtin(&self, name: &str) -> bool {
+        let symbol = CString::new(symbol_name(name)).unwrap();
+        !self.generic_functions.contains_key(name) && unsafe { LLVMGetNamedFunction(self.module, symbol.as_ptr()).is_null() }
+    }
+
+    fn is_enum_type(&self, ty: LLVMTypeRef) -> bool {
+        self.enums.values().any(|layout| layout.llvm_type == ty)
+    }
+
+    fn append_block(&mut self, name: &str) -> LLVMBasicBlockRef {
+        unsafe {
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            LLVMAppendBasicBlockInContext(self.context, function, CString::new(name).unwrap().as_ptr())
+        }
+    }
+
+    // Stack slots go at the start of the entry block, where they are
+    // allocated once per call and mem2reg can promote them to registers
+    fn entry_alloca(&mut self, ty: LLVMTypeRef, name: &str) -> LLVMValueRef {
+        unsafe {
+            let function = LLVMGetBasicBlockParent(LLVMGetInsertBlock(self.builder));
+            let entry = LLVMGetEntryBasicBlock(function);
+            let builder = LLVMCreateBuilderInContext(self.context);
+            let first = LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(builder, first);
+            }
+            let slot = LLVMBuildAlloca(builder, ty, CString::new(name).unwrap().as_ptr());
+            LLVMDisposeBuilder(builder);
+            slot
+        }
+    }
+
+    // Store `value` to the place `target`, combining it with what's there
+    // for a compound assignment, whose operator comes with the type of the
+    // target. Assigning a whole owned binding drops its old value, unless
+    // that was moved out.
+    fn assign_place(
+        &mut self,
+        target: &Expression,
+        operator: Option<(BinaryOp, Type)>,
+        value: LLVMValueRef,
+        span: Span,
+    ) -> Result<(), Box<Diagnostic>> {
+        let (pointer, ty) = self.place_pointer(target)?;
+        let value = match operator {
+            Some((operator, operand_type)) => {
+                let current = unsafe { LLVMBuildLoad2(self.builder, ty, pointer, CString::new("current").unwrap().as_ptr()) };
+                self.build_binary(operator, current, value, &operand_type, span)?
+            }
+            None => value,
+        };
+
+        let binding = self.owned.iter_mut().flatten().rev().find(|binding| binding.slot == pointer);
+        if let Some(binding) = binding {
+            let (moved, ty) = (std::mem::replace(&mut binding.moved, false), binding.ty.clone());
+            if !moved && self.needs_drop(&ty.substitute(&self.type_arguments)) {
+                let old = unsafe { LLVMBuildLoad2(self.builder, LLVMGetAllocatedType(pointer), pointer, CString::new("dropped").unwrap().as_ptr()) };
+                self.drop_value(old, &ty);
+            }
+        }
+        unsafe {
+            LLVMBuildStore(self.builder, value, pointer);
+        }
+        Ok(())
+    }
+
+    // A pointer to the place `expr` names, with the type of what it points to
+    fn place_pointer(&mut self, expr: &Expression) -> Result<(LLVMValueRef, LLVMTypeRef), Box<Diagnostic>> {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => {
+                let slot = self.named_values.get(name).copied().ok_or_else(|| {
+                    Diagnostic::error(format!("Unknown variable: {}", name), expr.span).with_code("E0304")
+                })?;
+                Ok((slot, unsafe { LLVMGetAllocatedType(slot) }))
+            }
+            ExpressionKind::FieldAccess { object, field } => {
+                let (pointer, ty) = self.place_pointer(object)?;
+                let index = self.struct_field_index(ty, field, expr.span)?;
+                unsafe {
+                    let field_pointer =
+                        LLVMBuildStructGEP2(self.builder, ty, pointer, index, CString::new(field.as_str()).unwrap().as_ptr());
+                    Ok((field_pointer, LLVMStructGetTypeAtIndex(ty, index)))
+                }
+            }
+            ExpressionKind::Deref { operand, pointee: Some(pointee) } => {
+                let pointer = self.generate_expression(operand)?;
+                Ok((pointer, self.type_to_llvm(pointee)))
+            }
+            ExpressionKind::Index { array, index, array_type } => {
+                let array_type = array_type.as_ref().map(|ty| ty.substitute(&self.type_arguments));
+                let (base, element_type, mut indices) = match array_type {
+                    Some(Type::Array(_, len)) => {
+                        let (pointer, ty) = self.array_pointer(array)?;
+                        let index = self.generate_expression(index)?;
+                        let len = unsafe { LLVMConstInt(LLVMInt64TypeInContext(self.context), len as u64, 0) };
+                        self.check_bounds(index, len, expr.span);
+                        let zero = unsafe { LLVMConstInt(LLVMInt64TypeInContext(self.context), 0, 0) };
+                        (pointer, ty, vec![zero, index])
+                    }
+                    Some(Type::Slice(element)) => {
+                        let slice = self.generate_expression(array)?;
+                        let index = self.generate_expression(index)?;
+                        let (data, len) = unsafe {
+                            (
+                                LLVMBuildExtractValue(self.builder, slice, 0, CString::new("data").unwrap().as_ptr()),
+                                LLVMBuildExtractValue(self.builder, slice, 1, CString::new("len").unwrap().as_ptr()),
+                            )
+                        };
+                        self.check_bounds(index, len, expr.span);
+                        (data, self.type_to_llvm(&element), vec![index])
+                    }
+                    _ => return Err(Diagnostic::error("Cannot index into this expression", expr.span).with_code("E0301").into()),
+                };
+                unsafe {
+                    let pointer = LLVMBuildGEP2(
+                        self.builder,
+                        element_type,
+                        base,
+                        indices.as_mut_ptr(),
+                        indices.len() as u32,
+                        CString::new("element").unwrap().as_ptr()
+                    );
+                    let element_type = match indices.len() {
+                        2 => LLVMGetElementType(element_type),
+                        _ => element_type,
+                    };
+                    Ok((pointer, element_type))
+                }
+            }
+            _ => Err(Diagnostic::error("Cannot take the address of this expression", expr.span).with_code("E0301").into()),
+        }
+    }
+
+    // A pointer to the array `expr`, spilling it to the stack when it isn't
+    // a place, such as the array returned by a call
+    fn array_pointer(&mut self, expr: &Expression) -> Result<(LLVMValueRef, LLVMTypeRef), Box<Diagnostic>> {
+        if is_place(expr) {
+            return self.place_pointer(expr);
+        }
+        let value = self.generate_expression(expr)?;
+        unsafe {
+            let ty = LLVMTypeOf(value);
+            let slot = self.entry_alloca(ty, "array");
+            LLVMBuildStore(self.builder, value, slot);
+            Ok((slot, ty))
+        }
+    }
+
+    // Trap unless `index` is less than `len`. Compared unsigned, a negative
+    // index is out of bounds too.
+    fn check_bounds(&mut self, index: LLVMValueRef, len: LLVMValueRef, span: Span) {
+        if !self.bounds_checks {
+            return;
+        }
+        unsafe {
+            let in_bounds = LLVMBuildICmp(
+                self.builder,
+                LLVMIntPredicate::LLVMIntULT,
+                index,
+                len,
+                CString::new("inbounds").unwrap().as_ptr()
+            );
+            self.trap_unless(in_bounds, "swiftpp_index_out_of_bounds", &[index, len], span);
+        }
+    }
+
+    // Continue if `condition` holds, or else call the runtime function `trap`
+    // with `args` and the line and column of `span`; it reports the error and
+    // exits
+    fn trap_unless(&mut self, condition: LLVMValueRef, trap: &str, args: &[LLVMValueRef], span: Span) {
+        let (line, column) = self.line_index.line_col(span.start);
+        unsafe {
+            let fail_block = self.append_block("trap");
+            let ok_block = self.append_block("ok");
+            LLVMBuildCondBr(self.builder, condition, ok_block, fail_block);
+
+            LLVMPositionBuilderAtEnd(self.builder, fail_block);
+            let i32_type = LLVMInt32TypeInContext(self.context);
+            let mut args = args.to_vec();
+            args.push(LLVMConstInt(i32_type, line as u64, 0));
+            args.push(LLVMConstInt(i32_type, column as u64, 0));
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function(trap, LLVMVoidTypeInContext(self.context), &param_types);
+            let no_return = CString::new("noreturn").unwrap();
+            let kind = LLVMGetEnumAttributeKindForName(no_return.as_ptr(), no_return.as_bytes().len());
+            LLVMAddAttributeAtIndex(function, LLVMAttributeFunctionIndex, LLVMCreateEnumAttribute(self.context, kind, 0));
+            self.build_call(function, &mut args);
+            LLVMBuildUnreachable(self.builder);
+
+            LLVMPositionBuilderAtEnd(self.builder, ok_block);
+        }
+    }
+
+    // Give the local `name` a stack slot holding `value`; mem2reg turns the
+    // slots that are never borrowed back into SSA values
+    fn bind_variable(&mut self, name: &str, value: LLVMValueRef) -> LLVMValueRef {
+        let slot = self.entry_alloca(unsafe { LLVMTypeOf(value) }, name);
+        unsafe {
+            LLVMBuildStore(self.builder, value, slot);
+        }
+        self.named_values.insert(name.to_string(), slot);
+        slot
+    }
+
+    // Bind `name` like `bind_variable`, and drop its value at the end of the
+    // block unless it's moved out first
+    fn bind_owned(&mut self, name: &str, ty: &Type, value: LLVMValueRef) -> LLVMValueRef {
+        let slot = self.bind_variable(name, value);
+        if let Some(scope) = self.owned.last_mut() {
+            scope.push(OwnedBinding { slot, ty: ty.clone(), moved: false });
+        }
+        slot
+    }
+
+    // Drop the owned bindings of the innermost blocks, starting with block
+    // `from`, that haven't been moved, in the reverse of declaration order
+    fn drop_owned(&mut self, from: usize) {
+        let bindings: Vec<_> = self.owned[from..]
+            .iter()
+            .flatten()
+            .filter(|binding| !binding.moved)
+            .map(|binding| (binding.slot, binding.ty.clone()))
+            .collect();
+        for (slot, ty) in bindings.into_iter().rev() {
+            if self.needs_drop(&ty.substitute(&self.type_arguments)) {
+                let value = unsafe {
+                    LLVMBuildLoad2(self.builder, LLVMGetAllocatedType(slot), slot, CString::new("dropped").unwrap().as_ptr())
+                };
+                self.drop_value(value, &ty);
+            }
+        }
+    }
+
+    // Whether each owned binding in scope has been moved, outermost first, so
+    // codegen can go back to this point to generate another path
+    fn moved_flags(&self) -> Vec<bool> {
+        self.owned.iter().flatten().map(|binding| binding.moved).collect()
+    }
+
+    // Restore `moved_flags` taken when the same outer blocks were open
+    fn set_moved_flags(&mut self, flags: &[bool]) {
+        for (binding, &moved) in self.owned.iter_mut().flatten().zip(flags) {
+            binding.moved = moved;
+        }
+    }
+
+    // Run the `Drop` impl of `value`'s type, then drop its fields. A string
+    // frees its buffer.
+    fn drop_value(&mut self, value: LLVMValueRef, ty: &Type) {
+        let ty = ty.substitute(&self.type_arguments);
+        if ty == Type::String {
+            self.build_string_free(value);
+            return;
+        }
+        if let Some(&function) = self.drops.get(&ty) {
+            self.build_call(function, &mut [value]);
+        }
+
+        let fields = self.structs.get(&ty.to_string()).map(|layout| layout.fields.clone()).unwrap_or_default();
+        for (index, (field, field_type)) in fields.iter().enumerate() {
+            if self.needs_drop(field_type) {
+                let field_value = unsafe {
+                    LLVMBuildExtractValue(self.builder, value, index as u32, CString::new(field.as_str()).unwrap().as_ptr())
+                };
+                self.drop_value(field_value, field_type);
+            }
+        }
+    }
+
+    // Whether dropping a `ty` runs any `Drop` impl or frees a string
+    fn needs_drop(&self, ty: &Type) -> bool {
+        *ty == Type::String
+            || self.drops.contains_key(ty)
+            || self
+                .structs
+                .get(&ty.to_string())
+                .is_some_and(|layout| layout.fields.iter().any(|(_, field_type)| self.needs_drop(field_type)))
+    }
+
+    // A call to one of the program's functions. Only a call that produces a
+    // value may be named.
+    fn build_call(&mut self, function: LLVMValueRef, args: &mut [LLVMValueRef]) -> LLVMValueRef {
+        unsafe {
+            let function_type = LLVMGlobalGetValueType(function);
+            let name = if LLVMGetReturnType(function_type) == LLVMVoidTypeInContext(self.context) { "" } else { "calltmp" };
+            LLVMBuildCall2(
+                self.builder,
+                function_type,
+                function,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                CString::new(name).unwrap().as_ptr()
+            )
+        }
+    }
+
+    fn is_terminated(&self) -> bool {
+        unsafe { !LLVMGetBasicBlockTerminator(LLVMGetInsertBlock(self.builder)).is_null() }
+    }
+
+    // Position of `field` in the struct value `aggregate`
+    fn field_index(&self, aggregate: LLVMValueRef, field: &str, span: Span) -> Result<u32, Box<Diagnostic>> {
+        self.struct_field_index(unsafe { LLVMTypeOf(aggregate) }, field, span)
+    }
+
+    // Position of `field` in values of the LLVM struct type `ty`
+    fn struct_field_index(&self, ty: LLVMTypeRef, field: &str, span: Span) -> Result<u32, Box<Diagnostic>> {
+        let layout = unsafe {
+            if LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMStructTypeKind {
+                let name = CStr::from_ptr(LLVMGetStructName(ty)).to_string_lossy();
+                self.structs.get(name.as_ref())
+            } else {
+                None
+            }
+        };
+        layout
+            .and_then(|layout| layout.fields.iter().position(|(name, _)| name == field))
+            .map(|index| index as u32)
+            .ok_or_else(|| Diagnostic::error(format!("Unknown field: {}", field), span).with_code("E0304").into())
+    }
+
+    // Named LLVM types for every struct and enum, created before any code so
+    // that functions can use types declared after them
+    fn declare_types(&mut self, program: &Program) {
+        let mut pending = Vec::new();
+        unsafe {
+            for statement in &program.statements {
+                match &statement.kind {
+                    StatementKind::Struct { name, generics, fields, .. } if !generics.is_empty() => {
+                        let (line, _) = self.line_index.line_col(statement.span.start);
+                        let generics = generics.iter().map(|param| param.name.clone()).collect();
+                        self.generic_structs.insert(
+                            name.to_string(),
+                            GenericStruct { generics, fields: fields.to_vec(), line: line as u32 },
+                        );
+                        continue;
+                    }
+                    StatementKind::Struct { name, fields, .. } => {
+                        let llvm_type = LLVMStructCreateNamed(self.context, CString::new(name.as_str()).unwrap().as_ptr());
+                        let (line, _) = self.line_index.line_col(statement.span.start);
+                        self.structs.insert(
+                            name.to_string(),
+                            StructLayout {
+                                llvm_type,
+                                fields: fields.to_vec(),
+                                line: line as u32,
+                                debug_type: std::ptr::null_mut(),
+                            },
+                        );
+                    }
+                    StatementKind::Enum(Enum { name, .. }) => {
+                        let llvm_type = LLVMStructCreateNamed(self.context, CString::new(name.as_str()).unwrap().as_ptr());
+                        self.enums.insert(name.to_string(), EnumLayout { llvm_type, variants: Vec::new() });
+                    }
+                    _ => continue,
+                }
+                pending.push(statement);
+            }
+        }
+
+        // Sizing an enum's payload needs the bodies of the types inside it, so
+        // bodies are set in dependency order. The analyzer rejects cycles.
+        while !pending.is_empty() {
+            let before = pending.len();
+            pending.retain(|statement| !self.define_type(statement));
+            if pending.len() == before {
+                break;
+            }
+        }
+    }
+
+    // Set the body of a declared struct or enum, unless a type it contains doesn't have one yet
+    fn define_type(&mut self, statement: &Statement) -> bool {
+        let member_types: Vec<&Type> = match &statement.kind {
+            StatementKind::Struct { fields, .. } => fields.iter().map(|(_, ty)| ty).collect(),
+            StatementKind::Enum(Enum { variants, .. }) => {
+                variants.iter().flat_map(|variant| &variant.fields).map(|(_, ty)| ty).collect()
+            }
+            _ => return true,
+        };
+        if !member_types.iter().all(|ty| self.is_defined(ty)) {
+            return false;
+        }
+
+        unsafe {
+            match &statement.kind {
+                StatementKind::Struct { name, fields, .. } => {
+                    let mut elements: Vec<_> = fields.iter().map(|(_, ty)| self.type_to_llvm(ty)).collect();
+                    LLVMStructSetBody(self.structs[name].llvm_type, elements.as_mut_ptr(), elements.len() as u32, 0);
+                }
+                StatementKind::Enum(Enum { name, variants, .. }) => {
+                    let data_layout = LLVMGetModuleDataLayout(self.module);
+                    let mut payloads = Vec::new();
+                    let mut payload_size = 0;
+                    for variant in variants {
+                        let mut fields: Vec<_> = variant.fields.iter().map(|(_, ty)| self.type_to_llvm(ty)).collect();
+                        let payload_type =
+                            LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), fields.len() as u32, 0);
+                        payload_size = payload_size.max(LLVMStoreSizeOfType(data_layout, payload_type));
+                        let field_types = variant.fields.iter().map(|(_, ty)| ty.clone()).collect();
+                        payloads.push((variant.name.clone(), payload_type, field_types));
+                    }
+
+                    let layout = self.enums.get_mut(name).unwrap();
+                    let storage = LLVMArrayType(LLVMInt64TypeInContext(self.context), payload_size.div_ceil(8) as u32);
+                    let mut elements = [LLVMInt32TypeInContext(self.context), storage];
+                    LLVMStructSetBody(layout.llvm_type, elements.as_mut_ptr(), elements.len() as u32, 0);
+                    layout.variants = payloads;
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn is_defined(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Custom(name, arguments) if self.generic_structs.contains_key(name) => {
+                let generic = &self.generic_structs[name];
+                let substitution = generic.generics.iter().cloned().zip(arguments.iter().cloned()).collect();
+                generic.fields.iter().all(|(_, field)| self.is_defined(&field.substitute(&substitution)))
+            }
+            Type::Custom(name, _) => {
+                let struct_type = self.structs.get(name).map(|layout| layout.llvm_type);
+                match struct_type.or_else(|| self.enums.get(name).map(|layout| layout.llvm_type)) {
+                    Some(llvm_type) => unsafe { LLVMIsOpaqueStruct(llvm_type) == 0 },
+                    None => true,
+                }
+            }
+            Type::Array(element, _) => self.is_defined(element),
+            _ => true,
+        }
+    }
+
+    // Describe a parameter (`arg_no` counts from 1) or local kept in `slot`
+    fn declare_debug_value(&mut self, name: &str, ty: &Type, slot: LLVMValueRef, span: Span, arg_no: Option<u32>) {
+        // Only function bodies have a scope to put variables in
+        let scope = self.debug_info.current_scope;
+        if scope == self.debug_info.file {
+            return;
+        }
+
+        let debug_type = self.debug_type(ty);
+        let (line, column) = self.line_index.line_col(span.start);
+        unsafe {
+            let variable = match arg_no {
+                Some(arg_no) => LLVMDIBuilderCreateParameterVariable(
+                    self.di_builder,
+                    scope,
+                    name.as_ptr() as *const _,
+                    name.len(),
+                    arg_no,
+                    self.debug_info.file,
+                    line as u32,
+                    debug_type,
+                    1,  // Keep even when optimized away
+                    LLVMDIFlagZero,
+                ),
+                None => LLVMDIBuilderCreateAutoVariable(
+                    self.di_builder,
+                    scope,
+                    name.as_ptr() as *const _,
+                    name.len(),
+                    self.debug_info.file,
+                    line as u32,
+                    debug_type,
+                    1,  // Keep even when optimized away
+                    LLVMDIFlagZero,
+                    0,  // Alignment in bits
+                ),
+            };
+
+            let location = LLVMDIBuilderCreateDebugLocation(
+                self.context,
+                line as u32,
+                column as u32,
+                scope,
+                std::ptr::null_mut(),
+            );
+            LLVMDIBuilderInsertDeclareAtEnd(
+                self.di_builder,
+                slot,
+                variable,
+                LLVMDIBuilderCreateExpression(self.di_builder, std::ptr::null_mut(), 0),
+                location,
+                LLVMGetInsertBlock(self.builder),
+            );
+        }
+    }
+
+    // The DWARF type debuggers use to display values of `ty`
+    fn debug_type(&mut self, ty: &Type) -> LLVMMetadataRef {
+        let ty = &ty.substitute(&self.type_arguments);
+        let name = ty.to_string();
+        unsafe {
+            let data_layout = LLVMGetModuleDataLayout(self.module);
+            match ty {
+                ty if ty.is_numeric() || *ty == Type::Bool => {
+                    let encoding = match ty.integer_kind() {
+                        _ if ty.is_float() => DW_ATE_FLOAT,
+                        Some((true, _)) => DW_ATE_SIGNED,
+                        Some((false, _)) => DW_ATE_UNSIGNED,
+                        None => DW_ATE_BOOLEAN,
+                    };
+                    LLVMDIBuilderCreateBasicType(
+                        self.di_builder,
+                        name.as_ptr() as *const _,
+                        name.len(),
+                        LLVMSizeOfTypeInBits(data_layout, self.type_to_llvm(ty)),
+                        encoding,
+                        LLVMDIFlagZero,
+                    )
+                }
+                Type::String => {
+                    let char_type = LLVMDIBuilderCreateBasicType(
+                        self.di_builder,
+                        "char".as_ptr() as *const _,
+                        4,
+                        8,
+                        DW_ATE_SIGNED_CHAR,
+                        LLVMDIFlagZero,
+                    );
+                    let llvm_type = self.type_to_llvm(ty);
+                    let pointer_size = LLVMSizeOfTypeInBits(data_layout, LLVMStructGetTypeAtIndex(llvm_type, 0));
+                    let bytes_type = LLVMDIBuilderCreatePointerType(self.di_builder, char_type, pointer_size, 0, 0, std::ptr::null(), 0);
+                    let usize_type = self.debug_type(&Type::Usize);
+                    let mut members = Vec::new();
+                    for (index, (field, field_type)) in [("ptr", bytes_type), ("len", usize_type), ("cap", usize_type)].into_iter().enumerate() {
+                        let field_llvm_type = LLVMStructGetTypeAtIndex(llvm_type, index as u32);
+                        members.push(LLVMDIBuilderCreateMemberType(
+                            self.di_builder,
+                            self.debug_info.file,
+                            field.as_ptr() as *const _,
+                            field.len(),
+                            self.debug_info.file,
+                            0,
+                            LLVMSizeOfTypeInBits(data_layout, field_llvm_type),
+                            LLVMABIAlignmentOfType(data_layout, field_llvm_type) * 8,
+                            LLVMOffsetOfElement(data_layout, llvm_type, index as u32) * 8,
+                            LLVMDIFlagZero,
+                            field_type,
+                        ));
+                    }
+                    LLVMDIBuilderCreateStructType(
+                        self.di_builder,
+                        self.debug_info.file,
+                        name.as_ptr() as *const _,
+                        name.len(),
+                        self.debug_info.file,
+                        0,
+                        LLVMSizeOfTypeInBits(data_layout, llvm_type),
+                        LLVMABIAlignmentOfType(data_layout, llvm_type) * 8,
+                        LLVMDIFlagZero,
+                        std::ptr::null_mut(),
+                        members.as_mut_ptr(),
+                        members.len() as u32,
+                        0,
+                        std::ptr::null_mut(),
+                        name.as_ptr() as *const _,
+                        name.len(),
+                    )
+                }
+                Type::Custom(struct_name, _)
+                    if self.structs.contains_key(struct_name) || self.generic_structs.contains_key(struct_name) =>
+                {
+                    self.type_to_llvm(ty);
+                    self.struct_debug_type(&name)
+                }
+                Type::Own(inner) => self.debug_type(inner),
+                Type::Array(element, len) => {
+                    let element_type = self.debug_type(element);
+                    let llvm_type = self.type_to_llvm(ty);
+                    let mut subscripts = [LLVMDIBuilderGetOrCreateSubrange(self.di_builder, 0, *len as i64)];
+                    LLVMDIBuilderCreateArrayType(
+                        self.di_builder,
+                        LLVMSizeOfTypeInBits(data_layout, llvm_type),
+                        LLVMABIAlignmentOfType(data_layout, llvm_type) * 8,
+                        element_type,
+                        subscripts.as_mut_ptr(),
+                        subscripts.len() as u32,
+                    )
+                }
+                Type::Ref(inner) | Type::RefMut(inner) => {
+                    let pointee = self.debug_type(inner);
+                    LLVMDIBuilderCreatePointerType(
+                        self.di_builder,
+                        pointee,
+                        LLVMSizeOfTypeInBits(data_layout, self.type_to_llvm(ty)),
+                        0,
+                        0,
+                        name.as_ptr() as *const _,
+                        name.len(),
+                    )
+                }
+                _ => LLVMDIBuilderCreateUnspecifiedType(self.di_builder, name.as_ptr() as *const _, name.len()),
+            }
+        }
+    }
+
+    // A DWARF composite type whose members sit at the offsets LLVM lays the fields out at
+    fn struct_debug_type(&mut self, name: &str) -> LLVMMetadataRef {
+        let layout = &self.structs[name];
+        if !layout.debug_type.is_null() {
+            return layout.debug_type;
+        }
+        let (llvm_type, line, fields) = (layout.llvm_type, layout.line, layout.fields.clone());
+
+        unsafe {
+            let data_layout = LLVMGetModuleDataLayout(self.module);
+            let mut members = Vec::new();
+            for (index, (field, ty)) in fields.iter().enumerate() {
+                let field_type = self.debug_type(ty);
+                let field_llvm_type = self.type_to_llvm(ty);
+                members.push(LLVMDIBuilderCreateMemberType(
+                    self.di_builder,
+                    self.debug_info.file,
+                    field.as_ptr() as *const _,
+                    field.len(),
+                    self.debug_info.file,
+                    line,
+                    LLVMSizeOfTypeInBits(data_layout, field_llvm_type),
+                    LLVMABIAlignmentOfType(data_layout, field_llvm_type) * 8,
+                    LLVMOffsetOfElement(data_layout, llvm_type, index as u32) * 8,
+                    LLVMDIFlagZero,
+                    field_type,
+                ));
+            }
+
+            let debug_type = LLVMDIBuilderCreateStructType(
+                self.di_builder,
+                self.debug_info.file,
+                name.as_ptr() as *const _,
+                name.len(),
+                self.debug_info.file,
+                line,
+                LLVMSizeOfTypeInBits(data_layout, llvm_type),
+                LLVMABIAlignmentOfType(data_layout, llvm_type) * 8,
+                LLVMDIFlagZero,
+                std::ptr::null_mut(),
+                members.as_mut_ptr(),
+                members.len() as u32,
+                0,  // No runtime language
+                std::ptr::null_mut(),
+                name.as_ptr() as *const _,
+                name.len(),
+            );
+            self.structs.get_mut(name).unwrap().debug_type = debug_type;
+            debug_type
+        }
+    }
+
+    // `print`, `println` and `to_string` lower to the runtime function
+    // matching the argument's type; `len` reads the length of an array, a
+    // slice or a string
+    fn generate_builtin_call(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+        type_arguments: &[Type],
+        span: Span,
+    ) -> Option<Result<LLVMValueRef, Box<Diagnostic>>> {
+        if name == "len" && arguments.len() == 1 {
+            return Some(self.generate_len(&arguments[0]));
+        }
+        if name != "print" && name != "println" && name != "to_string" {
+            return None;
+        }
+        if arguments.len() != 1 {
+            return Some(Err(
+                Diagnostic::error(format!("{} expects exactly one argument", name), span).with_code("E0301").into(),
+            ));
+        }
+
+        let value = match self.generate_expression(&arguments[0]) {
+            Ok(value) => value,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let argument_type = type_arguments.first().map(|ty| ty.substitute(&self.type_arguments));
+        let Some((suffix, mut args)) = self.runtime_arguments(value, argument_type) else {
+            return Some(Err(
+                Diagnostic::error(format!("Cannot pass a value of this type to {}", name), arguments[0].span)
+                    .with_code("E0301").into(),
+            ));
+        };
+        if name == "to_string" {
+            let string = self.build_string_call(&format!("swiftpp_to_string_{}", suffix), &args);
+            self.free_temporary(&arguments[0], value);
+            return Some(Ok(string));
+        }
+
+        let runtime_name = format!("swiftpp_{}_{}", name, suffix);
+        unsafe {
+            let param_types: Vec<_> = args.iter().map(|arg| LLVMTypeOf(*arg)).collect();
+            let function = self.get_or_declare_runtime_function(
+                &runtime_name,
+                LLVMVoidTypeInContext(self.context),
+                &param_types,
+            );
+
+            let call = LLVMBuildCall2(
+                self.builder,
+                LLVMGlobalGetValueType(function),
+                function,
+                args.as_mut_ptr(),
+                args.len() as u32,
+                CString::new("").unwrap().as_ptr()
+            );
+            self.free_temporary(&arguments[0], value);
+            Some(Ok(call))
+        }
+    }
+
+    // The suffix of the runtime functions that take a value of type `ty`,
+    // and the arguments `value` is passed to them as. Integers are widened
+    // to 64 bits, and an `i128` is passed in two halves, low first; `f32` is
+    // widened to `f64`, and a string is passed as its bytes and length.
+    fn runtime_arguments(&mut self, value: LLVMValueRef, ty: Option<Type>) -> Option<(&'static str, Vec<LLVMValueRef>)> {
+        unsafe {
+            let i64_type = LLVMInt64TypeInContext(self.context);
+            match ty? {
+                Type::Bool => Some(("bool", vec![value])),
+                Type::String => Some(("str", self.string_parts(value).to_vec())),
+                Type::I128 => {
+                    let high = LLVMBuildLShr(self.builder, value, LLVMConstInt(LLVMTypeOf(value), 64, 0), CString::new("high").unwrap().as_ptr());
+                    let low = LLVMBuildTrunc(self.builder, value, i64_type, CString::new("low").unwrap().as_ptr());
+                    let high = LLVMBuildTrunc(self.builder, high, i64_type, CString::new("high").unwrap().as_ptr());
+                    Some(("i128", vec![low, high]))
+                }
+                ty if ty.is_float() => {
+                    let double = LLVMBuildFPCast(self.builder, value, LLVMDoubleTypeInContext(self.context), CString::new("wide").unwrap().as_ptr());
+                    Some(("f64", vec![double]))
+                }
+                ty if ty.is_integer() => {
+                    let signed = ty.integer_kind().is_some_and(|(signed, _)| signed);
+                    let wide = LLVMBuildIntCast2(self.builder, value, i64_type, signed as i32, CString::new("wide").unwrap().as_ptr());
+                    Some((if signed { "i64" } else { "u64" }, vec![wide]))
+                }
+                _ => None,
+            }
+        }
+    }
+
+    fn generate_len(&mut self, argument: &Expression) -> Result<LLVMValueRef, Box<Diagnostic>> {
+        let value = self.generate_expression(argument)?;
+        unsafe {
+            let ty = LLVMTypeOf(value);
+            match LLVMGetTypeKind(ty) {
+                LLVMTypeKind::LLVMArrayTypeKind => {
+                    Ok(LLVMConstInt(LLVMInt64TypeInContext(self.context), LLVMGetArrayLength(ty) as u64, 0))
+                }
+                LLVMTypeKind::LLVMStructTypeKind => {
+                    Ok(LLVMBuildExtractValue(self.builder, value, 1, CString::new("len").unwrap().as_ptr()))
+                }
+                _ => Err(Diagnostic::error("Cannot take the length of a value of this type", argument.span).with_code("E0301").into()),
+            }
+        }
+    }
+
+    fn get_or_declare_runtime_function(
+        &mut self,
+        name: &str,
+        return_type: LLVMTypeRef,
+        param_types: &[LLVMTypeRef],
+    ) -> LLVMValueRef {
+        unsafe {
+            let c_name = CString::new(name).unwrap();
+            let function = LLVMGetNamedFunction(self.module, c_name.as_ptr());
+            if !function.is_null() {
+                return function;
+            }
+
+            let mut param_types = param_types.to_vec();
+            let function_type = LLVMFunctionType(
+                return_type,
+                param_types.as_mut_ptr(),
+                param_types.len() as u32,
+                0
+            );
+            LLVMAddFunction(self.module, c_name.as_ptr(), function_type)
+        }
+    }
+
+    // Emit the C `main` that starts the runtime and calls the program's `main`
+    pub fn generate_entry_point(&mut self) -> Result<(), Box<Diagnostic>> {
+        // `main` has no debug info of its own
+        self.clear_debug_location();
+
+        unsafe {
+            let user_main = LLVMGetNamedFunction(
+                self.module,
+                CString::new(symbol_name("main")).unwrap().as_ptr()
+            );
+            if user_main.is_null() {
+                return Err(Diagnostic::new(Severity::Error, "No `main` function found").with_code("E0305").into());
+            }
+
+            let i32_type = LLVMInt32TypeInContext(self.context);
+            let argv_type = LLVMPointerType(LLVMPointerType(LLVMInt8TypeInContext(self.context), 0), 0);
+            let void_type = LLVMVoidTypeInContext(self.context);
+
+            let rt_init = self.get_or_declare_runtime_function("swiftpp_rt_init", void_type, &[i32_type, argv_type]);
+            let rt_shutdown = self.get_or_declare_runtime_function("swiftpp_rt_shutdown", void_type, &[]);
+
+            let mut param_types = [i32_type, argv_type];
+            let main_type = LLVMFunctionType(i32_type, param_types.as_mut_ptr(), 2, 0);
+            let main = LLVMAddFunction(self.module, CString::new("main").unwrap().as_ptr(), main_type);
+
+            let bb = LLVMAppendBasicBlockInContext(
+                self.context,
+                main,
+                CString::new("entry").unwrap().as_ptr()
+            );
+            LLVMPositionBuilderAtEnd(self.builder, bb);
+
+            let mut init_args = [LLVMGetParam(main, 0), LLVMGetParam(main, 1)];
+            LLVMBuildCall2(
+                self.builder,
+                LLVMGlobalGetValueType(rt_init),
+                rt_init,
+                init_args.as_mut_ptr(),
+                init_args.len() as u32,
+                CString::new("").unwrap().as_ptr()
+            );
+
+            let user_main_type = LLVMGlobalGetValueType(user_main);
+            if LLVMCountParamTypes(user_main_type) != 0 {
+                return Err(Diagnostic::new(Severity::Error, "`main` must not take parameters").with_code("E0305").into());
+            }
+            let result = LLVMBuildCall2(
+                self.builder,
+                user_main_type,
+                user_main,
+                std::ptr::null_mut(),
+                0,
+                CString::new("").unwrap().as_ptr()
+            );
+
+            // The process exit code is the low 32 bits of `main`'s result
+            let return_type = LLVMGetReturnType(user_main_type);
+            let exit_code = match LLVMGetTypeKind(return_type) {
+                LLVMTypeKind::LLVMVoidTypeKind => LLVMConstInt(i32_type, 0, 0),
+                LLVMTypeKind::LLVMIntegerTypeKind => LLVMBuildIntCast2(
+                    self.builder,
+                    result,
+                    i32_type,
+                    1,
+                    CString::new("exitcode").unwrap().as_ptr()
+                ),
+                _ => {
+                    return Err(
+                        Diagnostic::new(Severity::Error, "`main` must return an integer or nothing")
+                            .with_code("E0305").into(),
+                    )
+                }
+            };
+
+            LLVMBuildCall2(
+                self.builder,
+                LLVMGlobalGetValueType(rt_shutdown),
+                rt_shutdown,
+                std::ptr::null_mut(),
+                0,
+                CString::new("").unwrap().as_ptr()
+            );
+            LLVMBuildRet(self.builder, exit_code);
+        }
+
+        self.verify().map_err(|message| Diagnostic::from(message).with_code("E0302").into())
+    }
+
+    fn type_to_llvm(&mut self, ty: &Type) -> LLVMTypeRef {
+        unsafe {
+            match ty {
+                Type::I8 | Type::U8 => LLVMInt8TypeInContext(self.context),
+                Type::I16 | Type::U16 => LLVMInt16TypeInContext(self.context),
+                Type::Int | Type::U32 => LLVMInt32TypeInContext(self.context),
+                Type::I64 | Type::U64 | Type::Usize => LLVMInt64TypeInContext(self.context),
+                Type::I128 => LLVMInt128TypeInContext(self.context),
+                Type::F32 => LLVMFloatTypeInContext(self.context),
+                Type::Float => LLVMDoubleTypeInContext(self.context),
+                Type::Bool => LLVMInt1TypeInContext(self.context),
+                // `{ i8*, i64, i64 }`: the bytes, their length and the
+                // capacity of the buffer, like the runtime's `SwiftString`
+                Type::String => {
+                    let i64_type = LLVMInt64TypeInContext(self.context);
+                    let mut fields = [LLVMPointerType(LLVMInt8TypeInContext(self.context), 0), i64_type, i64_type];
+                    LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), fields.len() as u32, 0)
+                }
+                Type::Array(inner, len) => LLVMArrayType(self.type_to_llvm(inner), *len as u32),
+                // `{ T*, i64 }`: the first element and the length
+                Type::Slice(inner) => {
+                    let mut fields = [LLVMPointerType(self.type_to_llvm(inner), 0), LLVMInt64TypeInContext(self.context)];
+                    LLVMStructTypeInContext(self.context, fields.as_mut_ptr(), fields.len() as u32, 0)
+                }
+                Type::Custom(name, arguments) if !arguments.is_empty() => self.struct_instance(name, arguments),
+                Type::Custom(name, _) => match (self.structs.get(name), self.enums.get(name)) {
+                    (Some(layout), _) => layout.llvm_type,
+                    (None, Some(layout)) => layout.llvm_type,
+                    (None, None) => LLVMVoidTypeInContext(self.context),
+                },
+                Type::Param(name) => match self.type_arguments.get(name).cloned() {
+                    Some(ty) => self.type_to_llvm(&ty),
+                    None => LLVMVoidTypeInContext(self.context),
+                },
+                Type::Ref(inner) | Type::RefMut(inner) => LLVMPointerType(self.type_to_llvm(inner), 0),
+                Type::Own(inner) => self.type_to_llvm(inner),
+                _ => LLVMVoidTypeInContext(self.context),
+            }
+        }
+    }
+
+    // The layout of generic struct `name` with `arguments` for its type
+    // parameters, created on first use
+    fn struct_instance(&mut self, name: &str, arguments: &[Type]) -> LLVMTypeRef {
+        let arguments: Vec<_> = arguments.iter().map(|ty| ty.substitute(&self.type_arguments)).collect();
+        let instance_name = Type::Custom(name.to_string(), arguments.clone()).to_string();
+        if let Some(layout) = self.structs.get(&instance_name) {
+            return layout.llvm_type;
+        }
+        let Some(generic) = self.generic_structs.get(name) else {
+            return unsafe { LLVMVoidTypeInContext(self.context) };
+        };
+
+        let substitution = generic.generics.iter().cloned().zip(arguments).collect();
+        let fields: Vec<_> = generic
+            .fields
+            .iter()
+            .map(|(field, ty)| (field.clone(), ty.substitute(&substitution)))
+            .collect();
+        let line = generic.line;
+        unsafe {
+            let llvm_type = LLVMStructCreateNamed(self.context, CString::new(instance_name.as_str()).unwrap().as_ptr());
+            let mut elements: Vec<_> = fields.iter().map(|(_, ty)| self.type_to_llvm(ty)).collect();
+            LLVMStructSetBody(llvm_type, elements.as_mut_ptr(), elements.len() as u32, 0);
+            self.structs.insert(
+                instance_name,
+                StructLayout { llvm_type, fields, line, debug_type: std::ptr::null_mut() },
+            );
+            llvm_type
+        }
+    }
+}
+
+impl Drop for CodeGenerator {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeDIBuilder(self.di_builder);
+            LLVMDisposeBuilder(self.builder);
+            LLVMDisposeModule(self.module);
+            LLVMContextDispose(self.context);
+        }
+    }
+}
+
+// An integer constant of type `ty`, whose value may need all 128 bits
+fn integer_constant(ty: LLVMTypeRef, value: u128) -> LLVMValueRef {
+    let words = [value as u64, (value >> 64) as u64];
+    unsafe { LLVMConstIntOfArbitraryPrecision(ty, words.len() as u32, words.as_ptr()) }
+}
+
+// Whether `expr` names memory that can be pointed to
+fn is_place(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Identifier(_) | ExpressionKind::Deref { .. } => true,
+        ExpressionKind::FieldAccess { object, .. } => is_place(object),
+        ExpressionKind::Index { array, array_type, .. } => match array_type {
+            Some(Type::Slice(_)) => true,
+            _ => is_place(array),
+        },
+        _ => false,
+    }
+}
+
+fn is_declaration(statement: &Statement) -> bool {
+    matches!(
+        statement.kind,
+        StatementKind::Function { .. }
+            | StatementKind::Impl(_)
+            | StatementKind::Struct { .. }
+            | StatementKind::Enum(_)
+            | StatementKind::Trait(_)
+    )
+}
+
+// The predicate for `<`, `<=`, `>` or `>=` on signed or unsigned integers
+fn ordering_predicate(operator: BinaryOp, signed: bool) -> LLVMIntPredicate {
+    match (operator, signed) {
+        (BinaryOp::Greater, true) => LLVMIntPredicate::LLVMIntSGT,
+        (BinaryOp::Greater, false) => LLVMIntPredicate::LLVMIntUGT,
+        (BinaryOp::Less, true) => LLVMIntPredicate::LLVMIntSLT,
+        (BinaryOp::Less, false) => LLVMIntPredicate::LLVMIntULT,
+        (BinaryOp::GreaterEqual, true) => LLVMIntPredicate::LLVMIntSGE,
+        (BinaryOp::GreaterEqual, false) => LLVMIntPredicate::LLVMIntUGE,
+        (_, true) => LLVMIntPredicate::LLVMIntSLE,
+        (_, false) => LLVMIntPredicate::LLVMIntULE,
+    }
+}
+
+// Whether `pattern`, which has no switch key of its own, matches `value`
+fn pattern_contains(pattern: &Pattern, value: i64) -> bool {
+    match &pattern.kind {
+        PatternKind::Range { start, end, inclusive: true } => *start <= value && value <= *end,
+        PatternKind::Range { start, end, inclusive: false } => *start <= value && value < *end,
+        PatternKind::Wildcard | PatternKind::Binding(_) => true,
+        _ => false,
+    }
+}
+
+// Methods are named after the impl they're in, like `<Point as Show>::show`,
+// since the same method name appears in every impl of a trait
+fn method_name(impl_decl: &Impl, method: &str) -> String {
+    format!("<{} as {}>::{}", impl_decl.self_type, impl_decl.trait_name, method)
+}
+
+// The program's `main` is renamed so the generated C entry point can own `main`
+fn symbol_name(name: &str) -> &str {
+    if name == "main" {
+        "swiftpp_main"
+    } else {
+        name
+    }
 }
@@ -0,0 +1,284 @@
+use std::fmt;
+use std::fmt::Write;
+
+use super::span::{LineIndex, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// A span with an explanation. Labels on the primary span are drawn with `^`,
+// the rest with `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// A proposed edit that replaces the text under `span`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub span: Span,
+    pub replacement: String,
+}
+
+// A problem found in the source. `primary_span` is `None` for problems that
+// don't come from the source text, such as a failed link.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    // E00xx: lexical and syntax errors, E01xx: name resolution and types,
+    // E02xx: ownership and borrowing, E03xx: code generation
+    pub code: Option<&'static str>,
+    pub severity: Severity,
+    pub message: String,
+    pub primary_span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code: None,
+            severity,
+            message: message.into(),
+            primary_span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, message).with_span(span)
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, message).with_span(span)
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.primary_span = Some(span);
+        self
+    }
+
+    // Label the primary span, which must already be set
+    pub fn with_primary_label(self, message: impl Into<String>) -> Self {
+        let span = self.primary_span.unwrap_or_default();
+        self.with_label(span, message)
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_suggestion(
+        mut self,
+        message: impl Into<String>,
+        span: Span,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.suggestions.push(Suggestion {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // Render in the style of rustc:
+    //
+    //   error[E0101]: Undefined variable: y
+    //    --> main.sp:3:12
+    //     |
+    //   3 |     return y + 1;
+    //     |            ^ not found in this scope
+    //     |
+    //     = help: a variable with a similar name exists: `x`
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let mut output = String::new();
+        match self.code {
+            Some(code) => write!(output, "{}[{}]: {}", self.severity, code, self.message),
+            None => write!(output, "{}: {}", self.severity, self.message),
+        }
+        .unwrap();
+
+        let line_index = LineIndex::new(source);
+        let lines: Vec<&str> = source.lines().collect();
+
+        // Each mark is drawn on its own line, primary first
+        let mut marks: Vec<(Span, char, Option<&str>)> = Vec::new();
+        if let Some(span) = self.primary_span {
+            let label = self.labels.iter().find(|label| label.span == span);
+            marks.push((span, '^', label.map(|label| label.message.as_str())));
+        }
+        for label in &self.labels {
+            if Some(label.span) != self.primary_span {
+                marks.push((label.span, '-', Some(label.message.as_str())));
+            }
+        }
+
+        let mut marked_lines: Vec<usize> = marks
+            .iter()
+            .map(|(span, _, _)| line_index.line_col(span.start).0)
+            .collect();
+        marked_lines.sort_unstable();
+        marked_lines.dedup();
+
+        let gutter = marked_lines.last().map_or(0, |line| line.to_string().len());
+        let pad = " ".repeat(gutter);
+
+        if let Some(span) = self.primary_span {
+            let (line, column) = line_index.line_col(span.start);
+            write!(output, "\n{}--> {}:{}:{}", pad, file_name, line, column).unwrap();
+        }
+
+        if !marked_lines.is_empty() {
+            write!(output, "\n{} |", pad).unwrap();
+            let mut previous_line = None;
+            for &line in &marked_lines {
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    write!(output, "\n...").unwrap();
+                }
+                previous_line = Some(line);
+
+                let text = lines.get(line - 1).copied().unwrap_or("");
+                write!(output, "\n{:>width$} | {}", line, text, width = gutter).unwrap();
+
+                let line_start = line_index.line_start(line).unwrap_or(0);
+                for (span, marker, message) in &marks {
+                    if line_index.line_col(span.start).0 != line {
+                        continue;
+                    }
+                    // Spans running past the end of the line are cut off there
+                    let start = (span.start - line_start).min(text.len());
+                    let end = (span.end.max(span.start) - line_start).min(text.len());
+                    let indent = text.get(..start).map_or(start, |prefix| prefix.chars().count());
+                    let width = text.get(start..end).map_or(1, |marked| marked.chars().count()).max(1);
+
+                    write!(
+                        output,
+                        "\n{} | {}{}",
+                        pad,
+                        " ".repeat(indent),
+                        marker.to_string().repeat(width)
+                    )
+                    .unwrap();
+                    if let Some(message) = message {
+                        write!(output, " {}", message).unwrap();
+                    }
+                }
+            }
+        }
+
+        if !self.notes.is_empty() || !self.suggestions.is_empty() {
+            write!(output, "\n{} |", pad).unwrap();
+        }
+        for note in &self.notes {
+            write!(output, "\n{} = note: {}", pad, note).unwrap();
+        }
+        for suggestion in &self.suggestions {
+            write!(output, "\n{} = help: {}: `{}`", pad, suggestion.message, suggestion.replacement).unwrap();
+        }
+
+        output.push('\n');
+        output
+    }
+
+    // Machine-readable form for `--error-format=json`, one object per diagnostic
+    pub fn to_json(&self, source: &str, file_name: &str) -> serde_json::Value {
+        let line_index = LineIndex::new(source);
+        let span_json = |span: Span, is_primary: bool, label: Option<&str>| {
+            let (line_start, column_start) = line_index.line_col(span.start);
+            let (line_end, column_end) = line_index.line_col(span.end);
+            serde_json::json!({
+                "file_name": file_name,
+                "byte_start": span.start,
+                "byte_end": span.end,
+                "line_start": line_start,
+                "column_start": column_start,
+                "line_end": line_end,
+                "column_end": column_end,
+                "is_primary": is_primary,
+                "label": label,
+            })
+        };
+
+        let mut spans = Vec::new();
+        if let Some(span) = self.primary_span {
+            let label = self.labels.iter().find(|label| label.span == span);
+            spans.push(span_json(span, true, label.map(|label| label.message.as_str())));
+        }
+        for label in &self.labels {
+            if Some(label.span) != self.primary_span {
+                spans.push(span_json(label.span, false, Some(&label.message)));
+            }
+        }
+
+        let suggestions: Vec<_> = self
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                serde_json::json!({
+                    "message": suggestion.message,
+                    "replacement": suggestion.replacement,
+                    "span": span_json(suggestion.span, false, None),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "code": self.code,
+            "severity": self.severity.to_string(),
+            "message": self.message,
+            "spans": spans,
+            "notes": self.notes,
+            "suggestions": suggestions,
+            "rendered": self.render(source, file_name),
+        })
+    }
+}
+
+// Failures outside the source text (LLVM, the linker, the filesystem) carry only a message
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
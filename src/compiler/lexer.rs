@@ -1,6 +1,14 @@
 use std::str::Chars;
 use std::iter::Peekable;
 
+use super::diagnostic::Diagnostic;
+use super::span::Span;
+
+// The types a number literal can be suffixed with
+const NUMBER_SUFFIXES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "u8", "u16", "u32", "u64", "usize", "f32", "f64",
+];
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     // Keywords
@@ -10,33 +18,64 @@ pub enum TokenType {
     If,
     Else,
     While,
+    Break,
+    Continue,
     For,
+    In,
     Struct,
     Enum,
+    Trait,
+    Impl,
+    Where,
     Match,
     Async,
     Await,
     Move,
     Own,
     Ref,
-    
-    // Literals
-    Integer(i64),
-    Float(f64),
+    Mut,
+    Const,
+    As,
+    Import,
+
+    // Literals, with the type suffix of `255u8` or `1.5f32`. An integer is
+    // unsigned here; the analyzer checks it fits its type, negated under `-`
+    Integer(u128, Option<String>),
+    Float(f64, Option<String>),
     String(String),
+    // A string literal with `{expr}` in it
+    InterpolatedString(Vec<StringPart>),
+    Char(char),
+    Boolean(bool),
     Identifier(String),
-    
+
     // Operators
     Plus,
     Minus,
     Multiply,
     Divide,
+    Modulo,
+    ShiftLeft,
+    ShiftRight,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    MultiplyAssign,
+    DivideAssign,
+    ModuloAssign,
     Equal,
     NotEqual,
     Greater,
+    GreaterEqual,
     Less,
-    
+    LessEqual,
+    Not,
+    And,
+    Or,
+    Ampersand,
+    Pipe,
+    Question,
+
     // Delimiters
     LeftParen,
     RightParen,
@@ -46,37 +85,80 @@ pub enum TokenType {
     RightBracket,
     Semicolon,
     Colon,
+    DoubleColon,
     Comma,
+    Dot,
+    DotDot,
+    DotDotEqual,
     Arrow,
-    
+    FatArrow,
+    Hash,
+
+    // Malformed input, with a message describing the problem
+    Error(String),
+
     EOF,
 }
 
-#[derive(Debug)]
+// A piece of an interpolated string literal, with where it is in the source
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Text(String, Span),
+    // The tokens between a pair of braces; the span covers the braces
+    Expression(Vec<Token>, Span),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
     pub column: usize,
+    // Number of characters the token spans
+    pub length: usize,
+    pub span: Span,
+}
+
+impl Token {
+    // Lexical errors travel through the token stream; this lifts one out as a diagnostic
+    pub fn diagnostic(&self) -> Option<Diagnostic> {
+        match &self.token_type {
+            TokenType::Error(message) => Some(Diagnostic::error(message.clone(), self.span).with_code("E0001")),
+            _ => None,
+        }
+    }
 }
 
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     line: usize,
     column: usize,
+    offset: usize,
+    // Byte offset of the next character, for spans
+    position: usize,
+    file_id: usize,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_file_id(input, 0)
+    }
+
+    pub fn with_file_id(input: &'a str, file_id: usize) -> Self {
         Lexer {
             input: input.chars().peekable(),
             line: 1,
             column: 1,
+            offset: 0,
+            position: 0,
+            file_id,
         }
     }
-    
+
     fn advance(&mut self) -> Option<char> {
         let c = self.input.next();
         if let Some(ch) = c {
+            self.offset += 1;
+            self.position += ch.len_utf8();
             if ch == '\n' {
                 self.line += 1;
                 self.column = 1;
@@ -86,65 +168,390 @@ impl<'a> Lexer<'a> {
         }
         c
     }
-    
+
     fn peek(&mut self) -> Option<&char> {
         self.input.peek()
     }
-    
-    fn skip_whitespace(&mut self) {
-        while let Some(&c) = self.peek() {
-            if !c.is_whitespace() {
-                break;
-            }
+
+    // Look `n` characters past the next one without consuming anything
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.input.clone().nth(n)
+    }
+
+    // Consume the next character if it is `expected`
+    fn match_char(&mut self, expected: char) -> bool {
+        if self.peek() == Some(&expected) {
             self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Skip whitespace and comments. Returns an error token for a block
+    // comment that is never closed.
+    fn skip_trivia(&mut self) -> Option<Token> {
+        loop {
+            match self.peek().copied() {
+                Some(c) if c.is_whitespace() => {
+                    self.advance();
+                }
+                Some('/') if self.peek_nth(1) == Some('/') => {
+                    while let Some(&c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.advance();
+                    }
+                }
+                Some('/') if self.peek_nth(1) == Some('*') => {
+                    let line = self.line;
+                    let column = self.column;
+                    let start = self.offset;
+                    let start_position = self.position;
+                    self.advance();
+                    self.advance();
+
+                    // Block comments nest, so `/* /* */ */` is one comment
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.advance() {
+                            Some('/') if self.match_char('*') => depth += 1,
+                            Some('*') if self.match_char('/') => depth -= 1,
+                            Some(_) => {}
+                            None => {
+                                return Some(Token {
+                                    token_type: TokenType::Error("Unterminated block comment".to_string()),
+                                    line,
+                                    column,
+                                    length: self.offset - start,
+                                    span: Span::new(self.file_id, start_position, self.position),
+                                });
+                            }
+                        }
+                    }
+                }
+                _ => return None,
+            }
         }
     }
-    
+
     fn read_identifier(&mut self, first_char: char) -> String {
         let mut identifier = String::new();
         identifier.push(first_char);
-        
+
         while let Some(&c) = self.peek() {
             if !c.is_alphanumeric() && c != '_' {
                 break;
             }
             identifier.push(self.advance().unwrap());
         }
-        
+
         identifier
     }
-    
+
+    // Append digits of the given radix (and `_` separators) to `number`
+    fn read_digits(&mut self, number: &mut String, radix: u32) {
+        while let Some(&c) = self.peek() {
+            if !c.is_digit(radix) && c != '_' {
+                break;
+            }
+            number.push(self.advance().unwrap());
+        }
+    }
+
     fn read_number(&mut self, first_char: char) -> TokenType {
+        if first_char == '0' {
+            let radix = match self.peek() {
+                Some(&'x') => 16,
+                Some(&'o') => 8,
+                Some(&'b') => 2,
+                _ => 10,
+            };
+            if radix != 10 {
+                let prefix = self.advance().unwrap();
+                return self.read_radix_integer(prefix, radix);
+            }
+        }
+
         let mut number = String::new();
         number.push(first_char);
+        self.read_digits(&mut number, 10);
         let mut is_float = false;
-        
-        while let Some(&c) = self.peek() {
-            if !c.is_digit(10) && c != '.' {
-                break;
+
+        // A `.` only starts a fraction when a digit follows, so `1..5` lexes
+        // as a range and `1.max(2)` as a method call
+        if self.peek() == Some(&'.') && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+            is_float = true;
+            number.push(self.advance().unwrap());
+            self.read_digits(&mut number, 10);
+
+            // Consume the whole literal even if it is malformed, so scanning
+            // resumes after it rather than in the middle of it
+            if self.peek() == Some(&'.') && self.peek_nth(1).is_some_and(|c| c.is_ascii_digit()) {
+                while let Some(&c) = self.peek() {
+                    if !c.is_ascii_digit() && c != '.' && c != '_' {
+                        break;
+                    }
+                    number.push(self.advance().unwrap());
+                }
+                return TokenType::Error(format!("Invalid number format: {}", number));
             }
-            if c == '.' {
-                if is_float {
-                    panic!("Invalid number format");
+        }
+
+        // Exponent: `e` or `E`, an optional sign, then digits
+        if let Some(&e) = self.peek() {
+            if e == 'e' || e == 'E' {
+                let has_digits = match self.peek_nth(1) {
+                    Some(c) if c.is_ascii_digit() => true,
+                    Some('+') | Some('-') => self.peek_nth(2).is_some_and(|c| c.is_ascii_digit()),
+                    _ => false,
+                };
+                if has_digits {
+                    is_float = true;
+                    number.push(self.advance().unwrap());
+                    if let Some(&sign) = self.peek() {
+                        if sign == '+' || sign == '-' {
+                            number.push(self.advance().unwrap());
+                        }
+                    }
+                    self.read_digits(&mut number, 10);
                 }
-                is_float = true;
             }
-            number.push(self.advance().unwrap());
         }
-        
+
+        let suffix = match self.read_suffix() {
+            Ok(suffix) => suffix,
+            Err(error) => return error,
+        };
+        // `1f32` is a float
+        if suffix.as_deref().is_some_and(|suffix| suffix.starts_with('f')) {
+            is_float = true;
+        } else if is_float && suffix.is_some() {
+            return TokenType::Error(format!("Invalid suffix `{}` for float literal", suffix.unwrap()));
+        }
+
+        let digits = number.replace('_', "");
         if is_float {
-            TokenType::Float(number.parse().unwrap())
+            match digits.parse() {
+                Ok(value) => TokenType::Float(value, suffix),
+                Err(_) => TokenType::Error(format!("Invalid float literal: {}", number)),
+            }
         } else {
-            TokenType::Integer(number.parse().unwrap())
+            match digits.parse() {
+                Ok(value) => TokenType::Integer(value, suffix),
+                Err(_) => TokenType::Error(format!("Integer literal out of range: {}", number)),
+            }
         }
     }
-    
+
+    // The type name right after a number, as in `255u8`
+    fn read_suffix(&mut self) -> Result<Option<String>, TokenType> {
+        if !self.peek().is_some_and(|c| c.is_alphabetic()) {
+            return Ok(None);
+        }
+        let mut suffix = String::new();
+        while let Some(&c) = self.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            suffix.push(self.advance().unwrap());
+        }
+        match NUMBER_SUFFIXES.contains(&suffix.as_str()) {
+            true => Ok(Some(suffix)),
+            false => Err(TokenType::Error(format!("Invalid suffix `{}` for number literal", suffix))),
+        }
+    }
+
+    fn read_radix_integer(&mut self, prefix: char, radix: u32) -> TokenType {
+        let mut number = String::new();
+
+        // Read any alphanumerics so a bad digit like `0b102` is one error
+        while let Some(&c) = self.peek() {
+            if !c.is_alphanumeric() && c != '_' {
+                break;
+            }
+            number.push(self.advance().unwrap());
+        }
+
+        // A suffix starts at the first `i` or `u`, which no digit can be
+        let (number, suffix) = match number.find(['i', 'u']) {
+            Some(start) => {
+                let suffix = number[start..].to_string();
+                if !NUMBER_SUFFIXES.contains(&suffix.as_str()) {
+                    return TokenType::Error(format!("Invalid suffix `{}` for number literal", suffix));
+                }
+                (number[..start].to_string(), Some(suffix))
+            }
+            None => (number, None),
+        };
+
+        let digits = number.replace('_', "");
+        if digits.is_empty() {
+            return TokenType::Error(format!("Missing digits after 0{}", prefix));
+        }
+        if let Some(bad) = digits.chars().find(|c| !c.is_digit(radix)) {
+            return TokenType::Error(format!("Invalid digit '{}' in base {} literal", bad, radix));
+        }
+
+        match u128::from_str_radix(&digits, radix) {
+            Ok(value) => TokenType::Integer(value, suffix),
+            Err(_) => TokenType::Error(format!("Integer literal out of range: 0{}{}", prefix, number)),
+        }
+    }
+
+    // Read the character after a `\`, or describe why it is not a valid escape
+    fn read_escape(&mut self) -> Result<char, String> {
+        match self.advance() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('\'') => Ok('\''),
+            Some('{') => Ok('{'),
+            Some('}') => Ok('}'),
+            Some('u') => {
+                if !self.match_char('{') {
+                    return Err("Expected '{' in unicode escape".to_string());
+                }
+                let mut hex = String::new();
+                while let Some(&c) = self.peek() {
+                    if c == '}' || c == '"' || c == '\'' || c == '\n' {
+                        break;
+                    }
+                    hex.push(self.advance().unwrap());
+                }
+                if !self.match_char('}') {
+                    return Err("Unterminated unicode escape".to_string());
+                }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid unicode escape: \\u{{{}}}", hex))
+            }
+            Some(c) => Err(format!("Unknown escape sequence: \\{}", c)),
+            None => Err("Unterminated escape sequence".to_string()),
+        }
+    }
+
+    // A plain string literal, or the parts of one that interpolates `{expr}`.
+    // `\{` and `\}` stand for the braces themselves.
+    fn read_string(&mut self) -> TokenType {
+        let mut parts = Vec::new();
+        let mut value = String::new();
+        let mut text_start = self.position;
+        let mut error = None;
+
+        let end = loop {
+            let position = self.position;
+            match self.advance() {
+                Some('"') => break position,
+                Some('\\') => match self.read_escape() {
+                    Ok(c) => value.push(c),
+                    Err(message) => {
+                        // Keep scanning to the closing quote; report the first problem
+                        error.get_or_insert(message);
+                    }
+                },
+                Some('{') => {
+                    if !value.is_empty() {
+                        let span = Span::new(self.file_id, text_start, position);
+                        parts.push(StringPart::Text(std::mem::take(&mut value), span));
+                    }
+                    match self.read_interpolation(position) {
+                        Ok(part) => parts.push(part),
+                        Err(message) => {
+                            error.get_or_insert(message);
+                        }
+                    }
+                    text_start = self.position;
+                }
+                Some(c) => value.push(c),
+                None => return TokenType::Error("Unterminated string literal".to_string()),
+            }
+        };
+
+        if let Some(message) = error {
+            return TokenType::Error(message);
+        }
+        if parts.is_empty() {
+            return TokenType::String(value);
+        }
+        if !value.is_empty() {
+            parts.push(StringPart::Text(value, Span::new(self.file_id, text_start, end)));
+        }
+        TokenType::InterpolatedString(parts)
+    }
+
+    // The tokens of an interpolated expression, up to the `}` matching the
+    // `{` at `start`. A quote ends the string literal even inside the braces.
+    // Error tokens are kept, so the parser reports them where they are.
+    fn read_interpolation(&mut self, start: usize) -> Result<StringPart, String> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+
+        loop {
+            if let Some(Token { token_type: TokenType::Error(message), .. }) = self.skip_trivia() {
+                return Err(message);
+            }
+            if self.peek() == Some(&'"') {
+                return Err("Expected '}' to end the interpolated expression".to_string());
+            }
+
+            let token = self.next_token();
+            match &token.token_type {
+                TokenType::RightBrace if depth == 0 => break,
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::EOF => return Err("Unterminated string literal".to_string()),
+                _ => {}
+            }
+            tokens.push(token);
+        }
+
+        Ok(StringPart::Expression(tokens, Span::new(self.file_id, start, self.position)))
+    }
+
+    fn read_char(&mut self) -> TokenType {
+        let value = match self.advance() {
+            Some('\\') => self.read_escape(),
+            Some('\'') => return TokenType::Error("Empty character literal".to_string()),
+            Some('\n') | None => return TokenType::Error("Unterminated character literal".to_string()),
+            Some(c) => Ok(c),
+        };
+
+        if !self.match_char('\'') {
+            // Skip to the closing quote on this line so the rest lexes normally
+            while let Some(&c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                self.advance();
+                if c == '\'' {
+                    return TokenType::Error("Character literal must contain one character".to_string());
+                }
+            }
+            return TokenType::Error("Unterminated character literal".to_string());
+        }
+
+        match value {
+            Ok(c) => TokenType::Char(c),
+            Err(message) => TokenType::Error(message),
+        }
+    }
+
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
-        
+        if let Some(error) = self.skip_trivia() {
+            return error;
+        }
+
         let current_line = self.line;
         let current_column = self.column;
-        
+        let current_offset = self.offset;
+        let current_position = self.position;
+
         let token_type = match self.advance() {
             None => TokenType::EOF,
             Some(c) => match c {
@@ -155,27 +562,114 @@ impl<'a> Lexer<'a> {
                 '[' => TokenType::LeftBracket,
                 ']' => TokenType::RightBracket,
                 ';' => TokenType::Semicolon,
-                ':' => TokenType::Colon,
                 ',' => TokenType::Comma,
-                '+' => TokenType::Plus,
+                '?' => TokenType::Question,
+                '#' => TokenType::Hash,
+                ':' => {
+                    if self.match_char(':') {
+                        TokenType::DoubleColon
+                    } else {
+                        TokenType::Colon
+                    }
+                },
+                '.' => {
+                    if self.match_char('.') {
+                        if self.match_char('=') {
+                            TokenType::DotDotEqual
+                        } else {
+                            TokenType::DotDot
+                        }
+                    } else {
+                        TokenType::Dot
+                    }
+                },
+                '+' => {
+                    if self.match_char('=') {
+                        TokenType::PlusAssign
+                    } else {
+                        TokenType::Plus
+                    }
+                },
                 '-' => {
-                    if let Some(&'>') = self.peek() {
-                        self.advance();
+                    if self.match_char('>') {
                         TokenType::Arrow
+                    } else if self.match_char('=') {
+                        TokenType::MinusAssign
                     } else {
                         TokenType::Minus
                     }
                 },
-                '*' => TokenType::Multiply,
-                '/' => TokenType::Divide,
+                '*' => {
+                    if self.match_char('=') {
+                        TokenType::MultiplyAssign
+                    } else {
+                        TokenType::Multiply
+                    }
+                },
+                '/' => {
+                    if self.match_char('=') {
+                        TokenType::DivideAssign
+                    } else {
+                        TokenType::Divide
+                    }
+                },
+                '%' => {
+                    if self.match_char('=') {
+                        TokenType::ModuloAssign
+                    } else {
+                        TokenType::Modulo
+                    }
+                },
                 '=' => {
-                    if let Some(&'=') = self.peek() {
-                        self.advance();
+                    if self.match_char('=') {
                         TokenType::Equal
+                    } else if self.match_char('>') {
+                        TokenType::FatArrow
                     } else {
                         TokenType::Assign
                     }
                 },
+                '!' => {
+                    if self.match_char('=') {
+                        TokenType::NotEqual
+                    } else {
+                        TokenType::Not
+                    }
+                },
+                '<' => {
+                    if self.match_char('<') {
+                        TokenType::ShiftLeft
+                    } else if self.match_char('=') {
+                        TokenType::LessEqual
+                    } else {
+                        TokenType::Less
+                    }
+                },
+                '>' => {
+                    if self.match_char('>') {
+                        TokenType::ShiftRight
+                    } else if self.match_char('=') {
+                        TokenType::GreaterEqual
+                    } else {
+                        TokenType::Greater
+                    }
+                },
+                '&' => {
+                    if self.match_char('&') {
+                        TokenType::And
+                    } else {
+                        TokenType::Ampersand
+                    }
+                },
+                '|' => {
+                    if self.match_char('|') {
+                        TokenType::Or
+                    } else {
+                        TokenType::Pipe
+                    }
+                },
+                '"' => self.read_string(),
+                '\'' => self.read_char(),
                 c if c.is_alphabetic() || c == '_' => {
                     let ident = self.read_identifier(c);
                     match ident.as_str() {
@@ -185,27 +679,41 @@ impl<'a> Lexer<'a> {
                         "if" => TokenType::If,
                         "else" => TokenType::Else,
                         "while" => TokenType::While,
+                        "break" => TokenType::Break,
+                        "continue" => TokenType::Continue,
                         "for" => TokenType::For,
+                        "in" => TokenType::In,
                         "struct" => TokenType::Struct,
                         "enum" => TokenType::Enum,
+                        "trait" => TokenType::Trait,
+                        "impl" => TokenType::Impl,
+                        "where" => TokenType::Where,
                         "match" => TokenType::Match,
                         "async" => TokenType::Async,
                         "await" => TokenType::Await,
                         "move" => TokenType::Move,
                         "own" => TokenType::Own,
                         "ref" => TokenType::Ref,
+                        "mut" => TokenType::Mut,
+                        "const" => TokenType::Const,
+                        "as" => TokenType::As,
+                        "import" => TokenType::Import,
+                        "true" => TokenType::Boolean(true),
+                        "false" => TokenType::Boolean(false),
                         _ => TokenType::Identifier(ident),
                     }
                 },
-                c if c.is_digit(10) => self.read_number(c),
-                _ => panic!("Unexpected character: {}", c),
+                c if c.is_ascii_digit() => self.read_number(c),
+                _ => TokenType::Error(format!("Unexpected character: {}", c)),
             },
         };
-        
+
         Token {
             token_type,
             line: current_line,
             column: current_column,
+            length: self.offset - current_offset,
+            span: Span::new(self.file_id, current_position, self.position),
         }
     }
 }
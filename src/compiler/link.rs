@@ -0,0 +1,118 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Name of the static archive built from the `swiftpp-runtime` crate
+pub const RUNTIME_LIB_NAME: &str = "libswiftpp_runtime.a";
+
+// Native libraries the Rust standard library inside the runtime depends on
+const SYSTEM_LIBS: &[&str] = &["-lpthread", "-ldl", "-lm", "-lrt", "-lutil", "-lgcc_s", "-lc"];
+
+pub struct Linker {
+    program: String,
+    runtime_lib: Option<PathBuf>,
+}
+
+impl Linker {
+    pub fn new(program: Option<String>, runtime_lib: Option<PathBuf>) -> Self {
+        let program = program
+            .or_else(|| env::var("SWIFTPP_LINKER").ok())
+            .unwrap_or_else(|| "cc".to_string());
+
+        Linker {
+            program,
+            runtime_lib,
+        }
+    }
+
+    pub fn link(&self, objects: &[PathBuf], output: &Path) -> Result<(), String> {
+        let runtime_lib = self.find_runtime_lib()?;
+
+        let mut command = Command::new(&self.program);
+        command.args(objects).arg(&runtime_lib).arg("-o").arg(output);
+
+        // Drop the parts of the runtime and the Rust standard library the
+        // program doesn't call
+        command.arg("-Wl,--gc-sections");
+        command.args(SYSTEM_LIBS);
+
+        log::debug!("Running linker: {:?}", command);
+
+        let result = command
+            .output()
+            .map_err(|e| format!("Failed to run linker '{}': {}", self.program, e))?;
+
+        if !result.status.success() {
+            return Err(format!(
+                "Linking failed ({}):\n{}",
+                result.status,
+                String::from_utf8_lossy(&result.stderr)
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Look for the runtime archive: explicit path, then $SWIFTPP_RUNTIME_LIB,
+    // then next to the running compiler (where cargo puts it). A test binary
+    // runs from cargo's `deps` directory, where the archive has a hash in
+    // its name; take the newest.
+    fn find_runtime_lib(&self) -> Result<PathBuf, String> {
+        if let Some(path) = &self.runtime_lib {
+            return Ok(path.clone());
+        }
+
+        if let Ok(path) = env::var("SWIFTPP_RUNTIME_LIB") {
+            return Ok(PathBuf::from(path));
+        }
+
+        if let Ok(exe) = env::current_exe() {
+            if let Some(dir) = exe.parent() {
+                for candidate in [dir.join(RUNTIME_LIB_NAME), dir.join("..").join(RUNTIME_LIB_NAME)] {
+                    if candidate.exists() {
+                        return Ok(candidate);
+                    }
+                }
+
+                let (prefix, suffix) = RUNTIME_LIB_NAME.split_at(RUNTIME_LIB_NAME.len() - 2);
+                let newest = fs::read_dir(dir)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|entry| {
+                        let name = entry.file_name().to_string_lossy().into_owned();
+                        name.starts_with(&format!("{}-", prefix)) && name.ends_with(suffix)
+                    })
+                    .max_by_key(|entry| entry.metadata().and_then(|metadata| metadata.modified()).ok());
+                if let Some(entry) = newest {
+                    return Ok(entry.path());
+                }
+            }
+        }
+
+        Err(format!(
+            "Could not find the Swift++ runtime ({}); pass --runtime-lib or set SWIFTPP_RUNTIME_LIB",
+            RUNTIME_LIB_NAME
+        ))
+    }
+}
+
+// A new directory for the intermediate files of one build, readable only by
+// the current user. Each call gets its own, so concurrent builds can't
+// collide or have their files swapped out from under them.
+pub fn scratch_dir() -> Result<PathBuf, String> {
+    let base = env::temp_dir();
+    loop {
+        let path = base.join(format!("swiftpp-{:016x}", rand::random::<u64>()));
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        match builder.create(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("Failed to create a directory in {}: {}", base.display(), e)),
+        }
+    }
+}
@@ -1,26 +1,125 @@
+pub mod span;
+pub mod diagnostic;
 pub mod lexer;
 pub mod ast;
 pub mod parser;
 pub mod analyzer;
+pub mod cfg;
+pub mod patterns;
+pub mod borrowck;
 pub mod codegen;
+pub mod optimizer;
+pub mod target;
+pub mod link;
 
-use std::path::Path;
-use std::fs;
+// Re-export the main types of each compilation stage
+pub use span::{LineIndex, Span};
+pub use diagnostic::{Diagnostic, Label, Severity, Suggestion};
+pub use lexer::{Lexer, Token, TokenType};
+pub use parser::Parser;
+pub use analyzer::{SemanticAnalyzer, Symbol, SymbolKind, SymbolTable};
+pub use codegen::CodeGenerator;
+pub use optimizer::{OptLevel, Optimizer};
+pub use target::{EmitKind, TargetMachine};
+pub use link::Linker;
+
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct CompilerOptions {
+    pub emit: EmitKind,
+    pub opt_level: OptLevel,
+    // Textual pass pipeline that replaces the default one for `opt_level`
+    pub passes: Option<String>,
+    // Write the module's IR before and after optimization next to the output
+    pub dump_ir: bool,
+    // Linker driver used by `build` (defaults to `cc`)
+    pub linker: Option<String>,
+    // Path to libswiftpp_runtime.a (defaults to searching next to the compiler)
+    pub runtime_lib: Option<PathBuf>,
+    // Index arrays and slices without checking the index against the length
+    pub no_bounds_checks: bool,
+}
 
 pub struct Compiler {
     pub source: String,
     pub output_path: String,
+    pub options: CompilerOptions,
 }
 
 impl Compiler {
     pub fn new(source: String, output_path: String) -> Self {
+        Self::with_options(source, output_path, CompilerOptions::default())
+    }
+
+    pub fn with_options(source: String, output_path: String, options: CompilerOptions) -> Self {
         Compiler {
             source,
             output_path,
+            options,
+        }
+    }
+
+    // On success, returns the warnings found along the way
+    pub fn compile(&self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let target_machine = TargetMachine::host(self.options.opt_level).map_err(failure)?;
+        let (codegen, warnings) = self.generate(&target_machine, false)?;
+        self.optimize(&target_machine, &codegen).map_err(failure)?;
+
+        // Step 6: Emission
+        target_machine
+            .emit_to_file(codegen.module(), Path::new(&self.output_path), self.options.emit)
+            .map_err(failure)?;
+
+        Ok(warnings)
+    }
+
+    // Compile to an object file with an entry point and link it into an executable
+    pub fn build(&self) -> Result<Vec<Diagnostic>, Vec<Diagnostic>> {
+        let target_machine = TargetMachine::host(self.options.opt_level).map_err(failure)?;
+        let (codegen, warnings) = self.generate(&target_machine, true)?;
+        self.optimize(&target_machine, &codegen).map_err(failure)?;
+
+        let output_path = Path::new(&self.output_path);
+        let scratch = link::scratch_dir().map_err(failure)?;
+        let object_path = scratch.join("program.o");
+
+        let linker = Linker::new(self.options.linker.clone(), self.options.runtime_lib.clone());
+        let result = target_machine
+            .emit_to_file(codegen.module(), &object_path, EmitKind::Object)
+            .and_then(|()| linker.link(std::slice::from_ref(&object_path), output_path));
+        let _ = std::fs::remove_dir_all(&scratch);
+        result.map_err(failure)?;
+        Ok(warnings)
+    }
+
+    // Step 5: Optimization
+    fn optimize(&self, target_machine: &TargetMachine, codegen: &codegen::CodeGenerator) -> Result<(), String> {
+        let module = codegen.module();
+        let output_path = Path::new(&self.output_path);
+        if self.options.dump_ir {
+            target_machine.emit_to_file(module, &output_path.with_extension("pre-opt.ll"), EmitKind::LlvmIr)?;
         }
+
+        let optimizer = match &self.options.passes {
+            Some(passes) => Optimizer::with_passes(self.options.opt_level, passes),
+            None => Optimizer::new(self.options.opt_level),
+        };
+        optimizer.optimize(module, target_machine)?;
+        codegen.verify()?;
+
+        if self.options.dump_ir {
+            target_machine.emit_to_file(module, &output_path.with_extension("post-opt.ll"), EmitKind::LlvmIr)?;
+        }
+
+        Ok(())
     }
 
-    pub fn compile(&self) -> Result<(), String> {
+    fn generate(
+        &self,
+        target_machine: &TargetMachine,
+        entry_point: bool,
+    ) -> Result<(codegen::CodeGenerator, Vec<Diagnostic>), Vec<Diagnostic>> {
         // Step 1: Lexical Analysis
         let mut lexer = lexer::Lexer::new(&self.source);
         let mut tokens = Vec::new();
@@ -32,18 +131,43 @@ impl Compiler {
             tokens.push(token);
         }
 
+        // Report every lexical error at once rather than just the first
+        let lex_errors: Vec<Diagnostic> = tokens.iter().filter_map(Token::diagnostic).collect();
+        if !lex_errors.is_empty() {
+            return Err(lex_errors);
+        }
+
         // Step 2: Parsing
         let mut parser = parser::Parser::new(tokens);
-        let ast = parser.parse()?;
+        let mut ast = parser.parse()?;
 
         // Step 3: Semantic Analysis
         let mut analyzer = analyzer::SemanticAnalyzer::new();
-        analyzer.analyze(&ast)?;
+        if let Err(mut errors) = analyzer.analyze(&ast) {
+            errors.extend_from_slice(analyzer.warnings());
+            return Err(errors);
+        }
+        analyzer.annotate(&mut ast);
+        let warnings = analyzer.warnings().to_vec();
 
         // Step 4: Code Generation
-        let mut codegen = codegen::CodeGenerator::new("swiftpp_module");
-        codegen.generate(&ast)?;
+        let mut codegen = codegen::CodeGenerator::with_source("swiftpp_module", &self.source);
+        codegen.set_bounds_checks(!self.options.no_bounds_checks);
+        // Debug builds trap on integer overflow; optimized ones wrap
+        codegen.set_overflow_checks(self.options.opt_level == OptLevel::O0);
+        // Struct layouts in debug info and the optimization passes both
+        // consult the data layout, so set it before generating anything
+        target_machine.configure_module(codegen.module());
+        codegen.generate(&ast).map_err(|error| vec![*error])?;
+        if entry_point {
+            codegen.generate_entry_point().map_err(|error| vec![*error])?;
+        }
 
-        Ok(())
+        Ok((codegen, warnings))
     }
 }
+
+// Wrap an error from outside the source text, such as LLVM or the linker
+fn failure(message: String) -> Vec<Diagnostic> {
+    vec![Diagnostic::from(message)]
+}
@@ -1,93 +1,135 @@
-use llvm_sys::core::*;
+use llvm_sys::error::*;
 use llvm_sys::prelude::*;
-use llvm_sys::transforms::pass_manager_builder::*;
-use llvm_sys::{LLVMPassManager, LLVMPassManagerBuilder};
+use llvm_sys::target_machine::LLVMCodeGenOptLevel;
+use llvm_sys::transforms::pass_builder::*;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::str::FromStr;
 
-pub struct Optimizer {
-    pass_manager: *mut LLVMPassManager,
-}
-
-impl Optimizer {
-    pub fn new(module: LLVMModuleRef) -> Self {
-        unsafe {
-            let pass_manager = LLVMCreatePassManager();
-            let builder = LLVMPassManagerBuilderCreate();
-
-            // Set optimization level (O3 for maximum optimization)
-            LLVMPassManagerBuilderSetOptLevel(builder, 3);
-            
-            // Enable size optimizations
-            LLVMPassManagerBuilderSetSizeLevel(builder, 0);
+use super::target::TargetMachine;
 
-            // Add target-specific optimizations
-            LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 275);
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    Os,
+    Oz,
+}
 
-            // Populate the pass manager with optimization passes
-            LLVMPassManagerBuilderPopulateModulePassManager(builder, pass_manager);
+impl OptLevel {
+    // Speed level of the pipeline (0-3)
+    pub fn speed_level(&self) -> u32 {
+        match self {
+            OptLevel::O0 => 0,
+            OptLevel::O1 => 1,
+            OptLevel::O2 | OptLevel::Os | OptLevel::Oz => 2,
+            OptLevel::O3 => 3,
+        }
+    }
 
-            // Add essential analysis passes
-            Self::add_analysis_passes(pass_manager);
+    // Size level of the pipeline (0-2)
+    pub fn size_level(&self) -> u32 {
+        match self {
+            OptLevel::Os => 1,
+            OptLevel::Oz => 2,
+            _ => 0,
+        }
+    }
 
-            // Add optimization passes
-            Self::add_optimization_passes(pass_manager);
+    pub fn codegen_level(&self) -> LLVMCodeGenOptLevel {
+        match self {
+            OptLevel::O0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            OptLevel::O1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            OptLevel::O2 | OptLevel::Os | OptLevel::Oz => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            OptLevel::O3 => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        }
+    }
+}
 
-            LLVMPassManagerBuilderDispose(builder);
+impl FromStr for OptLevel {
+    type Err = String;
 
-            Optimizer { pass_manager }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "3" => Ok(OptLevel::O3),
+            "s" => Ok(OptLevel::Os),
+            "z" => Ok(OptLevel::Oz),
+            _ => Err(format!("Unknown optimization level: {}", s)),
         }
     }
+}
 
-    pub fn optimize(&self, module: LLVMModuleRef) -> bool {
-        unsafe {
-            LLVMRunPassManager(self.pass_manager, module) != 0
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptLevel::O0 => write!(f, "O0"),
+            OptLevel::O1 => write!(f, "O1"),
+            OptLevel::O2 => write!(f, "O2"),
+            OptLevel::O3 => write!(f, "O3"),
+            OptLevel::Os => write!(f, "Os"),
+            OptLevel::Oz => write!(f, "Oz"),
         }
     }
+}
 
-    fn add_analysis_passes(pm: *mut LLVMPassManager) {
-        unsafe {
-            // Basic Alias Analysis
-            LLVMAddBasicAliasAnalysisPass(pm);
-            
-            // Type-Based Alias Analysis
-            LLVMAddTypeBasedAliasAnalysisPass(pm);
-            
-            // Scalar Evolution Analysis
-            LLVMAddScalarEvolutionAliasAnalysisPass(pm);
+pub struct Optimizer {
+    level: OptLevel,
+    passes: Option<String>,
+}
+
+impl Optimizer {
+    pub fn new(level: OptLevel) -> Self {
+        Optimizer { level, passes: None }
+    }
+
+    // Run a textual new-PM pipeline such as "default<O2>,loop-vectorize"
+    // instead of the default pipeline for the level
+    pub fn with_passes(level: OptLevel, passes: &str) -> Self {
+        Optimizer {
+            level,
+            passes: Some(passes.to_string()),
         }
     }
 
-    fn add_optimization_passes(pm: *mut LLVMPassManager) {
-        unsafe {
-            // Function-level optimizations
-            LLVMAddFunctionInliningPass(pm);        // Inline small functions
-            LLVMAddConstantPropagationPass(pm);     // Constant propagation
-            LLVMAddDeadStoreEliminationPass(pm);    // Remove dead stores
-            LLVMAddAggressiveDCEPass(pm);          // Aggressive dead code elimination
-            LLVMAddCFGSimplificationPass(pm);       // Control flow graph simplification
-            LLVMAddGVNPass(pm);                    // Global value numbering
-            
-            // Loop optimizations
-            LLVMAddLoopUnrollPass(pm);             // Unroll loops
-            LLVMAddLoopVectorizePass(pm);          // Vectorize loops
-            LLVMAddLoopRotatePass(pm);             // Rotate loops
-            
-            // SIMD optimizations
-            LLVMAddSLPVectorizePass(pm);           // Vectorize straight-line code
-            
-            // Memory optimizations
-            LLVMAddMemCpyOptPass(pm);              // Optimize memcpy calls
-            LLVMAddReassociatePass(pm);            // Reassociate expressions
-            
-            // Peephole optimizations
-            LLVMAddInstructionCombiningPass(pm);    // Combine instructions
+    pub fn pipeline(&self) -> String {
+        match &self.passes {
+            Some(passes) => passes.clone(),
+            None => format!("default<{}>", self.level),
         }
     }
-}
 
-impl Drop for Optimizer {
-    fn drop(&mut self) {
+    pub(crate) fn optimize(&self, module: LLVMModuleRef, target_machine: &TargetMachine) -> Result<(), String> {
+        let pipeline = CString::new(self.pipeline())
+            .map_err(|_| "Pass pipeline contains a NUL byte".to_string())?;
+
         unsafe {
-            LLVMDisposePassManager(self.pass_manager);
+            let options = LLVMCreatePassBuilderOptions();
+
+            // Vectorization pays off for speed, unrolling costs size
+            let vectorize = self.level.speed_level() >= 2 && self.level.size_level() == 0;
+            LLVMPassBuilderOptionsSetLoopVectorization(options, vectorize as LLVMBool);
+            LLVMPassBuilderOptionsSetSLPVectorization(options, vectorize as LLVMBool);
+            LLVMPassBuilderOptionsSetLoopInterleaving(options, vectorize as LLVMBool);
+            LLVMPassBuilderOptionsSetLoopUnrolling(options, (self.level.size_level() == 0) as LLVMBool);
+            LLVMPassBuilderOptionsSetMergeFunctions(options, (self.level == OptLevel::Oz) as LLVMBool);
+
+            let error = LLVMRunPasses(module, pipeline.as_ptr(), target_machine.as_raw(), options);
+            LLVMDisposePassBuilderOptions(options);
+
+            if !error.is_null() {
+                let message = LLVMGetErrorMessage(error);
+                let result = CStr::from_ptr(message).to_string_lossy().into_owned();
+                LLVMDisposeErrorMessage(message);
+                return Err(format!("Invalid pass pipeline '{}': {}", self.pipeline(), result));
+            }
         }
+
+        Ok(())
     }
 }
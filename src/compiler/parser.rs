@@ -1,46 +1,556 @@
-use super::lexer::{Token, TokenType};
+use super::lexer::{StringPart, Token, TokenType};
 use super::ast::*;
+use super::diagnostic::Diagnostic;
+
+// Re-export the AST so parser consumers can name the nodes it produces
+pub use super::ast;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // Cleared while parsing `if`/`while` conditions, where `{` opens the body
+    allow_struct_literal: bool,
+    // Syntax errors recovered from so far
+    diagnostics: Vec<Diagnostic>,
+    // Number of enclosing blocks, so recovery knows whether a '}' closes one
+    block_depth: usize,
+    // Names `parse_type` reads as `Type::Param`: the type parameters of the
+    // declaration being parsed, and `Self` inside a trait or impl
+    type_params: Vec<String>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // Lookahead relies on a trailing EOF, which callers often filter out
+        if tokens.last().is_none_or(|token| token.token_type != TokenType::EOF) {
+            let (line, column, span) = tokens.last().map_or((1, 1, Span::default()), |token| {
+                let end = Span::new(token.span.file_id, token.span.end, token.span.end);
+                (token.line, token.column + token.length, end)
+            });
+            tokens.push(Token {
+                token_type: TokenType::EOF,
+                line,
+                column,
+                length: 0,
+                span,
+            });
+        }
+
         Parser {
             tokens,
             current: 0,
+            allow_struct_literal: true,
+            diagnostics: Vec::new(),
+            block_depth: 0,
+            type_params: Vec::new(),
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Program, Vec<Diagnostic>> {
+        let (program, diagnostics) = self.parse_partial();
+        if diagnostics.is_empty() {
+            Ok(program)
+        } else {
+            Err(diagnostics)
         }
     }
 
-    pub fn parse(&mut self) -> Result<Program, String> {
+    // Parse the whole input, skipping past syntax errors instead of stopping at
+    // the first one. The program holds every statement that parsed cleanly.
+    pub fn parse_partial(&mut self) -> (Program, Vec<Diagnostic>) {
         let mut statements = Vec::new();
         while !self.is_at_end() {
-            statements.push(self.parse_statement()?);
+            if let Some(statement) = self.parse_statement_recovering() {
+                statements.push(statement);
+            }
+        }
+        (Program { statements }, std::mem::take(&mut self.diagnostics))
+    }
+
+    // On error, record it and skip to a point where parsing can resume
+    fn parse_statement_recovering(&mut self) -> Option<Statement> {
+        let start = self.current;
+        match self.parse_statement() {
+            Ok(statement) => Some(statement),
+            Err(diagnostic) => {
+                self.diagnostics.push(*diagnostic);
+                self.synchronize();
+                // A stray token that is itself a sync point would otherwise loop forever
+                if self.current == start {
+                    self.advance();
+                }
+                None
+            }
+        }
+    }
+
+    // Panic-mode recovery: discard tokens up to the end of the statement, a
+    // closing brace or the keyword that starts the next statement
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            match self.peek().token_type {
+                TokenType::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                TokenType::RightBrace => {
+                    // Outside any block the brace closes the broken declaration
+                    if self.block_depth == 0 {
+                        self.advance();
+                    }
+                    return;
+                }
+                TokenType::Fn
+                | TokenType::Struct
+                | TokenType::Enum
+                | TokenType::Trait
+                | TokenType::Impl
+                | TokenType::Import
+                | TokenType::Let
+                | TokenType::Return
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::For => return,
+                _ => {
+                    self.advance();
+                }
+            }
         }
-        Ok(Program { statements })
     }
 
-    fn parse_statement(&mut self) -> Result<Statement, String> {
+    fn parse_statement(&mut self) -> Result<Statement, Box<Diagnostic>> {
         match &self.peek().token_type {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Fn => self.parse_function(),
             TokenType::Return => self.parse_return_statement(),
             TokenType::If => self.parse_if_statement(),
             TokenType::While => self.parse_while_statement(),
+            TokenType::Break | TokenType::Continue => {
+                let start = self.advance().span;
+                let kind = match self.previous().token_type {
+                    TokenType::Break => StatementKind::Break,
+                    _ => StatementKind::Continue,
+                };
+                self.consume(TokenType::Semicolon, "Expected ';' after 'break' or 'continue'")?;
+                Ok(Statement::new(kind, self.span_from(start)))
+            }
             TokenType::Struct => self.parse_struct_declaration(),
-            _ => Ok(Statement::Expression(self.parse_expression()?)),
+            TokenType::Enum => self.parse_enum_declaration(),
+            TokenType::Trait => self.parse_trait_declaration(),
+            TokenType::Impl => self.parse_impl_declaration(),
+            TokenType::LeftBrace => {
+                let start = self.peek().span;
+                let statements = self.parse_block()?;
+                Ok(Statement::new(StatementKind::Block(statements), self.span_from(start)))
+            }
+            _ => {
+                let start = self.peek().span;
+                let expr = self.parse_expression()?;
+                let operator = match self.peek().token_type {
+                    TokenType::Assign => Some(None),
+                    TokenType::PlusAssign => Some(Some(BinaryOp::Add)),
+                    TokenType::MinusAssign => Some(Some(BinaryOp::Subtract)),
+                    TokenType::MultiplyAssign => Some(Some(BinaryOp::Multiply)),
+                    TokenType::DivideAssign => Some(Some(BinaryOp::Divide)),
+                    TokenType::ModuloAssign => Some(Some(BinaryOp::Modulo)),
+                    _ => None,
+                };
+                if let Some(operator) = operator {
+                    self.advance();
+                    let value = self.parse_expression()?;
+                    self.consume(TokenType::Semicolon, "Expected ';' after assignment")?;
+                    return Ok(Statement::new(
+                        StatementKind::Assign { target: expr, operator, value, operand_type: None },
+                        self.span_from(start),
+                    ));
+                }
+                // Like a block, a `match` statement needs no ';' after its closing brace
+                if !matches!(expr.kind, ExpressionKind::Match { .. }) || self.check(&TokenType::Semicolon) {
+                    self.consume(TokenType::Semicolon, "Expected ';' after expression")?;
+                }
+                Ok(Statement::new(StatementKind::Expression(expr), self.span_from(start)))
+            }
+        }
+    }
+
+    fn parse_function(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        // A nested function can't use the type parameters of the one around it
+        let mut type_params: Vec<String> = self.type_params.iter().filter(|name| *name == "Self").cloned().collect();
+        std::mem::swap(&mut self.type_params, &mut type_params);
+        let result = self.parse_function_in_scope();
+        self.type_params = type_params;
+        result
+    }
+
+    fn parse_function_in_scope(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Fn, "Expected 'fn'")?.span;
+        let name = self.consume_identifier("Expected function name")?;
+        let name_span = self.previous().span;
+        let mut generics = self.parse_generic_params()?;
+        let params = self.parse_params()?;
+
+        let return_type = if self.match_token(TokenType::Arrow) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        // `where T: Display, U: Clone + Debug`
+        if self.match_token(TokenType::Where) {
+            while !self.check(&TokenType::LeftBrace) && !self.check(&TokenType::FatArrow) {
+                let param_name = self.consume_identifier("Expected type parameter in where clause")?;
+                let Some(param) = generics.iter_mut().find(|param| param.name == param_name) else {
+                    return Err(Diagnostic::error(
+                        format!("Cannot find type parameter `{}` in this scope", param_name),
+                        self.previous().span,
+                    )
+                    .with_code("E0002")
+                    .with_primary_label("not declared in this function's type parameters").into());
+                };
+                self.consume(TokenType::Colon, "Expected ':' after type parameter")?;
+                let bounds = self.parse_bounds()?;
+                param.bounds.extend(bounds);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let expression_body = self.match_token(TokenType::FatArrow);
+        let body = if expression_body {
+            let value = self.parse_expression()?;
+            let span = value.span;
+            self.consume(TokenType::Semicolon, "Expected ';' after function body")?;
+            vec![Statement::new(StatementKind::Return(Some(value)), span)]
+        } else {
+            self.parse_block()?
+        };
+
+        Ok(Statement::new(
+            StatementKind::Function {
+                name,
+                name_span,
+                generics,
+                params,
+                return_type,
+                body,
+                expression_body,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    // `(a: i32, b)`; the first parameter of a method may be a bare `self`
+    fn parse_params(&mut self) -> Result<Vec<Param>, Box<Diagnostic>> {
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            let param_name = self.consume_identifier("Expected parameter name")?;
+            let span = self.previous().span;
+            // Unannotated parameters are inferred from the body and the calls
+            let ty = if self.match_token(TokenType::Colon) {
+                Some(self.parse_type()?)
+            } else if param_name == "self" && params.is_empty() && self.type_params.iter().any(|name| name == "Self") {
+                Some(Type::Param("Self".to_string()))
+            } else {
+                None
+            };
+            params.push(Param {
+                name: param_name,
+                ty,
+                span,
+            });
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        Ok(params)
+    }
+
+    // `<T, U: Display + Clone>`, which puts the names in scope for `parse_type`
+    fn parse_generic_params(&mut self) -> Result<Vec<GenericParam>, Box<Diagnostic>> {
+        let mut generics: Vec<GenericParam> = Vec::new();
+        if !self.match_token(TokenType::Less) {
+            return Ok(generics);
+        }
+        while !self.check(&TokenType::Greater) {
+            let name = self.consume_identifier("Expected type parameter name")?;
+            let span = self.previous().span;
+            if generics.iter().any(|param| param.name == name) {
+                return Err(Diagnostic::error(format!("Type parameter `{}` is declared more than once", name), span)
+                    .with_code("E0002").into());
+            }
+            let bounds = if self.match_token(TokenType::Colon) {
+                self.parse_bounds()?
+            } else {
+                Vec::new()
+            };
+            self.type_params.push(name.clone());
+            generics.push(GenericParam { name, span, bounds });
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
         }
+        self.consume(TokenType::Greater, "Expected '>' after type parameters")?;
+        Ok(generics)
+    }
+
+    // `Display + Clone`
+    fn parse_bounds(&mut self) -> Result<Vec<(String, Span)>, Box<Diagnostic>> {
+        let mut bounds = Vec::new();
+        loop {
+            let name = self.consume_identifier("Expected trait name")?;
+            bounds.push((name, self.previous().span));
+            if !self.match_token(TokenType::Plus) {
+                return Ok(bounds);
+            }
+        }
+    }
+
+    fn parse_return_statement(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Return, "Expected 'return'")?.span;
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.consume(TokenType::Semicolon, "Expected ';' after return")?;
+        Ok(Statement::new(StatementKind::Return(value), self.span_from(start)))
     }
 
-    fn parse_let_statement(&mut self) -> Result<Statement, String> {
-        self.consume(TokenType::Let, "Expected 'let'")?;
-        let name = match &self.consume_any()?.token_type {
-            TokenType::Identifier(name) => name.clone(),
-            _ => return Err("Expected identifier".to_string()),
+    fn parse_if_statement(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::If, "Expected 'if'")?.span;
+        let condition = self.parse_condition()?;
+        let then_branch = self.parse_block()?;
+
+        // `else if` chains nest as an `if` that is the sole statement of the else branch
+        let else_branch = if self.match_token(TokenType::Else) {
+            if self.check(&TokenType::If) {
+                Some(vec![self.parse_if_statement()?])
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
         };
 
+        Ok(Statement::new(
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_while_statement(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::While, "Expected 'while'")?.span;
+        let condition = self.parse_condition()?;
+        let body = self.parse_block()?;
+        Ok(Statement::new(StatementKind::While { condition, body }, self.span_from(start)))
+    }
+
+    fn parse_struct_declaration(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Struct, "Expected 'struct'")?.span;
+        let name = self.consume_identifier("Expected struct name")?;
+        let name_span = self.previous().span;
+
+        let outer = std::mem::take(&mut self.type_params);
+        let result = self.parse_struct_body(name, name_span);
+        self.type_params = outer;
+        Ok(Statement::new(result?, self.span_from(start)))
+    }
+
+    fn parse_struct_body(&mut self, name: String, name_span: Span) -> Result<StatementKind, Box<Diagnostic>> {
+        let generics = self.parse_generic_params()?;
+        self.consume(TokenType::LeftBrace, "Expected '{' after struct name")?;
+        let mut fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let field_name = self.consume_identifier("Expected field name")?;
+            self.consume(TokenType::Colon, "Expected ':' after field name")?;
+            fields.push((field_name, self.parse_type()?));
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after struct fields")?;
+        Ok(StatementKind::Struct { name, name_span, generics, fields })
+    }
+
+    fn parse_enum_declaration(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Enum, "Expected 'enum'")?.span;
+        let name = self.consume_identifier("Expected enum name")?;
+        let name_span = self.previous().span;
+
+        self.consume(TokenType::LeftBrace, "Expected '{' after enum name")?;
+        let mut variants = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let variant_name = self.consume_identifier("Expected variant name")?;
+            let span = self.previous().span;
+            let mut fields = Vec::new();
+            if self.match_token(TokenType::LeftParen) {
+                while !self.check(&TokenType::RightParen) {
+                    // `radius: f64` names the field; a bare type doesn't
+                    let field_name = if self.check_next(&TokenType::Colon) {
+                        let field_name = self.consume_identifier("Expected field name")?;
+                        self.advance();
+                        Some(field_name)
+                    } else {
+                        None
+                    };
+                    fields.push((field_name, self.parse_type()?));
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after variant fields")?;
+            }
+            variants.push(Variant {
+                name: variant_name,
+                span,
+                fields,
+            });
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after enum variants")?;
+
+        Ok(Statement::new(
+            StatementKind::Enum(Enum { name, name_span, variants }),
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_trait_declaration(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Trait, "Expected 'trait'")?.span;
+        let name = self.consume_identifier("Expected trait name")?;
+        let name_span = self.previous().span;
+
+        let outer = std::mem::replace(&mut self.type_params, vec!["Self".to_string()]);
+        let result = self.parse_trait_methods();
+        self.type_params = outer;
+        let methods = result?;
+
+        Ok(Statement::new(
+            StatementKind::Trait(Trait { name, name_span, methods }),
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_trait_methods(&mut self) -> Result<Vec<TraitMethod>, Box<Diagnostic>> {
+        self.consume(TokenType::LeftBrace, "Expected '{' after trait name")?;
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            self.consume(TokenType::Fn, "Expected 'fn' in trait body")?;
+            let name = self.consume_identifier("Expected method name")?;
+            let name_span = self.previous().span;
+            if self.check(&TokenType::Less) {
+                return Err(self.error("Trait methods can't have type parameters"));
+            }
+            let params = self.parse_params()?;
+            if params.first().is_none_or(|param| param.name != "self") {
+                return Err(Diagnostic::error(format!("Method `{}` must take `self` first", name), name_span)
+                    .with_code("E0002")
+                    .with_primary_label("expected `self` as the first parameter").into());
+            }
+            let return_type = if self.match_token(TokenType::Arrow) {
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+            self.consume(TokenType::Semicolon, "Expected ';' after method signature")?;
+            methods.push(TraitMethod { name, name_span, params, return_type });
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after trait methods")?;
+        Ok(methods)
+    }
+
+    fn parse_impl_declaration(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Impl, "Expected 'impl'")?.span;
+        let trait_name = self.consume_identifier("Expected trait name")?;
+        let trait_span = self.previous().span;
+        self.consume(TokenType::For, "Expected 'for' after trait name")?;
+        let type_start = self.peek().span;
+        let self_type = self.parse_type()?;
+        let type_span = self.span_from(type_start);
+
+        let outer = std::mem::replace(&mut self.type_params, vec!["Self".to_string()]);
+        let result = self.parse_impl_methods();
+        self.type_params = outer;
+        let methods = result?;
+
+        Ok(Statement::new(
+            StatementKind::Impl(Impl { trait_name, trait_span, self_type, type_span, methods }),
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_impl_methods(&mut self) -> Result<Vec<Statement>, Box<Diagnostic>> {
+        self.consume(TokenType::LeftBrace, "Expected '{' after impl type")?;
+        let mut methods = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if !self.check(&TokenType::Fn) {
+                return Err(self.error("Expected 'fn' in impl body"));
+            }
+            let method = self.parse_function()?;
+            if let StatementKind::Function { generics, name_span, .. } = &method.kind {
+                if let Some(param) = generics.first() {
+                    return Err(Diagnostic::error("Methods in an impl can't have type parameters", param.span)
+                        .with_code("E0002")
+                        .with_label(*name_span, "in this method").into());
+                }
+            }
+            methods.push(method);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after impl methods")?;
+        Ok(methods)
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Statement>, Box<Diagnostic>> {
+        let open = self.consume(TokenType::LeftBrace, "Expected '{'")?.span;
+        self.block_depth += 1;
+        let mut statements = Vec::new();
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.parse_statement_recovering() {
+                statements.push(statement);
+            }
+        }
+        self.block_depth -= 1;
+
+        // Running out of input inside a block still keeps what was parsed
+        if let Err(diagnostic) = self.consume(TokenType::RightBrace, "Expected '}'") {
+            self.diagnostics.push(diagnostic.with_label(open, "unclosed delimiter"));
+        }
+        Ok(statements)
+    }
+
+    // `if x == y { ... }` must not read `y { ... }` as a struct literal
+    fn parse_condition(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        self.with_struct_literals(false, |parser| parser.parse_expression())
+    }
+
+    fn with_struct_literals<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> Result<T, Box<Diagnostic>>,
+    ) -> Result<T, Box<Diagnostic>> {
+        let previous = std::mem::replace(&mut self.allow_struct_literal, allowed);
+        let result = parse(self);
+        self.allow_struct_literal = previous;
+        result
+    }
+
+    fn parse_let_statement(&mut self) -> Result<Statement, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Let, "Expected 'let'")?.span;
+        let mutable = self.match_token(TokenType::Mut);
+        let name = self.consume_identifier("Expected identifier")?;
+        let name_span = self.previous().span;
+
         let type_annotation = if self.match_token(TokenType::Colon) {
             Some(self.parse_type()?)
         } else {
@@ -51,68 +561,528 @@ impl Parser {
         let initializer = self.parse_expression()?;
         self.consume(TokenType::Semicolon, "Expected ';'")?;
 
-        Ok(Statement::Let {
-            name,
-            type_annotation,
-            initializer,
-        })
+        Ok(Statement::new(
+            StatementKind::Let {
+                name,
+                name_span,
+                mutable,
+                type_annotation,
+                initializer,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        self.parse_binary_expression(0)
+    }
+
+    // Precedence climbing: parse a unary operand, then fold in every binary
+    // operator that binds at least as tightly as `min_precedence`
+    fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expression, Box<Diagnostic>> {
+        let mut expr = self.parse_cast()?;
+
+        while let Some(op) = self.peek_binary_operator() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.advance();
+
+            // Left-associative: the right operand only takes tighter operators
+            let right = self.parse_binary_expression(op.precedence() + 1)?;
+            let span = expr.span.to(right.span);
+            expr = Expression::new(
+                ExpressionKind::Binary {
+                    left: Box::new(expr),
+                    operator: op,
+                    right: Box::new(right),
+                    operand_type: None,
+                },
+                span,
+            );
+        }
+
+        Ok(expr)
+    }
+
+    // `x as u8` binds tighter than any binary operator, but not a prefix one
+    fn parse_cast(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let mut expr = self.parse_unary()?;
+        while self.match_token(TokenType::As) {
+            let target = self.parse_type()?;
+            let span = self.span_from(expr.span);
+            expr = Expression::new(
+                ExpressionKind::Cast {
+                    operand: Box::new(expr),
+                    target,
+                    operand_type: None,
+                },
+                span,
+            );
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let operator = match &self.peek().token_type {
+            TokenType::Minus => UnaryOp::Negate,
+            TokenType::Not => UnaryOp::Not,
+            TokenType::Ref | TokenType::Move | TokenType::Multiply | TokenType::Ampersand => {
+                return self.parse_ownership()
+            }
+            _ => return self.parse_postfix(),
+        };
+        let start = self.advance().span;
+        let operand = self.parse_unary()?;
+
+        Ok(Expression::new(
+            ExpressionKind::Unary {
+                operator,
+                operand: Box::new(operand),
+                operand_type: None,
+            },
+            self.span_from(start),
+        ))
     }
 
-    fn parse_expression(&mut self) -> Result<Expression, String> {
-        self.parse_binary_expression()
+    // `ref x`, `ref mut x`, `move x`, `*r` and the slice `&a`
+    fn parse_ownership(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let start = self.advance().span;
+        let kind = match &self.previous().token_type {
+            TokenType::Ref => {
+                let mutable = self.match_token(TokenType::Mut);
+                let operand = Box::new(self.parse_unary()?);
+                ExpressionKind::Borrow { operand, mutable }
+            }
+            TokenType::Move => ExpressionKind::Move(Box::new(self.parse_unary()?)),
+            TokenType::Ampersand => ExpressionKind::Slice {
+                operand: Box::new(self.parse_unary()?),
+                array_type: None,
+            },
+            _ => ExpressionKind::Deref {
+                operand: Box::new(self.parse_unary()?),
+                pointee: None,
+            },
+        };
+        Ok(Expression::new(kind, self.span_from(start)))
     }
 
-    fn parse_binary_expression(&mut self) -> Result<Expression, String> {
+    // Calls, field accesses, method calls and indexing bind tighter than any
+    // prefix or binary operator
+    fn parse_postfix(&mut self) -> Result<Expression, Box<Diagnostic>> {
         let mut expr = self.parse_primary()?;
 
-        while let Some(op) = self.match_binary_operator() {
-            let right = self.parse_primary()?;
-            expr = Expression::Binary {
-                left: Box::new(expr),
-                operator: op,
-                right: Box::new(right),
-            };
+        loop {
+            let start = expr.span;
+            if self.match_token(TokenType::LeftParen) {
+                let arguments = self.parse_arguments()?;
+                expr = Expression::new(
+                    ExpressionKind::Call {
+                        function: Box::new(expr),
+                        arguments,
+                        type_arguments: Vec::new(),
+                    },
+                    self.span_from(start),
+                );
+            } else if self.match_token(TokenType::Dot) {
+                let name = self.consume_identifier("Expected field or method name after '.'")?;
+
+                if self.match_token(TokenType::LeftParen) {
+                    let arguments = self.parse_arguments()?;
+                    expr = Expression::new(
+                        ExpressionKind::MethodCall {
+                            receiver: Box::new(expr),
+                            method: name,
+                            arguments,
+                            receiver_type: None,
+                        },
+                        self.span_from(start),
+                    );
+                } else {
+                    expr = Expression::new(
+                        ExpressionKind::FieldAccess {
+                            object: Box::new(expr),
+                            field: name,
+                        },
+                        self.span_from(start),
+                    );
+                }
+            } else if self.match_token(TokenType::LeftBracket) {
+                let mut index = self.parse_expression()?;
+                if self.match_token(TokenType::DotDot) {
+                    let end = self.parse_expression()?;
+                    let span = index.span.to(end.span);
+                    index = Expression::new(
+                        ExpressionKind::Range {
+                            start: Box::new(index),
+                            end: Box::new(end),
+                        },
+                        span,
+                    );
+                }
+                self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+                expr = Expression::new(
+                    ExpressionKind::Index {
+                        array: Box::new(expr),
+                        index: Box::new(index),
+                        array_type: None,
+                    },
+                    self.span_from(start),
+                );
+            } else {
+                break;
+            }
         }
 
         Ok(expr)
     }
 
-    fn parse_primary(&mut self) -> Result<Expression, String> {
-        match &self.peek().token_type {
-            TokenType::Integer(n) => {
+    fn parse_primary(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let start = self.peek().span;
+        let kind = match &self.peek().token_type {
+            TokenType::Integer(n, suffix) => ExpressionKind::Integer(*n, suffix.as_deref().and_then(Type::primitive)),
+            TokenType::Float(n, suffix) => ExpressionKind::Float(*n, suffix.as_deref().and_then(Type::primitive)),
+            TokenType::String(value) => ExpressionKind::String(value.clone()),
+            TokenType::InterpolatedString(parts) => {
+                let parts = parts.clone();
+                self.advance();
+                return self.parse_interpolation(parts, start);
+            }
+            TokenType::Boolean(value) => ExpressionKind::Boolean(*value),
+            TokenType::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                return if self.check(&TokenType::DoubleColon) {
+                    self.parse_path(name, start)
+                } else if self.allow_struct_literal && self.check(&TokenType::LeftBrace) {
+                    self.parse_struct_literal(name, start)
+                } else {
+                    Ok(Expression::new(ExpressionKind::Identifier(name), start))
+                };
+            }
+            TokenType::LeftParen => {
+                self.advance();
+                let expr = self.with_struct_literals(true, |parser| parser.parse_expression())?;
+                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+                // The parenthesized expression's span covers the parentheses
+                return Ok(Expression::new(expr.kind, self.span_from(start)));
+            }
+            TokenType::Match => return self.parse_match(),
+            TokenType::If => return self.parse_if_expression(),
+            TokenType::LeftBracket => {
                 self.advance();
-                Ok(Expression::Integer(*n))
+                let mut elements = Vec::new();
+                while !self.check(&TokenType::RightBracket) {
+                    elements.push(self.parse_expression()?);
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RightBracket, "Expected ']' after array elements")?;
+                return Ok(Expression::new(ExpressionKind::ArrayLiteral(elements), self.span_from(start)));
+            }
+            _ => return Err(self.error("Expected expression")),
+        };
+
+        // Literals are a single token
+        self.advance();
+        Ok(Expression::new(kind, start))
+    }
+
+    // `"a{x}b"` becomes `"a" + to_string(x) + "b"`. Each `{expr}` is parsed
+    // from the tokens the lexer collected between its braces.
+    fn parse_interpolation(&mut self, parts: Vec<StringPart>, span: Span) -> Result<Expression, Box<Diagnostic>> {
+        let mut pieces = Vec::new();
+        for part in parts {
+            let piece = match part {
+                StringPart::Text(text, span) => Expression::new(ExpressionKind::String(text), span),
+                StringPart::Expression(tokens, braces) => {
+                    if tokens.is_empty() {
+                        return Err(Diagnostic::error("Expected an expression between '{' and '}'", braces)
+                            .with_code("E0002")
+                            .with_note("write `\\{` and `\\}` for braces in the text").into());
+                    }
+                    // The tokens hold absolute spans, so errors point into the braces
+                    if let Some(error) = tokens.iter().find_map(Token::diagnostic) {
+                        return Err(error.into());
+                    }
+                    let mut parser = Parser::new(tokens);
+                    parser.type_params = self.type_params.clone();
+                    let value = parser.parse_expression();
+                    self.diagnostics.append(&mut parser.diagnostics);
+                    let value = value?;
+                    if !parser.is_at_end() {
+                        return Err(parser.error("Expected '}' after interpolated expression"));
+                    }
+                    Expression::new(
+                        ExpressionKind::Call {
+                            function: Box::new(Expression::new(ExpressionKind::Identifier("to_string".to_string()), braces)),
+                            arguments: vec![value],
+                            type_arguments: Vec::new(),
+                        },
+                        braces,
+                    )
+                }
+            };
+            pieces.push(piece);
+        }
+
+        // The lexer only makes an interpolated string of a literal with braces in it
+        let mut pieces = pieces.into_iter();
+        let mut expr = pieces.next().unwrap();
+        for piece in pieces {
+            let span = expr.span.to(piece.span);
+            expr = Expression::new(
+                ExpressionKind::Binary {
+                    left: Box::new(expr),
+                    operator: BinaryOp::Add,
+                    right: Box::new(piece),
+                    operand_type: None,
+                },
+                span,
+            );
+        }
+        Ok(Expression::new(ExpressionKind::Interpolation(Box::new(expr)), span))
+    }
+
+    fn parse_path(&mut self, first: String, start: Span) -> Result<Expression, Box<Diagnostic>> {
+        let mut segments = vec![first];
+        while self.match_token(TokenType::DoubleColon) {
+            segments.push(self.consume_identifier("Expected identifier after '::'")?);
+        }
+        Ok(Expression::new(ExpressionKind::Path(segments), self.span_from(start)))
+    }
+
+    // `Point { x: 1, y }`; a field without a value takes the variable of the same name
+    fn parse_struct_literal(&mut self, name: String, start: Span) -> Result<Expression, Box<Diagnostic>> {
+        self.consume(TokenType::LeftBrace, "Expected '{'")?;
+        let mut fields = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let field_name = self.consume_identifier("Expected field name")?;
+            let value = if self.match_token(TokenType::Colon) {
+                self.parse_expression()?
+            } else {
+                Expression::new(ExpressionKind::Identifier(field_name.clone()), self.previous().span)
+            };
+            fields.push((field_name, value));
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after struct fields")?;
+
+        Ok(Expression::new(
+            ExpressionKind::StructLiteral {
+                name,
+                fields,
+                type_arguments: Vec::new(),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    fn parse_match(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let start = self.consume(TokenType::Match, "Expected 'match'")?.span;
+        let scrutinee = self.parse_condition()?;
+
+        self.consume(TokenType::LeftBrace, "Expected '{' after match scrutinee")?;
+        let mut arms = Vec::new();
+        while !self.check(&TokenType::RightBrace) {
+            let pattern = self.parse_pattern()?;
+            self.consume(TokenType::FatArrow, "Expected '=>' after pattern")?;
+            let body = self.with_struct_literals(true, |parser| parser.parse_expression())?;
+            let span = pattern.span.to(body.span);
+            arms.push(MatchArm { pattern, body, span });
+            if !self.match_token(TokenType::Comma) {
+                break;
             }
-            TokenType::Float(n) => {
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after match arms")?;
+
+        Ok(Expression::new(
+            ExpressionKind::Match {
+                scrutinee: Box::new(scrutinee),
+                arms,
+                scrutinee_type: None,
+            },
+            self.span_from(start),
+        ))
+    }
+
+    // `if c { a } else { b }` where a value is expected; the `else` is required
+    fn parse_if_expression(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        let start = self.consume(TokenType::If, "Expected 'if'")?.span;
+        let condition = self.parse_condition()?;
+        let then_branch = self.parse_branch_value()?;
+        self.consume(TokenType::Else, "Expected 'else' after the value of an 'if' expression")?;
+        let else_branch = if self.check(&TokenType::If) {
+            self.parse_if_expression()?
+        } else {
+            self.parse_branch_value()?
+        };
+
+        Ok(Expression::new(
+            ExpressionKind::If {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            },
+            self.span_from(start),
+        ))
+    }
+
+    // `{ expr }`
+    fn parse_branch_value(&mut self) -> Result<Expression, Box<Diagnostic>> {
+        self.consume(TokenType::LeftBrace, "Expected '{'")?;
+        let value = self.with_struct_literals(true, |parser| parser.parse_expression())?;
+        self.consume(TokenType::RightBrace, "Expected '}' after the value of the branch")?;
+        Ok(value)
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, Box<Diagnostic>> {
+        let start = self.peek().span;
+        let kind = match &self.peek().token_type {
+            TokenType::Identifier(name) if name == "_" => {
                 self.advance();
-                Ok(Expression::Float(*n))
+                PatternKind::Wildcard
             }
             TokenType::Identifier(name) => {
+                let name = name.clone();
                 self.advance();
+                if !self.check(&TokenType::DoubleColon) {
+                    return Ok(Pattern::new(PatternKind::Binding(name), start));
+                }
+
+                let mut path = vec![name];
+                while self.match_token(TokenType::DoubleColon) {
+                    path.push(self.consume_identifier("Expected identifier after '::'")?);
+                }
+                let mut fields = Vec::new();
                 if self.match_token(TokenType::LeftParen) {
-                    self.parse_call(name.clone())
-                } else {
-                    Ok(Expression::Identifier(name.clone()))
+                    while !self.check(&TokenType::RightParen) {
+                        fields.push(self.parse_pattern()?);
+                        if !self.match_token(TokenType::Comma) {
+                            break;
+                        }
+                    }
+                    self.consume(TokenType::RightParen, "Expected ')' after patterns")?;
+                }
+                PatternKind::Variant { path, fields }
+            }
+            TokenType::Boolean(value) => {
+                let value = *value;
+                self.advance();
+                PatternKind::Boolean(value)
+            }
+            TokenType::Integer(..) | TokenType::Minus => {
+                let start_value = self.parse_pattern_integer()?;
+                let inclusive = match self.peek().token_type {
+                    TokenType::DotDotEqual => true,
+                    TokenType::DotDot => false,
+                    _ => return Ok(Pattern::new(PatternKind::Integer(start_value), self.span_from(start))),
+                };
+                self.advance();
+                let end = self.parse_pattern_integer()?;
+                PatternKind::Range {
+                    start: start_value,
+                    end,
+                    inclusive,
                 }
             }
-            _ => Err("Expected expression".to_string()),
+            _ => return Err(self.error("Expected pattern")),
+        };
+        Ok(Pattern::new(kind, self.span_from(start)))
+    }
+
+    // An integer literal in a pattern, which may be negative
+    fn parse_pattern_integer(&mut self) -> Result<i64, Box<Diagnostic>> {
+        let negative = self.match_token(TokenType::Minus);
+        match self.peek().token_type {
+            TokenType::Integer(value, _) => {
+                let value = match i64::try_from(value) {
+                    Ok(value) => value,
+                    // `-9223372036854775808` is the one value whose magnitude doesn't fit
+                    Err(_) if negative && value == 1 << 63 => i64::MIN,
+                    Err(_) => return Err(self.error("Integer literal out of range for a pattern")),
+                };
+                self.advance();
+                Ok(if negative { value.wrapping_neg() } else { value })
+            }
+            _ => Err(self.error("Expected integer literal")),
         }
     }
 
-    fn parse_type(&mut self) -> Result<Type, String> {
-        match &self.consume_any()?.token_type {
-            TokenType::Identifier(name) => match name.as_str() {
-                "i32" => Ok(Type::Int),
-                "f64" => Ok(Type::Float),
-                "string" => Ok(Type::String),
-                "bool" => Ok(Type::Bool),
-                _ => Ok(Type::Custom(name.clone())),
-            },
-            _ => Err("Expected type".to_string()),
+    // Comma-separated arguments up to the closing ')'; the '(' is already consumed
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, Box<Diagnostic>> {
+        let mut arguments = Vec::new();
+        while !self.check(&TokenType::RightParen) {
+            arguments.push(self.with_struct_literals(true, |parser| parser.parse_expression())?);
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
+        Ok(arguments)
+    }
+
+    fn parse_type(&mut self) -> Result<Type, Box<Diagnostic>> {
+        if self.match_token(TokenType::Own) {
+            return Ok(Type::Own(Box::new(self.parse_type()?)));
+        }
+        if self.match_token(TokenType::Ref) {
+            let mutable = self.match_token(TokenType::Mut);
+            let inner = Box::new(self.parse_type()?);
+            return Ok(if mutable { Type::RefMut(inner) } else { Type::Ref(inner) });
+        }
+        // `&[T]`
+        if self.match_token(TokenType::Ampersand) {
+            self.consume(TokenType::LeftBracket, "Expected '[' after '&' in slice type")?;
+            let element = self.parse_type()?;
+            self.consume(TokenType::RightBracket, "Expected ']' after slice element type")?;
+            return Ok(Type::Slice(Box::new(element)));
+        }
+        // `[T; N]`
+        if self.match_token(TokenType::LeftBracket) {
+            let element = self.parse_type()?;
+            self.consume(TokenType::Semicolon, "Expected ';' after array element type")?;
+            let len = match self.peek().token_type {
+                TokenType::Integer(len, _) => len as usize,
+                _ => return Err(self.error("Expected array length")),
+            };
+            self.advance();
+            self.consume(TokenType::RightBracket, "Expected ']' after array length")?;
+            return Ok(Type::Array(Box::new(element), len));
         }
+
+        let name = self.consume_identifier("Expected type")?;
+        if let Some(primitive) = Type::primitive(&name) {
+            return Ok(primitive);
+        }
+        if self.type_params.contains(&name) {
+            return Ok(Type::Param(name));
+        }
+
+        // `Pair<i32, f64>`
+        let mut arguments = Vec::new();
+        if self.match_token(TokenType::Less) {
+            while !self.check(&TokenType::Greater) {
+                arguments.push(self.parse_type()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume_greater("Expected '>' after type arguments")?;
+        }
+        Ok(Type::Custom(name, arguments))
     }
 
     // Helper methods
+
+    // Span from `start` through the last consumed token
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
     fn peek(&self) -> &Token {
         &self.tokens[self.current]
     }
@@ -132,19 +1102,42 @@ impl Parser {
         &self.tokens[self.current - 1]
     }
 
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, String> {
+    // An error at the current token
+    fn error(&self, message: &str) -> Box<Diagnostic> {
+        Diagnostic::error(message, self.peek().span).with_code("E0002").into()
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, Box<Diagnostic>> {
         if self.check(&token_type) {
             Ok(self.advance())
         } else {
-            Err(message.to_string())
+            Err(self.error(message))
         }
     }
 
-    fn consume_any(&mut self) -> Result<&Token, String> {
-        if !self.is_at_end() {
-            Ok(self.advance())
-        } else {
-            Err("Unexpected end of input".to_string())
+    // A `>` closing type arguments; the first half of a `>>` closes the
+    // inner list of `Pair<Pair<i32>>`, leaving a `>` for the outer one
+    fn consume_greater(&mut self, message: &str) -> Result<(), Box<Diagnostic>> {
+        if self.check(&TokenType::ShiftRight) {
+            let token = &mut self.tokens[self.current];
+            token.token_type = TokenType::Greater;
+            token.span.start += 1;
+            token.column += 1;
+            token.length -= 1;
+            return Ok(());
+        }
+        self.consume(TokenType::Greater, message)?;
+        Ok(())
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<String, Box<Diagnostic>> {
+        match &self.peek().token_type {
+            TokenType::Identifier(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error(message)),
         }
     }
 
@@ -156,6 +1149,13 @@ impl Parser {
         }
     }
 
+    // Whether the token after the current one is `token_type`
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| &token.token_type == token_type)
+    }
+
     fn match_token(&mut self, token_type: TokenType) -> bool {
         if self.check(&token_type) {
             self.advance();
@@ -165,24 +1165,23 @@ impl Parser {
         }
     }
 
-    fn match_binary_operator(&mut self) -> Option<BinaryOp> {
+    fn peek_binary_operator(&self) -> Option<BinaryOp> {
         match &self.peek().token_type {
-            TokenType::Plus => {
-                self.advance();
-                Some(BinaryOp::Add)
-            }
-            TokenType::Minus => {
-                self.advance();
-                Some(BinaryOp::Subtract)
-            }
-            TokenType::Multiply => {
-                self.advance();
-                Some(BinaryOp::Multiply)
-            }
-            TokenType::Divide => {
-                self.advance();
-                Some(BinaryOp::Divide)
-            }
+            TokenType::Plus => Some(BinaryOp::Add),
+            TokenType::Minus => Some(BinaryOp::Subtract),
+            TokenType::Multiply => Some(BinaryOp::Multiply),
+            TokenType::Divide => Some(BinaryOp::Divide),
+            TokenType::Modulo => Some(BinaryOp::Modulo),
+            TokenType::ShiftLeft => Some(BinaryOp::ShiftLeft),
+            TokenType::ShiftRight => Some(BinaryOp::ShiftRight),
+            TokenType::Equal => Some(BinaryOp::Equal),
+            TokenType::NotEqual => Some(BinaryOp::NotEqual),
+            TokenType::Greater => Some(BinaryOp::Greater),
+            TokenType::Less => Some(BinaryOp::Less),
+            TokenType::GreaterEqual => Some(BinaryOp::GreaterEqual),
+            TokenType::LessEqual => Some(BinaryOp::LessEqual),
+            TokenType::And => Some(BinaryOp::And),
+            TokenType::Or => Some(BinaryOp::Or),
             _ => None,
         }
     }
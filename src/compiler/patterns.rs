@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+// Exhaustiveness and reachability of `match` arms, following the usefulness
+// algorithm from Maranget's "Warnings for pattern matching". An arm is
+// reachable if its pattern is useful after the arms above it, and a match is
+// exhaustive if a wildcard arm at the end would not be useful.
+
+// A pattern reduced to what matters for matching. Bindings are wildcards.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pat {
+    Wild,
+    // A variant's position in its enum, and patterns for its payload
+    Variant(usize, Vec<Pat>),
+    Bool(bool),
+    // An inclusive range of integers; a literal is a range of one value
+    Range(i64, i64),
+}
+
+// The values a pattern position ranges over
+#[derive(Debug, Clone, PartialEq)]
+pub enum Domain {
+    Enum(String),
+    Bool,
+    // An integer type's name and the values it holds
+    Int(String, i64, i64),
+    // Floats, strings and structs, which only wildcards and bindings match
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Constructor {
+    Variant(usize),
+    Bool(bool),
+    Range(i64, i64),
+}
+
+impl Constructor {
+    fn of(pat: &Pat) -> Option<Constructor> {
+        match pat {
+            Pat::Wild => None,
+            Pat::Variant(index, _) => Some(Constructor::Variant(*index)),
+            Pat::Bool(value) => Some(Constructor::Bool(*value)),
+            Pat::Range(start, end) => Some(Constructor::Range(*start, *end)),
+        }
+    }
+
+    // Whether `pat`, which isn't a wildcard, matches every value built by this
+    // constructor. Integer constructors come from splitting, so they are never
+    // partly inside a range pattern.
+    fn is_covered_by(&self, pat: &Pat) -> bool {
+        match (self, pat) {
+            (Constructor::Variant(index), Pat::Variant(other, _)) => index == other,
+            (Constructor::Bool(value), Pat::Bool(other)) => value == other,
+            (Constructor::Range(start, end), Pat::Range(other_start, other_end)) => {
+                other_start <= start && end <= other_end
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct Checker {
+    // Each enum's variants with the domains of their payload fields
+    enums: HashMap<String, Vec<(String, Vec<Domain>)>>,
+}
+
+impl Checker {
+    pub fn new(enums: HashMap<String, Vec<(String, Vec<Domain>)>>) -> Self {
+        Checker { enums }
+    }
+
+    // Whether each arm can match a value the arms above it don't, and a value
+    // of `domain` that no arm matches, if there is one
+    pub fn check(&self, arms: &[Pat], domain: &Domain) -> (Vec<bool>, Option<Pat>) {
+        let domains = [domain.clone()];
+        let mut rows: Vec<Vec<Pat>> = Vec::new();
+        let mut reachable = Vec::new();
+        for arm in arms {
+            let row = vec![arm.clone()];
+            reachable.push(self.usefulness(&rows, &row, &domains).is_some());
+            rows.push(row);
+        }
+
+        let missing = self
+            .usefulness(&rows, &[Pat::Wild], &domains)
+            .map(|mut witness| witness.remove(0));
+        (reachable, missing)
+    }
+
+    // Write `pat` the way it would appear in source
+    pub fn display(&self, pat: &Pat, domain: &Domain) -> String {
+        match (pat, domain) {
+            (Pat::Variant(index, fields), Domain::Enum(name)) => {
+                let (variant, field_domains) = &self.enums[name][*index];
+                if fields.is_empty() {
+                    return format!("{}::{}", name, variant);
+                }
+                let fields: Vec<_> = fields
+                    .iter()
+                    .zip(field_domains)
+                    .map(|(field, domain)| self.display(field, domain))
+                    .collect();
+                format!("{}::{}({})", name, variant, fields.join(", "))
+            }
+            (Pat::Bool(value), _) => value.to_string(),
+            (Pat::Range(start, end), domain) if start == end => bound(*start, domain),
+            (Pat::Range(start, end), domain) => format!("{}..={}", bound(*start, domain), bound(*end, domain)),
+            _ => "_".to_string(),
+        }
+    }
+
+    // The values that the row `v` matches but none of `rows` do, as a witness
+    // pattern for each column; `None` if there are no such values
+    fn usefulness(&self, rows: &[Vec<Pat>], v: &[Pat], domains: &[Domain]) -> Option<Vec<Pat>> {
+        let Some((head, tail)) = v.split_first() else {
+            return if rows.is_empty() { Some(Vec::new()) } else { None };
+        };
+        let heads: Vec<&Pat> = rows.iter().map(|row| &row[0]).collect();
+
+        match Constructor::of(head) {
+            Some(Constructor::Range(start, end)) => split_range(start, end, &heads)
+                .into_iter()
+                .find_map(|constructor| self.specialized_usefulness(rows, v, domains, constructor)),
+            Some(constructor) => self.specialized_usefulness(rows, v, domains, constructor),
+            None => {
+                let all = self.constructors(&domains[0], &heads);
+                let missing = all
+                    .iter()
+                    .find(|constructor| !heads.iter().any(|head| constructor.is_covered_by(head)));
+
+                // Every constructor appears in the column, so the wildcard is
+                // useful if it is useful as any one of them. An enum without
+                // variants has no values at all.
+                if domains[0] != Domain::Other && missing.is_none() {
+                    return all
+                        .into_iter()
+                        .find_map(|constructor| self.specialized_usefulness(rows, v, domains, constructor));
+                }
+
+                // Otherwise only the rows that start with a wildcard matter
+                let default: Vec<Vec<Pat>> = rows
+                    .iter()
+                    .filter(|row| row[0] == Pat::Wild)
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                let mut witness = self.usefulness(&default, tail, &domains[1..])?;
+                let head = match missing {
+                    Some(constructor) if heads.iter().any(|head| **head != Pat::Wild) => {
+                        let arity = self.field_domains(&domains[0], *constructor).len();
+                        apply(*constructor, vec![Pat::Wild; arity])
+                    }
+                    _ => Pat::Wild,
+                };
+                witness.insert(0, head);
+                Some(witness)
+            }
+        }
+    }
+
+    // Usefulness among the values built by `constructor`, whose fields take the
+    // place of the first column
+    fn specialized_usefulness(
+        &self,
+        rows: &[Vec<Pat>],
+        v: &[Pat],
+        domains: &[Domain],
+        constructor: Constructor,
+    ) -> Option<Vec<Pat>> {
+        let mut field_domains = self.field_domains(&domains[0], constructor);
+        let arity = field_domains.len();
+        field_domains.extend_from_slice(&domains[1..]);
+
+        let rows: Vec<Vec<Pat>> = rows
+            .iter()
+            .filter_map(|row| specialize(row, constructor, arity))
+            .collect();
+        let v = specialize(v, constructor, arity)?;
+
+        let mut witness = self.usefulness(&rows, &v, &field_domains)?;
+        let rest = witness.split_off(arity);
+        let mut result = vec![apply(constructor, witness)];
+        result.extend(rest);
+        Some(result)
+    }
+
+    // Every constructor of `domain`. Integers are split at the bounds of the
+    // ranges in `heads`, so each piece is either inside or outside every range.
+    fn constructors(&self, domain: &Domain, heads: &[&Pat]) -> Vec<Constructor> {
+        match domain {
+            Domain::Enum(name) => (0..self.enums[name].len()).map(Constructor::Variant).collect(),
+            Domain::Bool => vec![Constructor::Bool(false), Constructor::Bool(true)],
+            Domain::Int(_, min, max) => split_range(*min, *max, heads),
+            Domain::Other => Vec::new(),
+        }
+    }
+
+    fn field_domains(&self, domain: &Domain, constructor: Constructor) -> Vec<Domain> {
+        match (domain, constructor) {
+            (Domain::Enum(name), Constructor::Variant(index)) => self.enums[name][index].1.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+// `row` with its first pattern replaced by the fields it matches when the
+// value was built by `constructor`, or `None` if it can't match such a value
+fn specialize(row: &[Pat], constructor: Constructor, arity: usize) -> Option<Vec<Pat>> {
+    let mut specialized = match &row[0] {
+        Pat::Wild => vec![Pat::Wild; arity],
+        head if constructor.is_covered_by(head) => match head {
+            Pat::Variant(_, fields) => fields.clone(),
+            _ => Vec::new(),
+        },
+        _ => return None,
+    };
+    specialized.extend_from_slice(&row[1..]);
+    Some(specialized)
+}
+
+fn apply(constructor: Constructor, fields: Vec<Pat>) -> Pat {
+    match constructor {
+        Constructor::Variant(index) => Pat::Variant(index, fields),
+        Constructor::Bool(value) => Pat::Bool(value),
+        Constructor::Range(start, end) => Pat::Range(start, end),
+    }
+}
+
+// Cut `start..=end` wherever one of the ranges in `heads` begins or ends
+fn split_range(start: i64, end: i64, heads: &[&Pat]) -> Vec<Constructor> {
+    // One past `i64::MAX` is a boundary too, so work in i128
+    let mut bounds = vec![start as i128, end as i128 + 1];
+    for head in heads {
+        if let Pat::Range(head_start, head_end) = head {
+            for bound in [*head_start as i128, *head_end as i128 + 1] {
+                if bound > start as i128 && bound <= end as i128 {
+                    bounds.push(bound);
+                }
+            }
+        }
+    }
+    bounds.sort_unstable();
+    bounds.dedup();
+    bounds
+        .windows(2)
+        .map(|pair| Constructor::Range(pair[0] as i64, (pair[1] - 1) as i64))
+        .collect()
+}
+
+// An end of a range, naming the limits of its type rather than spelling them out
+fn bound(value: i64, domain: &Domain) -> String {
+    match domain {
+        Domain::Int(name, min, _) if value == *min && value != 0 => format!("{}::MIN", name),
+        Domain::Int(name, _, max) if value == *max => format!("{}::MAX", name),
+        _ => value.to_string(),
+    }
+}
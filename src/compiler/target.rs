@@ -0,0 +1,181 @@
+use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::target::*;
+use llvm_sys::target_machine::*;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::optimizer::OptLevel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EmitKind {
+    #[default]
+    Object,
+    Assembly,
+    LlvmIr,
+    LlvmBitcode,
+}
+
+impl EmitKind {
+    // Default file extension for outputs of this kind
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EmitKind::Object => "o",
+            EmitKind::Assembly => "s",
+            EmitKind::LlvmIr => "ll",
+            EmitKind::LlvmBitcode => "bc",
+        }
+    }
+}
+
+impl FromStr for EmitKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "obj" => Ok(EmitKind::Object),
+            "asm" => Ok(EmitKind::Assembly),
+            "llvm-ir" => Ok(EmitKind::LlvmIr),
+            "llvm-bc" => Ok(EmitKind::LlvmBitcode),
+            _ => Err(format!("Unknown emit kind: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for EmitKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmitKind::Object => write!(f, "obj"),
+            EmitKind::Assembly => write!(f, "asm"),
+            EmitKind::LlvmIr => write!(f, "llvm-ir"),
+            EmitKind::LlvmBitcode => write!(f, "llvm-bc"),
+        }
+    }
+}
+
+pub struct TargetMachine {
+    machine: LLVMTargetMachineRef,
+    triple: String,
+}
+
+impl TargetMachine {
+    pub fn host(opt_level: OptLevel) -> Result<Self, String> {
+        unsafe {
+            if LLVM_InitializeNativeTarget() != 0 || LLVM_InitializeNativeAsmPrinter() != 0 {
+                return Err("Failed to initialize native target".to_string());
+            }
+
+            let triple_ptr = LLVMGetDefaultTargetTriple();
+            let triple = CStr::from_ptr(triple_ptr).to_string_lossy().into_owned();
+
+            let mut target: LLVMTargetRef = std::ptr::null_mut();
+            let mut error = std::ptr::null_mut();
+            if LLVMGetTargetFromTriple(triple_ptr, &mut target, &mut error) != 0 {
+                LLVMDisposeMessage(triple_ptr);
+                return Err(format!("Failed to look up target {}: {}", triple, take_message(error)));
+            }
+
+            // Tune for the CPU we are running on
+            let cpu = LLVMGetHostCPUName();
+            let features = LLVMGetHostCPUFeatures();
+
+            let machine = LLVMCreateTargetMachine(
+                target,
+                triple_ptr,
+                cpu,
+                features,
+                opt_level.codegen_level(),
+                LLVMRelocMode::LLVMRelocPIC,
+                LLVMCodeModel::LLVMCodeModelDefault,
+            );
+
+            LLVMDisposeMessage(features);
+            LLVMDisposeMessage(cpu);
+            LLVMDisposeMessage(triple_ptr);
+
+            if machine.is_null() {
+                return Err(format!("Failed to create target machine for {}", triple));
+            }
+
+            Ok(TargetMachine { machine, triple })
+        }
+    }
+
+    pub fn triple(&self) -> &str {
+        &self.triple
+    }
+
+    pub fn as_raw(&self) -> LLVMTargetMachineRef {
+        self.machine
+    }
+
+    // Stamp the module with our triple and data layout before emitting it
+    pub(crate) fn configure_module(&self, module: LLVMModuleRef) {
+        unsafe {
+            let triple = CString::new(self.triple.as_str()).unwrap();
+            LLVMSetTarget(module, triple.as_ptr());
+
+            let data_layout = LLVMCreateTargetDataLayout(self.machine);
+            LLVMSetModuleDataLayout(module, data_layout);
+            LLVMDisposeTargetData(data_layout);
+        }
+    }
+
+    pub(crate) fn emit_to_file(&self, module: LLVMModuleRef, path: &Path, kind: EmitKind) -> Result<(), String> {
+        self.configure_module(module);
+
+        let path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| "Output path contains a NUL byte".to_string())?;
+
+        unsafe {
+            let mut error = std::ptr::null_mut();
+            let failed = match kind {
+                EmitKind::Object => LLVMTargetMachineEmitToFile(
+                    self.machine,
+                    module,
+                    path.as_ptr(),
+                    LLVMCodeGenFileType::LLVMObjectFile,
+                    &mut error,
+                ) != 0,
+                EmitKind::Assembly => LLVMTargetMachineEmitToFile(
+                    self.machine,
+                    module,
+                    path.as_ptr(),
+                    LLVMCodeGenFileType::LLVMAssemblyFile,
+                    &mut error,
+                ) != 0,
+                EmitKind::LlvmIr => LLVMPrintModuleToFile(module, path.as_ptr(), &mut error) != 0,
+                EmitKind::LlvmBitcode => LLVMWriteBitcodeToFile(module, path.as_ptr()) != 0,
+            };
+
+            if failed {
+                let message = if error.is_null() {
+                    "unknown error".to_string()
+                } else {
+                    take_message(error)
+                };
+                return Err(format!("Failed to emit {}: {}", kind, message));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for TargetMachine {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeTargetMachine(self.machine);
+        }
+    }
+}
+
+// Copy an LLVM-owned message into a Rust string and free the original
+unsafe fn take_message(message: *mut std::os::raw::c_char) -> String {
+    let result = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    result
+}
@@ -32,7 +32,13 @@ impl Formatter {
         .collect();
 
         let mut parser = parser::Parser::new(tokens);
-        let ast = parser.parse()?;
+        let ast = parser.parse().map_err(|diagnostics| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.render(source, "<input>"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })?;
 
         // Reset formatter state
         self.indent_level = 0;
@@ -45,48 +51,104 @@ impl Formatter {
     }
 
     fn format_ast(&mut self, ast: &ast::Program) -> Result<(), String> {
-        for item in &ast.items {
-            self.format_item(item)?;
-            self.newline()?;
+        for (i, stmt) in ast.statements.iter().enumerate() {
+            // Separate top-level declarations with a blank line
+            if i > 0 && Self::is_declaration(stmt) {
+                self.newline()?;
+            }
+            self.format_statement(stmt)?;
         }
         Ok(())
     }
 
-    fn format_item(&mut self, item: &ast::Item) -> Result<(), String> {
-        match item {
-            ast::Item::Function(func) => self.format_function(func),
-            ast::Item::Struct(struct_def) => self.format_struct(struct_def),
-            ast::Item::Enum(enum_def) => self.format_enum(enum_def),
-            // Add more item types as needed
-        }
+    fn is_declaration(stmt: &ast::Statement) -> bool {
+        matches!(
+            stmt.kind,
+            ast::StatementKind::Function { .. }
+                | ast::StatementKind::Struct { .. }
+                | ast::StatementKind::Enum(_)
+                | ast::StatementKind::Trait(_)
+                | ast::StatementKind::Impl(_)
+        )
     }
 
-    fn format_function(&mut self, func: &ast::Function) -> Result<(), String> {
-        // Write function signature
-        write!(self.output, "fn {}(", func.name).map_err(|e| e.to_string())?;
-        
-        // Format parameters
-        for (i, param) in func.parameters.iter().enumerate() {
-            if i > 0 {
-                write!(self.output, ", ").map_err(|e| e.to_string())?;
-            }
-            write!(self.output, "{}: {}", param.name, param.type_name)
-                .map_err(|e| e.to_string())?;
+    fn format_function(
+        &mut self,
+        name: &str,
+        generics: &[ast::GenericParam],
+        params: &[ast::Param],
+        return_type: &Option<ast::Type>,
+        body: &[ast::Statement],
+        expression_body: bool,
+    ) -> Result<(), String> {
+        // Write function signature; bounds all go in a `where` clause
+        let names: Vec<_> = generics.iter().map(|param| param.name.as_str()).collect();
+        write!(self.output, "fn {}{}", name, Self::format_generics(&names)).map_err(|e| e.to_string())?;
+        self.format_params(params)?;
+        write!(self.output, " ").map_err(|e| e.to_string())?;
+        if let Some(return_type) = return_type {
+            write!(self.output, "-> {} ", return_type).map_err(|e| e.to_string())?;
+        }
+        let bounded: Vec<_> = generics
+            .iter()
+            .filter(|param| !param.bounds.is_empty())
+            .map(|param| format!("{}: {}", param.name, Self::format_bounds(&param.bounds)))
+            .collect();
+        if !bounded.is_empty() {
+            write!(self.output, "where {} ", bounded.join(", ")).map_err(|e| e.to_string())?;
         }
-        
-        write!(self.output, ") -> {} ", func.return_type).map_err(|e| e.to_string())?;
-        
+
         // Format function body
-        self.format_block(&func.body)
+        if expression_body {
+            if let [ast::Statement { kind: ast::StatementKind::Return(Some(value)), .. }] = body {
+                let value = self.format_expression(value)?;
+                return writeln!(self.output, "=> {};", value).map_err(|e| e.to_string());
+            }
+        }
+        self.format_block(body)?;
+        self.newline()
     }
 
-    fn format_struct(&mut self, struct_def: &ast::Struct) -> Result<(), String> {
-        writeln!(self.output, "struct {} {{", struct_def.name).map_err(|e| e.to_string())?;
+    // `(a: i32, b)`, with a method's `self` written bare
+    fn format_params(&mut self, params: &[ast::Param]) -> Result<(), String> {
+        let params: Vec<_> = params
+            .iter()
+            .map(|param| match &param.ty {
+                Some(ast::Type::Param(ty)) if param.name == "self" && ty == "Self" => param.name.clone(),
+                Some(ty) => format!("{}: {}", param.name, ty),
+                None => param.name.clone(),
+            })
+            .collect();
+        write!(self.output, "({})", params.join(", ")).map_err(|e| e.to_string())
+    }
+
+    fn format_generics(params: &[&str]) -> String {
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("<{}>", params.join(", "))
+        }
+    }
+
+    fn format_bounds(bounds: &[(String, ast::Span)]) -> String {
+        bounds.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(" + ")
+    }
+
+    fn format_struct(&mut self, name: &str, generics: &[ast::GenericParam], fields: &[(String, ast::Type)]) -> Result<(), String> {
+        let params: Vec<_> = generics
+            .iter()
+            .map(|param| match param.bounds.as_slice() {
+                [] => param.name.clone(),
+                bounds => format!("{}: {}", param.name, Self::format_bounds(bounds)),
+            })
+            .collect();
+        let params: Vec<_> = params.iter().map(String::as_str).collect();
+        writeln!(self.output, "struct {}{} {{", name, Self::format_generics(&params)).map_err(|e| e.to_string())?;
         self.indent_level += 1;
 
-        for field in &struct_def.fields {
+        for (field_name, field_type) in fields {
             self.indent()?;
-            writeln!(self.output, "{}: {},", field.name, field.type_name)
+            writeln!(self.output, "{}: {},", field_name, field_type)
                 .map_err(|e| e.to_string())?;
         }
 
@@ -95,39 +157,25 @@ impl Formatter {
         writeln!(self.output, "}}").map_err(|e| e.to_string())
     }
 
-    fn format_enum(&mut self, enum_def: &ast::Enum) -> Result<(), String> {
-        writeln!(self.output, "enum {} {{", enum_def.name).map_err(|e| e.to_string())?;
+    fn format_enum(&mut self, enum_decl: &ast::Enum) -> Result<(), String> {
+        writeln!(self.output, "enum {} {{", enum_decl.name).map_err(|e| e.to_string())?;
         self.indent_level += 1;
 
-        for variant in &enum_def.variants {
+        for variant in &enum_decl.variants {
             self.indent()?;
-            match &variant.data {
-                ast::EnumVariantData::Unit => {
-                    writeln!(self.output, "{},", variant.name).map_err(|e| e.to_string())?;
-                }
-                ast::EnumVariantData::Tuple(types) => {
-                    write!(self.output, "{}(", variant.name).map_err(|e| e.to_string())?;
-                    for (i, ty) in types.iter().enumerate() {
-                        if i > 0 {
-                            write!(self.output, ", ").map_err(|e| e.to_string())?;
-                        }
-                        write!(self.output, "{}", ty).map_err(|e| e.to_string())?;
-                    }
-                    writeln!(self.output, "),").map_err(|e| e.to_string())?;
-                }
-                ast::EnumVariantData::Struct(fields) => {
-                    writeln!(self.output, "{} {{", variant.name).map_err(|e| e.to_string())?;
-                    self.indent_level += 1;
-                    for field in fields {
-                        self.indent()?;
-                        writeln!(self.output, "{}: {},", field.name, field.type_name)
-                            .map_err(|e| e.to_string())?;
-                    }
-                    self.indent_level -= 1;
-                    self.indent()?;
-                    writeln!(self.output, "}},").map_err(|e| e.to_string())?;
-                }
+            write!(self.output, "{}", variant.name).map_err(|e| e.to_string())?;
+            if !variant.fields.is_empty() {
+                let fields: Vec<_> = variant
+                    .fields
+                    .iter()
+                    .map(|(name, ty)| match name {
+                        Some(name) => format!("{}: {}", name, ty),
+                        None => ty.to_string(),
+                    })
+                    .collect();
+                write!(self.output, "({})", fields.join(", ")).map_err(|e| e.to_string())?;
             }
+            writeln!(self.output, ",").map_err(|e| e.to_string())?;
         }
 
         self.indent_level -= 1;
@@ -135,13 +183,18 @@ impl Formatter {
         writeln!(self.output, "}}").map_err(|e| e.to_string())
     }
 
-    fn format_block(&mut self, block: &ast::Block) -> Result<(), String> {
-        writeln!(self.output, "{{").map_err(|e| e.to_string())?;
+    fn format_trait(&mut self, trait_decl: &ast::Trait) -> Result<(), String> {
+        writeln!(self.output, "trait {} {{", trait_decl.name).map_err(|e| e.to_string())?;
         self.indent_level += 1;
 
-        for stmt in &block.statements {
+        for method in &trait_decl.methods {
             self.indent()?;
-            self.format_statement(stmt)?;
+            write!(self.output, "fn {}", method.name).map_err(|e| e.to_string())?;
+            self.format_params(&method.params)?;
+            if let Some(return_type) = &method.return_type {
+                write!(self.output, " -> {}", return_type).map_err(|e| e.to_string())?;
+            }
+            writeln!(self.output, ";").map_err(|e| e.to_string())?;
         }
 
         self.indent_level -= 1;
@@ -149,68 +202,273 @@ impl Formatter {
         writeln!(self.output, "}}").map_err(|e| e.to_string())
     }
 
+    fn format_impl(&mut self, impl_decl: &ast::Impl) -> Result<(), String> {
+        writeln!(self.output, "impl {} for {} {{", impl_decl.trait_name, impl_decl.self_type)
+            .map_err(|e| e.to_string())?;
+        self.indent_level += 1;
+
+        for (i, method) in impl_decl.methods.iter().enumerate() {
+            if i > 0 {
+                self.newline()?;
+            }
+            self.format_statement(method)?;
+        }
+
+        self.indent_level -= 1;
+        self.indent()?;
+        writeln!(self.output, "}}").map_err(|e| e.to_string())
+    }
+
+    // Writes `{ ... }` without a trailing newline so `else` can follow the brace
+    fn format_block(&mut self, statements: &[ast::Statement]) -> Result<(), String> {
+        writeln!(self.output, "{{").map_err(|e| e.to_string())?;
+        self.indent_level += 1;
+
+        for stmt in statements {
+            self.format_statement(stmt)?;
+        }
+
+        self.indent_level -= 1;
+        self.indent()?;
+        write!(self.output, "}}").map_err(|e| e.to_string())
+    }
+
+    fn format_if(
+        &mut self,
+        condition: &ast::Expression,
+        then_branch: &[ast::Statement],
+        else_branch: &Option<Vec<ast::Statement>>,
+    ) -> Result<(), String> {
+        let condition = self.format_expression(condition)?;
+        write!(self.output, "if {} ", condition).map_err(|e| e.to_string())?;
+        self.format_block(then_branch)?;
+
+        match else_branch.as_deref() {
+            None => Ok(()),
+            // Keep `else if` chains flat
+            Some([ast::Statement { kind: ast::StatementKind::If { condition, then_branch, else_branch }, .. }]) => {
+                write!(self.output, " else ").map_err(|e| e.to_string())?;
+                self.format_if(condition, then_branch, else_branch)
+            }
+            Some(statements) => {
+                write!(self.output, " else ").map_err(|e| e.to_string())?;
+                self.format_block(statements)
+            }
+        }
+    }
+
     fn format_statement(&mut self, stmt: &ast::Statement) -> Result<(), String> {
-        match stmt {
-            ast::Statement::Let(var) => {
-                writeln!(
-                    self.output,
-                    "let {}: {} = {};",
-                    var.name,
-                    var.type_name,
-                    self.format_expression(&var.initializer)?
-                )
+        self.indent()?;
+        match &stmt.kind {
+            ast::StatementKind::Let { name, mutable, type_annotation, initializer, .. } => {
+                let initializer = self.format_expression(initializer)?;
+                let keyword = if *mutable { "let mut" } else { "let" };
+                match type_annotation {
+                    Some(type_annotation) => writeln!(
+                        self.output,
+                        "{} {}: {} = {};",
+                        keyword, name, type_annotation, initializer
+                    ),
+                    None => writeln!(self.output, "{} {} = {};", keyword, name, initializer),
+                }
                 .map_err(|e| e.to_string())
             }
-            ast::Statement::Return(expr) => {
+            ast::StatementKind::Function { name, generics, params, return_type, body, expression_body, .. } => {
+                self.format_function(name, generics, params, return_type, body, *expression_body)
+            }
+            ast::StatementKind::Struct { name, generics, fields, .. } => self.format_struct(name, generics, fields),
+            ast::StatementKind::Enum(enum_decl) => self.format_enum(enum_decl),
+            ast::StatementKind::Trait(trait_decl) => self.format_trait(trait_decl),
+            ast::StatementKind::Impl(impl_decl) => self.format_impl(impl_decl),
+            ast::StatementKind::Return(expr) => {
                 if let Some(e) = expr {
-                    writeln!(
-                        self.output,
-                        "return {};",
-                        self.format_expression(e)?
-                    )
-                    .map_err(|e| e.to_string())
+                    let value = self.format_expression(e)?;
+                    writeln!(self.output, "return {};", value).map_err(|e| e.to_string())
                 } else {
                     writeln!(self.output, "return;").map_err(|e| e.to_string())
                 }
             }
-            ast::Statement::Expression(expr) => {
-                writeln!(
-                    self.output,
-                    "{};",
-                    self.format_expression(expr)?
-                )
-                .map_err(|e| e.to_string())
+            ast::StatementKind::Expression(expr) => {
+                let is_match = matches!(expr.kind, ast::ExpressionKind::Match { .. });
+                let expr = self.format_expression(expr)?;
+                // A `match` statement ends with its brace, like a block
+                let terminator = if is_match { "" } else { ";" };
+                writeln!(self.output, "{}{}", expr, terminator).map_err(|e| e.to_string())
+            }
+            ast::StatementKind::Assign { target, operator, value, .. } => {
+                let target = self.format_expression(target)?;
+                let value = self.format_expression(value)?;
+                let operator = operator.map(|operator| operator.to_string()).unwrap_or_default();
+                writeln!(self.output, "{} {}= {};", target, operator, value).map_err(|e| e.to_string())
+            }
+            ast::StatementKind::If { condition, then_branch, else_branch } => {
+                self.format_if(condition, then_branch, else_branch)?;
+                self.newline()
+            }
+            ast::StatementKind::While { condition, body } => {
+                let condition = self.format_expression(condition)?;
+                write!(self.output, "while {} ", condition).map_err(|e| e.to_string())?;
+                self.format_block(body)?;
+                self.newline()
+            }
+            ast::StatementKind::Break => writeln!(self.output, "break;").map_err(|e| e.to_string()),
+            ast::StatementKind::Continue => writeln!(self.output, "continue;").map_err(|e| e.to_string()),
+            ast::StatementKind::Block(statements) => {
+                self.format_block(statements)?;
+                self.newline()
             }
-            // Add more statement types
         }
     }
 
     fn format_expression(&mut self, expr: &ast::Expression) -> Result<String, String> {
-        match expr {
-            ast::Expression::Literal(lit) => Ok(lit.to_string()),
-            ast::Expression::Identifier(name) => Ok(name.clone()),
-            ast::Expression::BinaryOp { left, op, right } => {
-                Ok(format!(
-                    "{} {} {}",
-                    self.format_expression(left)?,
-                    op,
-                    self.format_expression(right)?
-                ))
-            }
-            ast::Expression::Call { function, args } => {
-                let mut result = self.format_expression(function)?;
-                result.push('(');
-                for (i, arg) in args.iter().enumerate() {
+        match &expr.kind {
+            ast::ExpressionKind::Integer(value, suffix) => Ok(format!("{}{}", value, suffix_of(suffix))),
+            ast::ExpressionKind::Float(value, suffix) => Ok(format!("{:?}{}", value, suffix_of(suffix))),
+            ast::ExpressionKind::String(value) => Ok(format!("\"{}\"", escape_string(value))),
+            ast::ExpressionKind::Interpolation(desugared) => {
+                let mut result = String::new();
+                self.format_interpolation(desugared, &mut result)?;
+                Ok(format!("\"{}\"", result))
+            }
+            ast::ExpressionKind::Boolean(value) => Ok(value.to_string()),
+            ast::ExpressionKind::Identifier(name) => Ok(name.clone()),
+            ast::ExpressionKind::Binary { left, operator, right, .. } => {
+                // Parenthesize operands that bind looser than this operator;
+                // on the right, equal precedence needs parentheses too
+                let left = self.format_operand(left, operator.precedence())?;
+                let right = self.format_operand(right, operator.precedence() + 1)?;
+                Ok(format!("{} {} {}", left, operator, right))
+            }
+            ast::ExpressionKind::Unary { operator, operand, .. } => {
+                let operand = self.format_operand(operand, u8::MAX)?;
+                Ok(format!("{}{}", operator, operand))
+            }
+            ast::ExpressionKind::Call { function, arguments, .. } => {
+                let function = self.format_operand(function, u8::MAX)?;
+                Ok(format!("{}({})", function, self.format_list(arguments)?))
+            }
+            ast::ExpressionKind::MethodCall { receiver, method, arguments, .. } => {
+                let receiver = self.format_operand(receiver, u8::MAX)?;
+                Ok(format!("{}.{}({})", receiver, method, self.format_list(arguments)?))
+            }
+            ast::ExpressionKind::FieldAccess { object, field } => {
+                let object = self.format_operand(object, u8::MAX)?;
+                Ok(format!("{}.{}", object, field))
+            }
+            ast::ExpressionKind::Index { array, index, .. } => {
+                let array = self.format_operand(array, u8::MAX)?;
+                Ok(format!("{}[{}]", array, self.format_expression(index)?))
+            }
+            ast::ExpressionKind::Range { start, end } => {
+                Ok(format!("{}..{}", self.format_expression(start)?, self.format_expression(end)?))
+            }
+            ast::ExpressionKind::Path(segments) => Ok(segments.join("::")),
+            ast::ExpressionKind::ArrayLiteral(elements) => Ok(format!("[{}]", self.format_list(elements)?)),
+            ast::ExpressionKind::StructLiteral { name, fields, .. } => {
+                let mut result = format!("{} {{ ", name);
+                for (i, (field_name, value)) in fields.iter().enumerate() {
                     if i > 0 {
                         result.push_str(", ");
                     }
-                    result.push_str(&self.format_expression(arg)?);
+                    write!(result, "{}: {}", field_name, self.format_expression(value)?)
+                        .map_err(|e| e.to_string())?;
+                }
+                result.push_str(" }");
+                Ok(result)
+            }
+            ast::ExpressionKind::Match { scrutinee, arms, .. } => {
+                // Arms go one per line, indented one level past the line the `match` starts on
+                let mut result = format!("match {} {{\n", self.format_expression(scrutinee)?);
+                self.indent_level += 1;
+                for arm in arms {
+                    let body = self.format_expression(&arm.body)?;
+                    writeln!(result, "{}{} => {},", self.indent_str.repeat(self.indent_level), arm.pattern, body)
+                        .map_err(|e| e.to_string())?;
                 }
-                result.push(')');
+                self.indent_level -= 1;
+                write!(result, "{}}}", self.indent_str.repeat(self.indent_level)).map_err(|e| e.to_string())?;
                 Ok(result)
             }
-            // Add more expression types
+            ast::ExpressionKind::If { condition, then_branch, else_branch } => {
+                let mut result = format!(
+                    "if {} {{ {} }} else ",
+                    self.format_expression(condition)?,
+                    self.format_expression(then_branch)?
+                );
+                match else_branch.kind {
+                    ast::ExpressionKind::If { .. } => result.push_str(&self.format_expression(else_branch)?),
+                    _ => write!(result, "{{ {} }}", self.format_expression(else_branch)?).map_err(|e| e.to_string())?,
+                }
+                Ok(result)
+            }
+            // These take another prefix expression without parentheses, but
+            // not a binary one
+            ast::ExpressionKind::Borrow { operand, mutable } => {
+                let operand = self.format_operand(operand, u8::MAX - 1)?;
+                Ok(format!("{} {}", if *mutable { "ref mut" } else { "ref" }, operand))
+            }
+            ast::ExpressionKind::Cast { operand, target, .. } => {
+                // `x as u8 as i32` chains without parentheses
+                let operand = match operand.kind {
+                    ast::ExpressionKind::Cast { .. } => self.format_expression(operand)?,
+                    _ => self.format_operand(operand, u8::MAX - 1)?,
+                };
+                Ok(format!("{} as {}", operand, target))
+            }
+            ast::ExpressionKind::Slice { operand, .. } => Ok(format!("&{}", self.format_operand(operand, u8::MAX - 1)?)),
+            ast::ExpressionKind::Move(operand) => Ok(format!("move {}", self.format_operand(operand, u8::MAX - 1)?)),
+            ast::ExpressionKind::Deref { operand, .. } => Ok(format!("*{}", self.format_operand(operand, u8::MAX - 1)?)),
+        }
+    }
+
+    // Turn the `+` chain an interpolated string was desugared into back into
+    // its text and `{expr}`s
+    fn format_interpolation(&mut self, expr: &ast::Expression, result: &mut String) -> Result<(), String> {
+        match &expr.kind {
+            ast::ExpressionKind::Binary { left, right, .. } => {
+                self.format_interpolation(left, result)?;
+                self.format_interpolation(right, result)
+            }
+            ast::ExpressionKind::String(text) => {
+                result.push_str(&escape_string(text));
+                Ok(())
+            }
+            ast::ExpressionKind::Call { arguments, .. } => {
+                let value = self.format_expression(&arguments[0])?;
+                write!(result, "{{{}}}", value).map_err(|e| e.to_string())
+            }
+            _ => Err("Malformed string interpolation".to_string()),
+        }
+    }
+
+    // Format an operand, wrapping binary expressions weaker than `min_precedence`
+    fn format_operand(&mut self, expr: &ast::Expression, min_precedence: u8) -> Result<String, String> {
+        let formatted = self.format_expression(expr)?;
+        match &expr.kind {
+            ast::ExpressionKind::Binary { operator, .. } if operator.precedence() < min_precedence => {
+                Ok(format!("({})", formatted))
+            }
+            ast::ExpressionKind::Unary { .. }
+            | ast::ExpressionKind::Borrow { .. }
+            | ast::ExpressionKind::Slice { .. }
+            | ast::ExpressionKind::Move(_)
+            | ast::ExpressionKind::Deref { .. }
+                if min_precedence == u8::MAX =>
+            {
+                Ok(format!("({})", formatted))
+            }
+            // A cast binds looser than a prefix operator
+            ast::ExpressionKind::Cast { .. } if min_precedence >= u8::MAX - 1 => Ok(format!("({})", formatted)),
+            _ => Ok(formatted),
+        }
+    }
+
+    fn format_list(&mut self, expressions: &[ast::Expression]) -> Result<String, String> {
+        let mut parts = Vec::new();
+        for expr in expressions {
+            parts.push(self.format_expression(expr)?);
         }
+        Ok(parts.join(", "))
     }
 
     fn indent(&mut self) -> Result<(), String> {
@@ -224,3 +482,20 @@ impl Formatter {
         writeln!(self.output).map_err(|e| e.to_string())
     }
 }
+
+// The contents of a string literal as source text. Braces are escaped, or
+// they would interpolate.
+fn escape_string(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '{' | '}' => format!("\\{}", c),
+            '\'' => c.to_string(),
+            c => c.escape_debug().to_string(),
+        })
+        .collect()
+}
+
+fn suffix_of(suffix: &Option<ast::Type>) -> String {
+    suffix.as_ref().map(|ty| ty.to_string()).unwrap_or_default()
+}
@@ -0,0 +1,10 @@
+pub mod compiler;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod formatter;
+pub mod lsp;
+pub mod runtime;
+pub mod stdlib;
+
+// Re-export the compiler entry point
+pub use compiler::Compiler;
@@ -4,7 +4,7 @@ use tower_lsp::{Client, LanguageServer, LspService, Server};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::compiler::{lexer, parser, analyzer};
+use crate::compiler::{self, lexer, parser, analyzer};
 
 pub struct SwiftPPLanguageServer {
     client: Client,
@@ -28,8 +28,10 @@ impl LanguageServer for SwiftPPLanguageServer {
                     trigger_characters: Some(vec![".".to_string()]),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
+                    completion_item: None,
                 }),
                 definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
                     DiagnosticOptions {
@@ -61,7 +63,7 @@ impl LanguageServer for SwiftPPLanguageServer {
         let mut documents = self.document_map.lock().await;
         documents
             .documents
-            .insert(params.text_document.uri, params.text_document.text);
+            .insert(params.text_document.uri.clone(), params.text_document.text.clone());
         
         self.validate_document(&params.text_document.uri, &params.text_document.text)
             .await;
@@ -104,12 +106,31 @@ impl LanguageServer for SwiftPPLanguageServer {
         let documents = self.document_map.lock().await;
         if let Some(content) = documents.documents.get(&params.text_document_position_params.text_document.uri) {
             // Find symbol definition
-            let location = self.find_definition(content, params.text_document_position_params.position).await;
+            let uri = &params.text_document_position_params.text_document.uri;
+            let location = self.find_definition(uri, content, params.text_document_position_params.position).await;
             Ok(location.map(GotoDefinitionResponse::Scalar))
         } else {
             Ok(None)
         }
     }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let documents = self.document_map.lock().await;
+        let uri = &params.text_document_position.text_document.uri;
+        if let Some(content) = documents.documents.get(uri) {
+            let locations = self
+                .find_references(
+                    uri,
+                    content,
+                    params.text_document_position.position,
+                    params.context.include_declaration,
+                )
+                .await;
+            Ok(Some(locations))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 impl SwiftPPLanguageServer {
@@ -123,90 +144,99 @@ impl SwiftPPLanguageServer {
     }
 
     async fn validate_document(&self, uri: &Url, content: &str) {
-        // Perform lexical and syntactic analysis
-        let mut lexer = lexer::Lexer::new(content);
-        let tokens: Vec<_> = std::iter::from_fn(|| {
-            let token = lexer.next_token();
-            if token.token_type == lexer::TokenType::EOF {
-                None
-            } else {
-                Some(token)
-            }
-        })
-        .collect();
-
-        let mut diagnostics = Vec::new();
-
-        // Parse and collect syntax errors
-        let mut parser = parser::Parser::new(tokens);
-        match parser.parse() {
-            Ok(ast) => {
-                // Perform semantic analysis
-                let mut analyzer = analyzer::SemanticAnalyzer::new();
-                if let Err(errors) = analyzer.analyze(&ast) {
-                    for error in errors {
-                        diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position::new(0, 0), // TODO: Add proper error positions
-                                end: Position::new(0, 0),
-                            },
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            code: None,
-                            code_description: None,
-                            source: Some("swiftpp".to_string()),
-                            message: error,
-                            related_information: None,
-                            tags: None,
-                            data: None,
-                        });
-                    }
-                }
-            }
-            Err(error) => {
-                diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position::new(0, 0),
-                        end: Position::new(0, 0),
-                    },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    code: None,
-                    code_description: None,
-                    source: Some("swiftpp".to_string()),
-                    message: error,
-                    related_information: None,
-                    tags: None,
-                    data: None,
-                });
-            }
-        }
+        let (errors, _) = check_document(content);
+        let diagnostics = errors
+            .iter()
+            .map(|error| self.to_lsp_diagnostic(uri, content, error))
+            .collect();
 
         self.client
             .publish_diagnostics(uri.clone(), diagnostics, None)
             .await;
     }
 
+    fn to_lsp_diagnostic(&self, uri: &Url, content: &str, diagnostic: &compiler::Diagnostic) -> Diagnostic {
+        let span = diagnostic.primary_span.unwrap_or_default();
+        let range = Range {
+            start: self.index_to_position(content, span.start),
+            end: self.index_to_position(content, span.end),
+        };
+
+        // Secondary labels become related information pointing into the same document
+        let related: Vec<_> = diagnostic
+            .labels
+            .iter()
+            .filter(|label| Some(label.span) != diagnostic.primary_span)
+            .map(|label| DiagnosticRelatedInformation {
+                location: Location {
+                    uri: uri.clone(),
+                    range: Range {
+                        start: self.index_to_position(content, label.span.start),
+                        end: self.index_to_position(content, label.span.end),
+                    },
+                },
+                message: label.message.clone(),
+            })
+            .collect();
+
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message.push_str(&format!("\nnote: {}", note));
+        }
+        for suggestion in &diagnostic.suggestions {
+            message.push_str(&format!("\nhelp: {}: `{}`", suggestion.message, suggestion.replacement));
+        }
+
+        Diagnostic {
+            range,
+            severity: Some(match diagnostic.severity {
+                compiler::Severity::Error => DiagnosticSeverity::ERROR,
+                compiler::Severity::Warning => DiagnosticSeverity::WARNING,
+            }),
+            code: diagnostic.code.map(|code| NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("swiftpp".to_string()),
+            message,
+            related_information: if related.is_empty() { None } else { Some(related) },
+            tags: None,
+            data: None,
+        }
+    }
+
+    // The byte offset of an LSP position, whose character counts UTF-16 code
+    // units. A position past the end of its line is at the end of the line.
     fn position_to_index(&self, content: &str, position: Position) -> usize {
-        let mut current_line = 0;
-        let mut current_character = 0;
-        let mut index = 0;
-
-        for (i, c) in content.chars().enumerate() {
-            if current_line == position.line as usize
-                && current_character == position.character as usize
-            {
-                return i;
+        let mut line_start = 0;
+        for _ in 0..position.line {
+            match content[line_start..].find('\n') {
+                Some(newline) => line_start += newline + 1,
+                None => return content.len(),
             }
+        }
 
-            if c == '\n' {
-                current_line += 1;
-                current_character = 0;
-            } else {
-                current_character += 1;
+        let line = &content[line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut units = 0;
+        for (offset, c) in line.char_indices() {
+            if units >= position.character as usize {
+                return line_start + offset;
             }
-            index = i;
+            units += c.len_utf16();
         }
+        line_start + line.len()
+    }
 
-        index + 1
+    // Inverse of `position_to_index`, for a byte offset such as a span boundary
+    fn index_to_position(&self, content: &str, index: usize) -> Position {
+        let mut index = index.min(content.len());
+        while !content.is_char_boundary(index) {
+            index -= 1;
+        }
+        let before = &content[..index];
+        let line = before.matches('\n').count();
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let character = before[line_start..].encode_utf16().count();
+        Position::new(line as u32, character as u32)
     }
 
     async fn get_completion_items(&self, content: &str, position: Position) -> Vec<CompletionItem> {
@@ -228,8 +258,80 @@ impl SwiftPPLanguageServer {
         ]
     }
 
-    async fn find_definition(&self, content: &str, position: Position) -> Option<Location> {
-        // TODO: Implement symbol definition lookup
-        None
+    async fn find_definition(&self, uri: &Url, content: &str, position: Position) -> Option<Location> {
+        let (_, symbols) = check_document(content);
+        let symbol = symbols.symbol_at(self.position_to_index(content, position))?;
+        Some(self.location(uri, content, symbol.definition))
+    }
+
+    async fn find_references(
+        &self,
+        uri: &Url,
+        content: &str,
+        position: Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let (_, symbols) = check_document(content);
+        let symbol = match symbols.symbol_at(self.position_to_index(content, position)) {
+            Some(symbol) => symbol,
+            None => return Vec::new(),
+        };
+
+        let declaration = Some(symbol.definition).filter(|_| include_declaration);
+        declaration
+            .into_iter()
+            .chain(symbol.uses.iter().copied())
+            .map(|span| self.location(uri, content, span))
+            .collect()
+    }
+
+    fn location(&self, uri: &Url, content: &str, span: compiler::Span) -> Location {
+        Location {
+            uri: uri.clone(),
+            range: Range {
+                start: self.index_to_position(content, span.start),
+                end: self.index_to_position(content, span.end),
+            },
+        }
+    }
+}
+
+// Run the front end over a document, collecting every diagnostic along with
+// the symbols it resolved
+fn check_document(content: &str) -> (Vec<compiler::Diagnostic>, analyzer::SymbolTable) {
+    let mut lexer = lexer::Lexer::new(content);
+    let tokens: Vec<_> = std::iter::from_fn(|| {
+        let token = lexer.next_token();
+        if token.token_type == lexer::TokenType::EOF {
+            None
+        } else {
+            Some(token)
+        }
+    })
+    .collect();
+
+    // Report lexical errors and keep going with the remaining tokens
+    let (error_tokens, tokens): (Vec<_>, Vec<_>) = tokens
+        .into_iter()
+        .partition(|token| matches!(token.token_type, lexer::TokenType::Error(_)));
+    let mut diagnostics: Vec<_> = error_tokens.iter().filter_map(lexer::Token::diagnostic).collect();
+
+    // Parse with recovery so every syntax error is reported, not just the first
+    let mut parser = parser::Parser::new(tokens);
+    let (ast, syntax_errors) = parser.parse_partial();
+    let clean_parse = syntax_errors.is_empty();
+    diagnostics.extend(syntax_errors);
+
+    // Symbols still resolve in a partial program, but its semantic errors
+    // would mostly be noise from the missing pieces
+    let mut analyzer = analyzer::SemanticAnalyzer::new();
+    let result = analyzer.analyze(&ast);
+    if clean_parse {
+        if let Err(errors) = result {
+            diagnostics.extend(errors);
+        }
+        diagnostics.extend_from_slice(analyzer.warnings());
     }
+
+    (diagnostics, analyzer.symbols().clone())
 }
@@ -1,8 +1,7 @@
 use clap::{App, Arg};
 use std::fs;
-use std::path::Path;
-
-mod compiler;
+use std::path::{Path, PathBuf};
+use swiftpp::compiler::{self, CompilerOptions, Diagnostic, EmitKind, OptLevel};
 
 fn main() {
     // Setup logging
@@ -13,6 +12,8 @@ fn main() {
         .version("0.1.0")
         .author("Your Name")
         .about("Compiler for the Swift++ programming language")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .arg(
             Arg::with_name("INPUT")
                 .help("Input source file")
@@ -27,39 +28,195 @@ fn main() {
                 .help("Output file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("emit")
+                .long("emit")
+                .value_name("KIND")
+                .help("Kind of output to emit")
+                .possible_values(&["obj", "asm", "llvm-ir", "llvm-bc"])
+                .default_value("obj")
+                .takes_value(true),
+        )
+        .args(optimization_args())
+        .arg(error_format_arg())
         .arg(
             Arg::with_name("verbose")
                 .short('v')
                 .long("verbose")
                 .help("Enable verbose output"),
         )
+        .subcommand(
+            App::new("build")
+                .about("Compile and link an executable")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Input source file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Output executable")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("linker")
+                        .long("linker")
+                        .value_name("PROGRAM")
+                        .help("Linker driver to invoke (defaults to cc)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("runtime-lib")
+                        .long("runtime-lib")
+                        .value_name("FILE")
+                        .help("Path to the libswiftpp_runtime.a runtime archive")
+                        .takes_value(true),
+                )
+                .args(optimization_args())
+                .arg(error_format_arg()),
+        )
         .get_matches();
 
-    // Get input file
-    let input_path = matches.value_of("INPUT").unwrap();
-    let output_path = matches
-        .value_of("output")
-        .unwrap_or(&format!("{}.o", input_path));
+    if let Some(build_matches) = matches.subcommand_matches("build") {
+        let input_path = build_matches.value_of("INPUT").unwrap();
+        let output_path = match build_matches.value_of("output") {
+            Some(path) => path.to_string(),
+            None => Path::new(input_path)
+                .with_extension("")
+                .to_string_lossy()
+                .into_owned(),
+        };
 
-    // Read source file
-    let source = match fs::read_to_string(input_path) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("Error reading source file: {}", e);
-            std::process::exit(1);
+        let opt_level: OptLevel = build_matches.value_of("opt-level").unwrap().parse().unwrap();
+        let options = CompilerOptions {
+            linker: build_matches.value_of("linker").map(String::from),
+            runtime_lib: build_matches.value_of("runtime-lib").map(PathBuf::from),
+            opt_level,
+            dump_ir: build_matches.is_present("dump-ir"),
+            no_bounds_checks: build_matches.is_present("no-bounds-checks"),
+            passes: build_matches.value_of("passes").map(String::from),
+            ..CompilerOptions::default()
+        };
+        let compiler = compiler::Compiler::with_options(read_source(input_path), output_path.clone(), options);
+        let format = build_matches.value_of("error-format").unwrap();
+        match compiler.build() {
+            Ok(warnings) => {
+                report(&warnings, &compiler.source, input_path, format);
+                println!("Build successful!");
+                println!("Executable written to: {}", output_path);
+            }
+            Err(diagnostics) => {
+                report(&diagnostics, &compiler.source, input_path, format);
+                std::process::exit(1);
+            }
         }
+        return;
+    }
+
+    // Get input file
+    let input_path = matches.value_of("INPUT").unwrap();
+    let emit: EmitKind = matches.value_of("emit").unwrap().parse().unwrap();
+    let opt_level: OptLevel = matches.value_of("opt-level").unwrap().parse().unwrap();
+    let output_path = match matches.value_of("output") {
+        Some(path) => path.to_string(),
+        None => Path::new(input_path)
+            .with_extension(emit.extension())
+            .to_string_lossy()
+            .into_owned(),
     };
 
     // Create and run compiler
-    let compiler = compiler::Compiler::new(source, output_path.to_string());
+    let options = CompilerOptions {
+        emit,
+        opt_level,
+        dump_ir: matches.is_present("dump-ir"),
+        no_bounds_checks: matches.is_present("no-bounds-checks"),
+        passes: matches.value_of("passes").map(String::from),
+        ..CompilerOptions::default()
+    };
+    let compiler = compiler::Compiler::with_options(read_source(input_path), output_path.clone(), options);
+    let format = matches.value_of("error-format").unwrap();
     match compiler.compile() {
-        Ok(_) => {
+        Ok(warnings) => {
+            report(&warnings, &compiler.source, input_path, format);
             println!("Compilation successful!");
             println!("Output written to: {}", output_path);
         }
+        Err(diagnostics) => {
+            report(&diagnostics, &compiler.source, input_path, format);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn read_source(input_path: &str) -> String {
+    match fs::read_to_string(input_path) {
+        Ok(content) => content,
         Err(e) => {
-            eprintln!("Compilation error: {}", e);
+            eprintln!("Error reading source file: {}", e);
             std::process::exit(1);
         }
     }
 }
+
+// Print diagnostics to stderr, either rendered with source snippets or as one
+// JSON object per line for tools such as CI annotators
+fn report(diagnostics: &[Diagnostic], source: &str, file_name: &str, format: &str) {
+    if format == "json" {
+        for diagnostic in diagnostics {
+            eprintln!("{}", diagnostic.to_json(source, file_name));
+        }
+        return;
+    }
+
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.render(source, file_name));
+    }
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    match (errors, warnings) {
+        (0, 0) => {}
+        (0, 1) => eprintln!("warning: 1 warning emitted"),
+        (0, _) => eprintln!("warning: {} warnings emitted", warnings),
+        (1, _) => eprintln!("error: aborting due to previous error"),
+        _ => eprintln!("error: aborting due to {} previous errors", errors),
+    }
+}
+
+fn error_format_arg() -> Arg<'static> {
+    Arg::with_name("error-format")
+        .long("error-format")
+        .value_name("FORMAT")
+        .help("How to print errors")
+        .possible_values(&["human", "json"])
+        .default_value("human")
+        .takes_value(true)
+}
+
+// Flags shared by plain compilation and `build`
+fn optimization_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::with_name("opt-level")
+            .short('O')
+            .value_name("LEVEL")
+            .help("Optimization level")
+            .possible_values(&["0", "1", "2", "3", "s", "z"])
+            .default_value("0")
+            .takes_value(true),
+        Arg::with_name("passes")
+            .long("passes")
+            .value_name("PIPELINE")
+            .help("LLVM pass pipeline to run instead of the -O default, e.g. \"default<O2>,loop-vectorize\"")
+            .takes_value(true),
+        Arg::with_name("dump-ir")
+            .long("dump-ir")
+            .help("Write LLVM IR before and after optimization next to the output"),
+        Arg::with_name("no-bounds-checks")
+            .long("no-bounds-checks")
+            .help("Index arrays and slices without checking the index is in bounds"),
+    ]
+}
@@ -1,3 +1,5 @@
+pub use swiftpp_runtime::{startup, RuntimeError};
+
 use std::sync::{Arc, Mutex};
 use std::thread;
 use crossbeam_channel::{bounded, Sender, Receiver};
@@ -175,27 +177,3 @@ pub mod memory {
         }
     }
 }
-
-// Error Handling
-#[derive(Debug)]
-pub enum RuntimeError {
-    MemoryAllocationError,
-    ThreadPoolError(String),
-    SIMDError(String),
-    IndexOutOfBounds { index: usize, len: usize },
-}
-
-impl std::error::Error for RuntimeError {}
-
-impl std::fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            RuntimeError::MemoryAllocationError => write!(f, "Memory allocation failed"),
-            RuntimeError::ThreadPoolError(msg) => write!(f, "Thread pool error: {}", msg),
-            RuntimeError::SIMDError(msg) => write!(f, "SIMD operation error: {}", msg),
-            RuntimeError::IndexOutOfBounds { index, len } => {
-                write!(f, "Index {} out of bounds for length {}", index, len)
-            }
-        }
-    }
-}
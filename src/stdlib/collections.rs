@@ -56,6 +56,15 @@ impl<T> Vector<T> {
         }
     }
 
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        for value in other {
+            self.push(value.clone());
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -45,7 +45,7 @@ impl TaskScheduler {
             let receiver = Arc::clone(&receiver);
             std::thread::spawn(move || {
                 loop {
-                    let task = match receiver.lock().unwrap().recv() {
+                    let task: Task = match receiver.lock().unwrap().recv() {
                         Ok(task) => task,
                         Err(_) => break,
                     };
@@ -96,7 +96,7 @@ impl Runtime {
     }
 
     pub fn block_on<F: StdFuture>(&self, future: F) -> F::Output {
-        let (sender, receiver) = bounded(1);
+        let (_sender, receiver) = bounded::<()>(1);
         let mut future = Box::pin(future);
         
         let waker = futures::task::noop_waker();
@@ -3,8 +3,12 @@ pub mod collections;
 pub mod concurrent;
 pub mod math;
 
+// The parts compiled code calls live in the runtime crate
+pub use swiftpp_runtime::{ffi, string};
+
 // Re-export commonly used items
 pub use collections::Vector;
 pub use concurrent::Future;
 pub use io::{println, readln};
 pub use math::simd;
+pub use string::SwiftString;
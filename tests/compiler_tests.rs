@@ -1,6 +1,23 @@
-use swiftpp::compiler::{Compiler, lexer, parser, analyzer};
+use swiftpp::compiler::{Compiler, CompilerOptions, EmitKind, OptLevel, Severity, lexer, parser, analyzer};
 use pretty_assertions::assert_eq;
 
+// Every token up to, but not including, EOF
+fn lex(source: &str) -> Vec<lexer::Token> {
+    let mut lexer = lexer::Lexer::new(source);
+    std::iter::from_fn(|| {
+        let token = lexer.next_token();
+        if token.token_type == lexer::TokenType::EOF {
+            None
+        } else {
+            Some(token)
+        }
+    }).collect()
+}
+
+fn parse(source: &str) -> parser::ast::Program {
+    parser::Parser::new(lex(source)).parse().expect("Failed to parse")
+}
+
 #[test]
 fn test_lexer() {
     let source = r#"
@@ -10,17 +27,66 @@ fn test_lexer() {
         }
     "#;
 
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens: Vec<_> = std::iter::from_fn(|| {
-        let token = lexer.next_token();
-        if token.token_type == lexer::TokenType::EOF {
-            None
-        } else {
-            Some(token)
-        }
-    }).collect();
+    let tokens = lex(source);
+
+    assert_eq!(tokens.len(), 18); // fn, main, (, ), ->, i32, {, let, x, :, i32, =, 42, ;, return, x, ;, }
+}
+
+#[test]
+fn test_lexer_reports_errors_without_panicking() {
+    let source = "let a = 1.2.3; let b = @; let c = 340282366920938463463374607431768211456;";
 
-    assert_eq!(tokens.len(), 12); // fn, main, (), ->, i32, {, let, x, :, i32, =, 42, ;, return, x, ;, }
+    let tokens = lex(source);
+
+    let errors: Vec<_> = tokens
+        .iter()
+        .filter(|token| matches!(token.token_type, lexer::TokenType::Error(_)))
+        .collect();
+    assert_eq!(errors.len(), 3);
+    assert_eq!((errors[0].column, errors[0].length), (9, 5));
+    assert_eq!((errors[1].column, errors[1].length), (24, 1));
+    assert_eq!((errors[2].column, errors[2].length), (35, 39));
+
+    // Scanning continues past each error
+    assert_eq!(tokens.len(), 15);
+}
+
+#[test]
+fn test_lexer_operators_and_literals() {
+    use lexer::TokenType::*;
+
+    let source = r#"x += 0x1F_FF; a::b => 1..=5 && !done || 2.5e-3 <= 'c' // trailing
+        /* nested /* block */ comment */ "tab\t\u{41}" != true"#;
+
+    let tokens: Vec<_> = lex(source).into_iter().map(|token| token.token_type).collect();
+
+    assert_eq!(tokens, vec![
+        Identifier("x".to_string()), PlusAssign, Integer(0x1FFF, None), Semicolon,
+        Identifier("a".to_string()), DoubleColon, Identifier("b".to_string()), FatArrow,
+        Integer(1, None), DotDotEqual, Integer(5, None), And, Not, Identifier("done".to_string()), Or,
+        Float(2.5e-3, None), LessEqual, Char('c'),
+        String("tab\tA".to_string()), NotEqual, Boolean(true),
+    ]);
+}
+
+#[test]
+fn test_lexer_accepts_examples() {
+    for entry in std::fs::read_dir("examples").unwrap() {
+        let path = entry.unwrap().path();
+        let source = std::fs::read_to_string(&path).unwrap();
+
+        let mut lexer = lexer::Lexer::new(&source);
+        loop {
+            let token = lexer.next_token();
+            match token.token_type {
+                lexer::TokenType::EOF => break,
+                lexer::TokenType::Error(message) => {
+                    panic!("{}:{}:{}: {}", path.display(), token.line, token.column, message)
+                }
+                _ => {}
+            }
+        }
+    }
 }
 
 #[test]
@@ -31,27 +97,440 @@ fn test_parser() {
         }
     "#;
 
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens: Vec<_> = std::iter::from_fn(|| {
-        let token = lexer.next_token();
-        if token.token_type == lexer::TokenType::EOF {
-            None
-        } else {
-            Some(token)
+    let ast = parse(source);
+
+    match &ast.statements[0].kind {
+        parser::ast::StatementKind::Function { name, params, return_type, .. } => {
+            assert_eq!(name, "add");
+            assert_eq!(params.len(), 2);
+            assert!(return_type.is_some());
         }
-    }).collect();
+        _ => panic!("Expected function definition"),
+    }
+}
+
+#[test]
+fn test_parse_statements() {
+    use parser::ast::{ExpressionKind, StatementKind};
+
+    let source = r#"
+        struct Point { x: i32, y: i32, }
+
+        fn classify(p: Point, limit: i32) -> i32 {
+            let origin = Point { x: 0, y: 0 };
+            while p.x < limit {
+                {
+                    log(p.x);
+                }
+            }
+            if p.x == limit {
+                return 1;
+            } else if origin.y > p.y {
+                return 2;
+            } else {
+                return;
+            }
+        }
+    "#;
+
+    let ast = parse(source);
+    assert_eq!(ast.statements.len(), 2);
+
+    match &ast.statements[0].kind {
+        StatementKind::Struct { name, fields, .. } => {
+            assert_eq!(name, "Point");
+            assert_eq!(fields.len(), 2);
+        }
+        _ => panic!("Expected struct declaration"),
+    }
+
+    let body = match &ast.statements[1].kind {
+        StatementKind::Function { body, .. } => body,
+        _ => panic!("Expected function definition"),
+    };
+    assert_eq!(body.len(), 3);
+
+    match &body[0].kind {
+        StatementKind::Let { initializer, .. } => match &initializer.kind {
+            ExpressionKind::StructLiteral { name, fields, .. } => {
+                assert_eq!(name, "Point");
+                assert_eq!(fields.len(), 2);
+            }
+            other => panic!("Expected struct literal, found {:?}", other),
+        },
+        other => panic!("Expected struct literal, found {:?}", other),
+    }
+
+    // `limit { ... }` in the condition is the loop body, not a struct literal
+    match &body[1].kind {
+        StatementKind::While { condition, body } => {
+            match &condition.kind {
+                ExpressionKind::Binary { right, .. } => {
+                    assert!(matches!(&right.kind, ExpressionKind::Identifier(name) if name == "limit"));
+                }
+                other => panic!("Expected comparison, found {:?}", other),
+            }
+            assert_eq!(body.len(), 1);
+        }
+        other => panic!("Expected while loop, found {:?}", other),
+    }
+
+    match &body[2].kind {
+        StatementKind::If { else_branch: Some(else_branch), .. } => match &else_branch[0].kind {
+            StatementKind::If { else_branch: Some(last), .. } => {
+                assert_eq!(else_branch.len(), 1);
+                assert!(matches!(&last[0].kind, StatementKind::Return(None)));
+            }
+            other => panic!("Expected else-if chain, found {:?}", other),
+        },
+        other => panic!("Expected if statement, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_formatter_output() {
+    let source = "fn f(a: i32) -> i32 { if (a + 1) * 2 > a { return -(a - 1); } else if !done { g(); } else { return 0; } }";
+
+    let formatted = swiftpp::formatter::Formatter::new().format(source).expect("Failed to format");
+    assert_eq!(formatted, "\
+fn f(a: i32) -> i32 {
+    if (a + 1) * 2 > a {
+        return -(a - 1);
+    } else if !done {
+        g();
+    } else {
+        return 0;
+    }
+}
+");
+}
+
+#[test]
+fn test_expression_precedence() {
+    use parser::ast::{BinaryOp, ExpressionKind, StatementKind, UnaryOp};
+
+    let source = "let r = -a + b * c.len() < limit[0] || !Point::origin().x;";
+
+    let ast = parse(source);
+
+    let initializer = match &ast.statements[0].kind {
+        StatementKind::Let { initializer, .. } => initializer,
+        _ => panic!("Expected let statement"),
+    };
+
+    // ((-a + (b * c.len())) < limit[0]) || !(Point::origin().x)
+    let (comparison, negation) = match &initializer.kind {
+        ExpressionKind::Binary { left, operator: BinaryOp::Or, right, .. } => (&left.kind, &right.kind),
+        other => panic!("Expected || at the root, found {:?}", other),
+    };
+
+    match comparison {
+        ExpressionKind::Binary { left, operator: BinaryOp::Less, right, .. } => {
+            match &left.kind {
+                ExpressionKind::Binary { left, operator: BinaryOp::Add, right, .. } => {
+                    assert!(matches!(&left.kind, ExpressionKind::Unary { operator: UnaryOp::Negate, .. }));
+                    match &right.kind {
+                        ExpressionKind::Binary { operator: BinaryOp::Multiply, right, .. } => {
+                            assert!(matches!(&right.kind, ExpressionKind::MethodCall { method, .. } if method == "len"));
+                        }
+                        other => panic!("Expected b * c.len(), found {:?}", other),
+                    }
+                }
+                other => panic!("Expected addition, found {:?}", other),
+            }
+            assert!(matches!(&right.kind, ExpressionKind::Index { .. }));
+        }
+        other => panic!("Expected comparison, found {:?}", other),
+    }
+
+    match negation {
+        ExpressionKind::Unary { operator: UnaryOp::Not, operand, .. } => match &operand.kind {
+            ExpressionKind::FieldAccess { object, field } => {
+                assert_eq!(field, "x");
+                match &object.kind {
+                    ExpressionKind::Call { function, arguments, .. } => {
+                        assert!(arguments.is_empty());
+                        match &function.kind {
+                            ExpressionKind::Path(segments) => assert_eq!(segments, &["Point", "origin"]),
+                            other => panic!("Expected path, found {:?}", other),
+                        }
+                    }
+                    other => panic!("Expected call, found {:?}", other),
+                }
+            }
+            other => panic!("Expected field access, found {:?}", other),
+        },
+        other => panic!("Expected negation, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_spans() {
+    use parser::ast::{ExpressionKind, StatementKind};
+
+    // The non-ASCII string literal makes byte offsets differ from columns
+    let source = "fn main() -> i32 {\n    let s = \"héllo\";\n    return y + 1;\n}\n";
+
+    let tokens = lex(source);
+
+    let string = tokens
+        .iter()
+        .find(|token| matches!(token.token_type, lexer::TokenType::String(_)))
+        .unwrap();
+    assert_eq!((string.span.start, string.span.end), (31, 39));
+    let return_token = tokens.iter().find(|token| token.token_type == lexer::TokenType::Return).unwrap();
+    assert_eq!(&source[return_token.span.start..return_token.span.end], "return");
 
     let mut parser = parser::Parser::new(tokens);
     let ast = parser.parse().expect("Failed to parse");
+    let function = &ast.statements[0];
+    assert_eq!((function.span.start, function.span.end), (0, source.len() - 1));
 
-    match &ast.statements[0] {
-        parser::ast::Statement::Function { name, params, return_type, .. } => {
-            assert_eq!(name, "add");
-            assert_eq!(params.len(), 2);
-            assert!(return_type.is_some());
+    let body = match &function.kind {
+        StatementKind::Function { body, .. } => body,
+        _ => panic!("Expected function definition"),
+    };
+    assert_eq!(&source[body[1].span.start..body[1].span.end], "return y + 1;");
+    match &body[1].kind {
+        StatementKind::Return(Some(value)) => {
+            assert!(matches!(value.kind, ExpressionKind::Binary { .. }));
+            assert_eq!(&source[value.span.start..value.span.end], "y + 1");
+        }
+        other => panic!("Expected return, found {:?}", other),
+    }
+
+    // Semantic errors point at the offending expression
+    let mut analyzer = analyzer::SemanticAnalyzer::new();
+    let errors = analyzer.analyze(&ast).unwrap_err();
+    let span = errors[0].primary_span.unwrap();
+    assert_eq!(&source[span.start..span.end], "y");
+}
+
+#[test]
+fn test_diagnostic_rendering() {
+    let source = "fn main() -> i32 {\n    let count = 1;\n    return cuont + 1;\n}\n";
+
+    let compiler = Compiler::new(source.to_string(), "test_diagnostics.o".to_string());
+    let errors = compiler.compile().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].code, Some("E0101"));
+    // Warnings from the same run come along with the errors
+    assert_eq!(errors[1].severity, Severity::Warning);
+    assert_eq!(
+        errors[0].render(source, "main.sp"),
+        "error[E0101]: Undefined variable: cuont\n \
+         --> main.sp:3:12\n  \
+         |\n\
+         3 |     return cuont + 1;\n  \
+         |            ^^^^^ not found in this scope\n  \
+         |\n  \
+         = help: a variable with a similar name exists: `count`\n"
+    );
+
+    let json = errors[0].to_json(source, "main.sp");
+    assert_eq!(json["code"], "E0101");
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["spans"][0]["line_start"], 3);
+    assert_eq!(json["spans"][0]["column_start"], 12);
+    assert_eq!(json["spans"][0]["is_primary"], true);
+    assert_eq!(json["suggestions"][0]["replacement"], "count");
+
+    // Every stage reports through the same type
+    let errors = Compiler::new("let s = \"open;".to_string(), "test_diagnostics.o".to_string())
+        .compile()
+        .unwrap_err();
+    assert_eq!(errors[0].code, Some("E0001"));
+    let errors = Compiler::new("fn f() { 1 + ; }".to_string(), "test_diagnostics.o".to_string())
+        .compile()
+        .unwrap_err();
+    assert_eq!(errors[0].code, Some("E0002"));
+}
+
+#[test]
+fn test_parser_recovers_from_errors() {
+    use parser::ast::StatementKind;
+
+    let source = "fn first() -> i32 {\n    let x = ;\n    return 1;\n}\n}\nstruct Point { x: i32 y: i32 }\nfn second() {\n    foo(1,\n}\nfn third() {\n    let y = 2;\n";
+
+    let mut parser = parser::Parser::new(lex(source));
+    let (program, errors) = parser.parse_partial();
+
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Expected expression",
+            "Expected expression",
+            "Expected '}' after struct fields",
+            "Expected expression",
+            "Expected '}'",
+        ]
+    );
+    let spans: Vec<_> = errors.iter().map(|error| error.primary_span.unwrap()).collect();
+    assert_eq!(&source[spans[0].start..spans[0].end], ";");
+    assert_eq!(&source[spans[1].start..spans[1].end], "}");
+
+    // Functions around the errors are still available, with what parsed of their bodies
+    let functions: Vec<_> = program
+        .statements
+        .iter()
+        .filter_map(|statement| match &statement.kind {
+            StatementKind::Function { name, body, .. } => Some((name.as_str(), body.len())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(functions, vec![("first", 1), ("second", 0), ("third", 1)]);
+
+    let mut parser = parser::Parser::new(Vec::new());
+    assert!(parser.parse().is_ok());
+}
+
+#[test]
+fn test_scopes_and_symbols() {
+    let source = "fn main() -> i32 {
+    let total = 1;
+    if total > 0 {
+        let inner = total + 1;
+        let unused = 2;
+    }
+    let shadow = 1;
+    let shadow = shadow + later;
+    let later = 3;
+    return inner;
+}
+";
+
+    let ast = parse(source);
+
+    let mut analyzer = analyzer::SemanticAnalyzer::new();
+    let errors = analyzer.analyze(&ast).unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec!["Cannot use variable `later` before its definition", "Undefined variable: inner"]
+    );
+
+    // `later` and the second `shadow` are never read; `inner` is read only out of scope
+    let warnings: Vec<_> = analyzer.warnings().iter().map(|warning| warning.message.as_str()).collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "unused variable: `inner`",
+            "unused variable: `unused`",
+            "unused variable: `shadow`",
+            "unused variable: `later`",
+        ]
+    );
+
+    // Every use of `total` resolves to its definition
+    let offset = source.find("total + 1").unwrap();
+    let symbol = analyzer.symbols().symbol_at(offset).unwrap();
+    assert_eq!(symbol.kind, analyzer::SymbolKind::Variable);
+    assert_eq!(&source[symbol.definition.start..symbol.definition.end], "total");
+    assert_eq!(symbol.uses.len(), 2);
+}
+
+#[test]
+fn test_type_inference() {
+    let source = "fn multiply(a, b) => a * b;
+fn main() -> i32 {
+    let x = 42;
+    let scaled = multiply(x, 2);
+    let items = [];
+    let first: bool = items[0];
+    return scaled;
+}
+";
+    let mut ast = parse(source);
+    let mut analyzer = analyzer::SemanticAnalyzer::new();
+    assert!(analyzer.analyze(&ast).is_ok());
+
+    let type_at = |name: &str| {
+        let offset = source.find(name).unwrap();
+        analyzer.symbols().symbol_at(offset).unwrap().ty.as_ref().unwrap().to_string()
+    };
+    assert_eq!(type_at("multiply"), "fn(i32, i32) -> i32");
+    assert_eq!(type_at("x = 42"), "i32");
+    assert_eq!(type_at("items"), "[bool; 0]");
+
+    // Code generation sees the signature the calls settled on
+    analyzer.annotate(&mut ast);
+    match &ast.statements[0].kind {
+        parser::ast::StatementKind::Function { params, return_type, expression_body, .. } => {
+            assert!(params.iter().all(|param| param.ty.as_ref().unwrap().to_string() == "i32"));
+            assert_eq!(return_type.as_ref().unwrap().to_string(), "i32");
+            assert!(expression_body);
         }
         _ => panic!("Expected function definition"),
     }
+
+    // A conflict points at where the other type came from
+    let source = "fn half(a) => a / 2;\nfn main() -> bool {\n    return half(true);\n}\n";
+    let errors = analyzer::SemanticAnalyzer::new().analyze(&parse(source)).unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].message, "Argument type mismatch: expected i32, found bool");
+    let inferred = &errors[0].labels[1];
+    assert_eq!(&source[inferred.span.start..inferred.span.end], "a / 2");
+    assert_eq!(inferred.message, "`i32` inferred here");
+    assert_eq!(errors[1].message, "Return type mismatch: expected bool, found i32");
+
+    let errors = analyzer::SemanticAnalyzer::new()
+        .analyze(&parse("fn main() { let empty = []; }"))
+        .unwrap_err();
+    assert_eq!(errors[0].code, Some("E0110"));
+    assert_eq!(errors[0].message, "Type annotations needed for `empty`");
+}
+
+#[test]
+fn test_control_flow_checks() {
+    let source = "fn sign(x: i32) -> i32 {
+    if x > 0 {
+        return 1;
+    } else {
+        return 0;
+    }
+    println(x);
+}
+
+fn check(x: i32) -> i32 {
+    if x > 0 {
+        return 1;
+    }
+}
+
+fn spin() -> i32 {
+    while true {
+        println(1);
+    }
+}
+
+fn quit(x: bool) -> i32 {
+    if x {
+        return;
+    }
+    return 1;
+}
+";
+
+    let ast = parse(source);
+
+    let mut analyzer = analyzer::SemanticAnalyzer::new();
+    let errors = analyzer.analyze(&ast).unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    // `sign` returns on both branches and `spin` never leaves its loop
+    assert_eq!(
+        messages,
+        vec![
+            "`check` can reach the end of its body without returning a value",
+            "`return` without a value in `quit`, which returns i32",
+        ]
+    );
+    assert!(errors.iter().all(|error| error.code == Some("E0111")));
+
+    let warnings: Vec<_> = analyzer.warnings().iter().map(|warning| warning.message.as_str()).collect();
+    assert_eq!(warnings, vec!["unreachable statement", "`while` condition is always true"]);
+    let unreachable = analyzer.warnings()[0].primary_span.unwrap();
+    assert_eq!(&source[unreachable.start..unreachable.end], "println(x);");
 }
 
 #[test]
@@ -65,18 +544,7 @@ fn test_type_checker() {
         }
     "#;
 
-    let mut lexer = lexer::Lexer::new(source);
-    let tokens: Vec<_> = std::iter::from_fn(|| {
-        let token = lexer.next_token();
-        if token.token_type == lexer::TokenType::EOF {
-            None
-        } else {
-            Some(token)
-        }
-    }).collect();
-
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse().expect("Failed to parse");
+    let ast = parse(source);
 
     let mut analyzer = analyzer::SemanticAnalyzer::new();
     let result = analyzer.analyze(&ast);
@@ -118,3 +586,1263 @@ fn test_end_to_end() {
     let compiler = Compiler::new(source.to_string(), "test_fib.o".to_string());
     assert!(compiler.compile().is_ok());
 }
+
+#[test]
+fn test_emit_llvm_ir() {
+    let source = r#"
+        fn answer() -> i32 {
+            return 42;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_emit_test.ll");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    let compiler = Compiler::with_options(
+        source.to_string(),
+        output.to_string_lossy().into_owned(),
+        options,
+    );
+    compiler.compile().expect("Failed to compile");
+
+    let ir = std::fs::read_to_string(&output).expect("No IR written");
+    assert!(ir.contains("define i32 @answer()"));
+}
+
+#[test]
+fn test_build_executable() {
+    let source = r#"
+        fn main() -> i32 {
+            println("Hello from Swift++");
+            return 7;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_build_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(7));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "Hello from Swift++\n");
+
+    // The example from the install guide
+    let source = std::fs::read_to_string("examples/hello.spp").unwrap();
+    let output = std::env::temp_dir().join("swiftpp_hello_example");
+    Compiler::new(source, output.to_string_lossy().into_owned())
+        .build()
+        .expect("Failed to build examples/hello.spp");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "Hello, World!\nx = 42, y = 3.14\nfound the answer!\nSum: 499500\n"
+    );
+}
+
+#[test]
+fn test_structs() {
+    let source = r#"
+        fn main() -> i32 {
+            let mut line = Line { start: Point { x: 1, y: 2 }, end: Point { y: 4, x: 3 } };
+            line.end.x = 10;
+            println(line.end.x);
+            return line.start.y;
+        }
+
+        struct Point { x: i32, y: i32 }
+        struct Line { start: Point, end: Point }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_struct_test");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    let ir_path = output.with_extension("ll");
+    let compiler = Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options);
+    compiler.compile().expect("Failed to compile");
+
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR written");
+    assert!(ir.contains("%Line = type { %Point, %Point }"));
+    assert!(ir.contains("!DICompositeType(tag: DW_TAG_structure_type, name: \"Point\""));
+    assert!(ir.contains("!DIDerivedType(tag: DW_TAG_member, name: \"end\""));
+
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(2));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "10\n");
+
+    let source = "struct Point { x: i32, y: i32 }
+fn main() -> i32 {
+    let a = Point { x: 1 };
+    let b = Point { x: 1, y: 2, z: 3 };
+    return a.x + b.w;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_structs.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Missing field `y` in initializer of `Point`",
+            "Struct `Point` has no field named `z`",
+            "Struct `Point` has no field named `w`",
+        ]
+    );
+}
+
+#[test]
+fn test_enums_and_match() {
+    let source = r#"
+        enum Shape {
+            Circle(radius: f64),
+            Rectangle(f64, f64),
+            Empty,
+        }
+
+        enum Tree {
+            Leaf(i32),
+            Pair(Shape, i32),
+        }
+
+        fn size(t: Tree) -> i32 {
+            return match t {
+                Tree::Leaf(0) => 1,
+                Tree::Leaf(n) => n,
+                Tree::Pair(Shape::Empty, n) => n + 100,
+                Tree::Pair(_, n) => n + 200,
+            };
+        }
+
+        fn bucket(n: i32) -> i32 {
+            return match n {
+                0 => 10,
+                1..=5 => 20,
+                -3..0 => 30,
+                other => other,
+            };
+        }
+
+        fn main() -> i32 {
+            println(size(Tree::Leaf(0)));
+            println(size(Tree::Leaf(7)));
+            println(size(Tree::Pair(Shape::Empty, 5)));
+            println(size(Tree::Pair(Shape::Rectangle(2.0, 3.0), 5)));
+            println(bucket(3));
+            println(bucket(-1));
+            match bucket(42) {
+                42 => println(1),
+                _ => println(0),
+            }
+            return bucket(0);
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_enum_test");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    let ir_path = output.with_extension("ll");
+    let compiler = Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options);
+    compiler.compile().expect("Failed to compile");
+
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR written");
+    assert!(ir.contains("%Shape = type { i32, [2 x i64] }"));
+    assert!(ir.contains("switch i32 %tag"));
+
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(10));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "1\n7\n105\n205\n20\n30\n1\n");
+
+    let source = "enum Shape { Circle(f64), Square(f64), Empty }
+fn main(s: Shape, n: i32) -> i32 {
+    let a = match s {
+        Shape::Circle(_) => 1,
+        Shape::Empty => 2,
+    };
+    let b = match n {
+        0..=10 => 1,
+        5 => 2,
+        _ => 3,
+    };
+    let c = match s {
+        Shape::Square(1) => 1,
+        Shape::Empty(x) => x,
+        _ => 3,
+    };
+    return a + b + c;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_enums.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Non-exhaustive patterns: `Shape::Square(_)` not covered",
+            "Pattern type mismatch: expected f64, found i32",
+            "Pattern has 1 field, but variant `Shape::Empty` has 0",
+            "unreachable pattern",
+        ]
+    );
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("enum E { A(x: i32), B } fn f(e: E) -> i32 { return match e { E::A(1..=3) => 1, _ => 0 }; }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "enum E {
+    A(x: i32),
+    B,
+}
+
+fn f(e: E) -> i32 {
+    return match e {
+        E::A(1..=3) => 1,
+        _ => 0,
+    };
+}
+"
+    );
+}
+
+#[test]
+fn test_generics() {
+    let source = r#"
+        trait Area {
+            fn area(self) -> i32;
+        }
+
+        struct Pair<T> { first: T, second: T }
+        struct Rect { w: i32, h: i32 }
+
+        impl Area for Rect {
+            fn area(self) -> i32 {
+                return self.w * self.h;
+            }
+        }
+
+        impl Area for i32 {
+            fn area(self) -> i32 {
+                return self * self;
+            }
+        }
+
+        fn first<T>(p: Pair<T>) -> T {
+            return p.first;
+        }
+
+        fn total<T>(p: Pair<T>) -> i32 where T: Area {
+            return p.first.area() + p.second.area();
+        }
+
+        fn identity<T>(x: T) -> T {
+            return x;
+        }
+
+        fn main() -> i32 {
+            let numbers = Pair { first: 3, second: identity(4) };
+            let rects = Pair { first: Rect { w: 2, h: 5 }, second: Rect { w: 1, h: 1 } };
+            println(total(numbers));
+            println(first(rects).area());
+            return identity(first(numbers)) + identity(later());
+        }
+
+        fn later() -> i32 {
+            return 1;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_generics_test");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    let ir_path = output.with_extension("ll");
+    let compiler = Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options);
+    compiler.compile().expect("Failed to compile");
+
+    // One instance per list of type arguments, however often it's used
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR written");
+    assert_eq!(ir.matches("define i32 @\"identity<i32>\"").count(), 1);
+    assert!(ir.contains("%\"Pair<Rect>\" = type { %Rect, %Rect }"));
+    assert!(ir.contains("define %Rect @\"first<Rect>\"(%\"Pair<Rect>\""));
+    assert!(ir.contains("define i32 @\"<Rect as Area>::area\"(%Rect"));
+
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(4));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "25\n10\n");
+
+    let source = "trait Area { fn area(self) -> i32; }
+struct Pair<T, U> { first: T, second: U }
+impl Area for bool { fn area(self) -> f64 { return 1.0; } }
+fn total<T: Area>(a: T) -> i32 { return a.area(); }
+fn forward<U>(a: U) -> i32 { return total(a) + a.area(); }
+fn main(_p: Pair<i32>) -> i32 {
+    return total(1);
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_generics.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Method `area` has an incompatible return type for trait `Area`: expected i32, found f64",
+            "No method named `area` found for type U",
+            "`Pair` takes 2 type arguments, but 1 was supplied",
+            "The trait `Area` is not implemented for `U`",
+            "The trait `Area` is not implemented for `i32`",
+        ]
+    );
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("trait Show { fn show(self) -> i32; } impl Show for i32 { fn show(self) -> i32 { return self; } } fn f<T: Show>(x: T) -> i32 { return x.show(); }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "trait Show {
+    fn show(self) -> i32;
+}
+
+impl Show for i32 {
+    fn show(self) -> i32 {
+        return self;
+    }
+}
+
+fn f<T>(x: T) -> i32 where T: Show {
+    return x.show();
+}
+"
+    );
+}
+
+#[test]
+fn test_ownership() {
+    let source = r#"
+        struct Resource { id: i32 }
+        struct Holder { first: Resource, second: Resource }
+
+        impl Drop for Resource {
+            fn drop(self) {
+                println(self.id);
+            }
+        }
+
+        fn consume(r: own Resource) -> i32 {
+            return r.id;
+        }
+
+        fn set(target: ref mut i32, value: i32) {
+            *target = value;
+        }
+
+        fn main() -> i32 {
+            let kept: own Resource = Resource { id: 1 };
+            let given: own Resource = Resource { id: 2 };
+            let total = consume(given);
+            {
+                let _h: own Holder = Holder { first: Resource { id: 3 }, second: Resource { id: 4 } };
+            }
+            let mut counter = Resource { id: 5 };
+            set(ref mut counter.id, 6);
+            let view = ref counter;
+            return total + view.id + kept.id;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_ownership_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    // `given` is dropped by `consume`, and `kept` last, after the return value is computed
+    assert_eq!(result.status.code(), Some(9));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "2\n3\n4\n1\n");
+
+    let source = "struct Point { x: i32, y: i32 }
+fn take(p: own Point) -> i32 { return p.x; }
+fn main() -> i32 {
+    let a: own Point = Point { x: 1, y: 2 };
+    let b = a;
+    let mut p = Point { x: 1, y: 2 };
+    let _m = ref mut p;
+    let _n = ref mut p.x;
+    return a.x + take(b) + b.y;
+}
+fn dangle() {
+    let a = 1;
+    let mut r = ref a;
+    {
+        let b = 5;
+        r = ref b;
+    }
+    println(*r);
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_ownership.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Cannot borrow `p.x` as mutable more than once at a time",
+            "Use of moved value: `a`",
+            "Use of moved value: `b`",
+            "`b` does not live long enough",
+        ]
+    );
+    // Both the move and the use are pointed at
+    let labels: Vec<_> = errors[1]
+        .labels
+        .iter()
+        .map(|label| (&source[label.span.start..label.span.end], label.message.as_str()))
+        .collect();
+    assert_eq!(labels, vec![("a.x", "`a.x` used here after the move"), ("a", "`a` moved here")]);
+    assert_eq!(errors[2].code, Some("E0201"));
+    // A borrow stored in `r` can't outlive the block `b` is declared in
+    assert_eq!(errors[3].code, Some("E0206"));
+    assert_eq!(&source[errors[3].labels[1].span.start..errors[3].labels[1].span.end], "b");
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("fn f(a: own Point, b: ref mut i32) { let c = ref a; let d = ref mut (*b); g(move a); }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "fn f(a: own Point, b: ref mut i32) {
+    let c = ref a;
+    let d = ref mut *b;
+    g(move a);
+}
+"
+    );
+}
+
+#[test]
+fn test_mutation() {
+    let source = r#"
+        struct Point { x: i32, y: i32 }
+        struct Resource { id: i32 }
+
+        impl Drop for Resource {
+            fn drop(self) {
+                println(self.id);
+            }
+        }
+
+        fn bump(p: ref mut Point) {
+            p.x += 10;
+        }
+
+        fn main() -> i32 {
+            let mut total = 1;
+            total += 2;
+            total *= 4;
+            total -= 2;
+            let mut p = Point { x: 1, y: 2 };
+            p.y = 5;
+            bump(ref mut p);
+            p.x %= 7;
+            let mut r: own Resource = Resource { id: 1 };
+            r = Resource { id: 2 };
+            return total + p.x + p.y + r.id;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_mutation_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    // Assigning `r` drops the resource it held
+    assert_eq!(result.status.code(), Some(21));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "1\n2\n");
+
+    // Locals live in stack slots until mem2reg promotes them
+    let output = std::env::temp_dir().join("swiftpp_mutation_test.o");
+    let options = CompilerOptions {
+        opt_level: OptLevel::O2,
+        dump_ir: true,
+        ..CompilerOptions::default()
+    };
+    let source = "fn main() -> i32 { let mut x = 1; x += 2; return x; }";
+    Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options)
+        .compile()
+        .expect("Failed to compile");
+    let before = std::fs::read_to_string(output.with_extension("pre-opt.ll")).expect("No pre-opt IR");
+    let after = std::fs::read_to_string(output.with_extension("post-opt.ll")).expect("No post-opt IR");
+    assert!(before.contains("%x = alloca i32"));
+    assert!(!after.contains("alloca"));
+
+    let source = "struct Point { x: i32, y: i32 }
+fn f(n: i32) -> i32 { n = 2; return n; }
+fn main() -> i32 {
+    let a = 1;
+    a = 2;
+    let p = Point { x: 1, y: 2 };
+    p.x = 3;
+    let mut q = Point { x: 1, y: 2 };
+    q += p;
+    let _m = ref mut p;
+    return a;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_mutation.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Cannot assign to immutable variable `n`",
+            "Cannot assign to immutable variable `a`",
+            "Cannot assign to `p.x`, as `p` is not declared as mutable",
+            "Cannot apply `+=` to a value of type Point",
+            "Cannot borrow `p` as mutable, as `p` is not declared as mutable",
+        ]
+    );
+    let suggestion = &errors[1].suggestions[0];
+    assert_eq!(&source[suggestion.span.start..suggestion.span.end], "a");
+    assert_eq!(suggestion.replacement, "mut a");
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("fn main() { let mut x = 1; x *= 2; p.x = x; }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "fn main() {
+    let mut x = 1;
+    x *= 2;
+    p.x = x;
+}
+"
+    );
+}
+
+#[test]
+fn test_typed_operations() {
+    let source = r#"
+        fn half(x: f64) -> f64 {
+            return x / 2.0;
+        }
+
+        fn main() -> i32 {
+            let a = half(7.0) * 2.0 - 1.5;
+            let mut f = -a;
+            f += 0.25;
+            println(f);
+            println(a > 5.0);
+            println(7 % 3 == 1 && !(3 <= 2));
+            println(true != (2 > 1));
+            println("done");
+            println("done");
+            return 0;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_typed_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "-5.25\ntrue\ntrue\nfalse\ndone\ndone\n");
+
+    let output = std::env::temp_dir().join("swiftpp_typed_test.ll");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options)
+        .compile()
+        .expect("Failed to compile");
+    let ir = std::fs::read_to_string(&output).expect("No IR written");
+    assert!(ir.contains("fdiv double"));
+    assert!(ir.contains("fcmp ogt double"));
+    assert!(ir.contains("fneg double"));
+    // Both literals share one constant
+    assert_eq!(ir.matches("private unnamed_addr constant [4 x i8] c\"done\"").count(), 1);
+}
+
+#[test]
+fn test_loops_and_branches() {
+    let source = r#"
+        struct Resource { id: i32 }
+
+        impl Drop for Resource {
+            fn drop(self) {
+                println(self.id);
+            }
+        }
+
+        fn sign(n: i32) -> i32 {
+            return if n < 0 { -1 } else if n == 0 { 0 } else { 1 };
+        }
+
+        fn main() -> i32 {
+            let mut sum = 0;
+            let mut i = 0;
+            while i < 10 {
+                i += 1;
+                if i % 2 == 0 {
+                    continue;
+                }
+                if i > 7 {
+                    break;
+                }
+                sum += i;
+            }
+            let mut n = 0;
+            while true {
+                let _r: own Resource = Resource { id: n };
+                n += 1;
+                if n == 2 {
+                    break;
+                }
+            }
+            if sum > 100 {
+                return 0;
+            } else {
+                println(sign(-5) + sign(0));
+            }
+            return sum + sign(3);
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_loops_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    // `break` drops the resource of the last iteration
+    assert_eq!(result.status.code(), Some(17));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "0\n1\n-1\n");
+
+    let source = "struct Resource { id: i32 }
+fn take(r: own Resource) -> i32 { return r.id; }
+fn main() -> i32 {
+    let r: own Resource = Resource { id: 1 };
+    let mut i = 0;
+    while i < 3 {
+        if i == 1 {
+            take(r);
+            break;
+        }
+        i += 1;
+    }
+    return 0;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_loops.o".to_string())
+        .compile()
+        .unwrap_err();
+    assert_eq!(errors[0].message, "`r` is moved on some paths but not others");
+
+    let source = "fn main() -> i32 {
+    continue;
+    let _x = if true { 1 } else { 2.0 };
+    return 0;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_loops.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "`continue` outside of a loop",
+            "`if` and `else` have incompatible types: expected i32, found f64",
+        ]
+    );
+    assert_eq!(errors[0].code, Some("E0129"));
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("fn f(n: i32) -> i32 { while true { if n > 1 { break; } continue; } return if n > 0 { 1 } else if n < 0 { 2 } else { 3 }; }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "fn f(n: i32) -> i32 {
+    while true {
+        if n > 1 {
+            break;
+        }
+        continue;
+    }
+    return if n > 0 { 1 } else if n < 0 { 2 } else { 3 };
+}
+"
+    );
+}
+
+#[test]
+fn test_short_circuit() {
+    let source = r#"
+        fn touch(x: i32) -> bool {
+            println(x);
+            return true;
+        }
+
+        fn main() -> i32 {
+            let a = [1, 2, 3];
+            let i = 3;
+            println(i < len(a) && a[i] > 0);
+            println(i >= len(a) || a[i] > 0);
+            let skipped = false && touch(1) || true || touch(2);
+            println(true && touch(3) && skipped);
+            return 0;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_short_circuit_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "false\ntrue\n3\ntrue\n");
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "");
+}
+
+#[test]
+fn test_arrays_and_slices() {
+    let source = r#"
+        struct Grid { cells: [i32; 4] }
+
+        fn sum(values: &[i32]) -> i32 {
+            let mut total = 0;
+            let mut i = 0;
+            while i < len(values) {
+                total += values[i];
+                i += 1;
+            }
+            return total;
+        }
+
+        fn last<T>(values: &[T]) -> T {
+            return values[len(values) - 1];
+        }
+
+        fn main() -> i32 {
+            let mut numbers = [1, 2, 3, 4];
+            numbers[1] = 10;
+            let mut grid = Grid { cells: [0, 0, 0, 0] };
+            grid.cells[2] = 7;
+            println(sum(&numbers));
+            println(last(&[1.5, 2.5]));
+            let view = &grid.cells;
+            let i = 3;
+            return sum(&numbers) + view[i + 1];
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_arrays_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "18\n2.5\n");
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        "error: Index 4 out of bounds for length 4 at line 27, column 36\n"
+    );
+
+    let options = CompilerOptions {
+        no_bounds_checks: true,
+        ..CompilerOptions::default()
+    };
+    let ir_path = std::env::temp_dir().join("swiftpp_arrays_test.ll");
+    let options = CompilerOptions { emit: EmitKind::LlvmIr, ..options };
+    Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options)
+        .compile()
+        .expect("Failed to compile");
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR");
+    assert!(ir.contains("[4 x i32]"));
+    assert!(!ir.contains("swiftpp_index_out_of_bounds"));
+
+    let source = "fn set(values: &[i32]) -> i32 {
+    values[0] = 1;
+    return 0;
+}
+fn main() -> i32 {
+    let numbers = [1, 2, 3];
+    let _pair: [i32; 2] = numbers;
+    return 5[0];
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_arrays.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Cannot assign to an element of a slice",
+            "Type mismatch: expected [i32; 2], found [i32; 3]",
+            "Cannot index into a value of type i32",
+        ]
+    );
+    assert_eq!(errors[0].code, Some("E0128"));
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("fn f(a: [i32; 3], s: &[f64]) -> f64 { let t = &a; return s[t[0]]; }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "fn f(a: [i32; 3], s: &[f64]) -> f64 {
+    let t = &a;
+    return s[t[0]];
+}
+"
+    );
+}
+
+#[test]
+fn test_sized_integers() {
+    let source = r#"
+        fn bump(x: u8) -> u8 {
+            return x + 1;
+        }
+
+        fn main() -> i32 {
+            let big: i64 = 5_000_000_000;
+            let byte = 200u8;
+            let wide = big as i128 + big as i128;
+            let odd: i32 = -7;
+            println(big / 3);
+            println(byte / 3 > 60);
+            println(odd / 2);
+            println(250u8 as i8);
+            println(-1i32 as u32);
+            println(3.9 as i32);
+            println(1.0e10 as i32);
+            println(1u64 << 40);
+            println(odd >> 2);
+            println(byte >> 4);
+            println(true as i32 + 1);
+            println(2.5f32);
+            println(wide);
+            println(18446744073709551615u64);
+            println(-170141183460469231731687303715884105728i128);
+            println(-128i8);
+            println(bump(byte));
+            return bump(255u8) as i32;
+        }
+    "#;
+
+    // A debug build traps on overflow
+    let output = std::env::temp_dir().join("swiftpp_sized_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(101));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "1666666666\ntrue\n-3\n-6\n4294967295\n3\n2147483647\n1099511627776\n-2\n12\n2\n2.5\n10000000000\n18446744073709551615\n-170141183460469231731687303715884105728\n-128\n201\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        "error: Arithmetic overflow at line 3, column 20\n"
+    );
+
+    // An optimized one wraps
+    let options = CompilerOptions {
+        opt_level: OptLevel::O2,
+        ..CompilerOptions::default()
+    };
+    Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options)
+        .build()
+        .expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(0));
+
+    let ir_path = std::env::temp_dir().join("swiftpp_sized_test.ll");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options)
+        .compile()
+        .expect("Failed to compile");
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR written");
+    assert!(ir.contains("define i8 @bump(i8"));
+    assert!(ir.contains("@llvm.uadd.with.overflow.i8"));
+    assert!(ir.contains("udiv i8"));
+    assert!(ir.contains("sdiv i32"));
+    assert!(ir.contains("lshr i8"));
+    assert!(ir.contains("ashr i32"));
+    assert!(ir.contains("@llvm.fptosi.sat.i32.f64"));
+
+    let source = "fn main() -> i32 {
+    let _a: u8 = 256;
+    let _max: u64 = 18446744073709551616;
+    let _min = -170141183460469231731687303715884105729i128;
+    let _b = 1 as string;
+    let _c = 1.5 << 2.0;
+    let small: i8 = -128;
+    let _wide: i64 = small;
+    let values = [1, 2, 3];
+    let i: i32 = 2;
+    return values[i];
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_sized.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Cannot cast a value of type i32 as string",
+            "Cannot shift a value of type f64",
+            "Type mismatch: expected i64, found i8",
+            "Array index must be a usize, found i32",
+            "Literal out of range for u8",
+            "Literal out of range for u64",
+            "Literal out of range for i128",
+        ]
+    );
+    assert_eq!(errors[4].code, Some("E0131"));
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format("fn f(x: u8) -> i64 { let y = 255u8 as i32 + 1; return -(x as i64) << 2 >> 1.5f32 as i64; }")
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        "fn f(x: u8) -> i64 {
+    let y = 255u8 as i32 + 1;
+    return -(x as i64) << 2 >> 1.5f32 as i64;
+}
+"
+    );
+}
+
+#[test]
+fn test_checked_division() {
+    let cases = [
+        ("i32", "7", "/", "0", "Division by zero"),
+        ("u8", "7", "%", "0", "Division by zero"),
+        ("i8", "-128", "/", "-1", "Arithmetic overflow"),
+        ("i64", "-9223372036854775807 - 1", "%", "-1", "Arithmetic overflow"),
+    ];
+    for (ty, dividend, operator, divisor, error) in cases {
+        let source = format!(
+            "fn main() -> i32 {{\n    let d: {ty} = {divisor};\n    let x: {ty} = {dividend};\n    println(x {operator} d);\n    return 0;\n}}\n"
+        );
+        let output = std::env::temp_dir().join("swiftpp_division_test");
+        let compiler = Compiler::new(source, output.to_string_lossy().into_owned());
+        compiler.build().expect("Failed to build");
+        let result = std::process::Command::new(&output)
+            .output()
+            .expect("Failed to run executable");
+        assert_eq!(result.status.code(), Some(101));
+        assert_eq!(String::from_utf8_lossy(&result.stdout), "");
+        assert_eq!(
+            String::from_utf8_lossy(&result.stderr),
+            format!("error: {} at line 4, column 13\n", error)
+        );
+    }
+
+    // An optimized build still traps on zero, but wraps `MIN / -1`
+    let options = || CompilerOptions {
+        opt_level: OptLevel::O2,
+        ..CompilerOptions::default()
+    };
+    let output = std::env::temp_dir().join("swiftpp_division_release_test");
+    let source = "fn main() -> i32 { return 1 / 0; }";
+    Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options())
+        .build()
+        .expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&result.stderr), "error: Division by zero at line 1, column 27\n");
+
+    let source = "fn main() -> i32 {\n    let d: i8 = -1;\n    let x: i8 = -128;\n    println(x / d);\n    println(x % d);\n    return 0;\n}\n";
+    Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options())
+        .build()
+        .expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "-128\n0\n");
+}
+
+#[test]
+fn test_strings() {
+    let source = r#"
+        fn greet(name: string, version: f64) -> string {
+            return "Welcome to {name} version {version}!";
+        }
+
+        fn main() -> i32 {
+            let name = "Swift++";
+            let mut message = greet(name, 1.5);
+            println(message);
+            message += " \{ok\}";
+            println(message);
+            let word = "héllo wörld";
+            println(word[0..6]);
+            println(len(word));
+            println("apple" < "banana");
+            println(name == "Swift" + "++");
+            let count = 3;
+            println("{count} + {count * 2} = {count + count * 2}, {count > 2}");
+            println(to_string(255u8) + to_string(-1i128));
+            println(word[7..len(word)]);
+            return len(word[1..2]) as i32;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_strings_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(101));
+    assert_eq!(
+        String::from_utf8_lossy(&result.stdout),
+        "Welcome to Swift++ version 1.5!\nWelcome to Swift++ version 1.5! {ok}\nhéllo\n13\ntrue\ntrue\n3 + 6 = 9, true\n255-1\nwörld\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&result.stderr),
+        "error: Byte index 2 is not a character boundary at line 21, column 24\n"
+    );
+
+    let ir_path = std::env::temp_dir().join("swiftpp_strings_test.ll");
+    let options = CompilerOptions {
+        emit: EmitKind::LlvmIr,
+        ..CompilerOptions::default()
+    };
+    Compiler::with_options(source.to_string(), ir_path.to_string_lossy().into_owned(), options)
+        .compile()
+        .expect("Failed to compile");
+    let ir = std::fs::read_to_string(&ir_path).expect("No IR written");
+    assert!(ir.contains("private unnamed_addr constant [11 x i8] c\"Welcome to \""));
+    assert!(ir.contains("call void @swiftpp_string_concat("));
+    assert!(ir.contains("call i32 @swiftpp_string_compare("));
+    assert!(ir.contains("@swiftpp_to_string_f64(double"));
+    // The pieces of an interpolated string are freed once joined
+    assert!(ir.contains("call void @swiftpp_string_free("));
+
+    // Owned strings free their buffers when dropped or overwritten
+    let source = r#"
+        struct Named { name: string }
+
+        fn shout(word: own string) -> string {
+            return "{word}!";
+        }
+
+        fn main() -> i32 {
+            let mut message: own string = shout("hi");
+            let mut i = 0;
+            while i < 1000 {
+                message += "{i % 10}";
+                i += 1;
+            }
+            println(len(message));
+            println(message[0..6] + "..." + to_string(i));
+            let mut label: own string = to_string(1);
+            label = "{label}{2}";
+            println(label);
+            let held: own Named = Named { name: message };
+            println(held.name[1000..1003]);
+            return 0;
+        }
+    "#;
+    let output = std::env::temp_dir().join("swiftpp_owned_strings_test");
+    let compiler = Compiler::new(source.to_string(), output.to_string_lossy().into_owned());
+    compiler.build().expect("Failed to build");
+    let result = std::process::Command::new(&output)
+        .output()
+        .expect("Failed to run executable");
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "1003\nhi!012...1000\n12\n789\n");
+
+    // A slice of an owned string borrows it, so it can't outlive the buffer
+    let source = "fn tail(a: string) -> string {
+    let s: own string = a + \"...\";
+    return s[0..2];
+}
+fn main() -> i32 {
+    let mut s: own string = \"a\" + \"b\";
+    let t = s[0..1];
+    s = \"x\";
+    println(t);
+    return 0;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_strings.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec!["Cannot return a value borrowing from `s`", "Cannot assign to `s` because it is borrowed"]
+    );
+    assert_eq!(errors[0].code, Some("E0206"));
+
+    // The lexer splits an interpolated string into text and the tokens of each expression
+    let mut lexer = lexer::Lexer::new(r#""a{x + 1}b""#);
+    match lexer.next_token().token_type {
+        lexer::TokenType::InterpolatedString(parts) => match parts.as_slice() {
+            [lexer::StringPart::Text(a, a_span), lexer::StringPart::Expression(tokens, braces), lexer::StringPart::Text(b, b_span)] => {
+                assert_eq!((a.as_str(), a_span.start, a_span.end), ("a", 1, 2));
+                assert_eq!((braces.start, braces.end), (2, 9));
+                assert_eq!(tokens.len(), 3);
+                assert_eq!((b.as_str(), b_span.start, b_span.end), ("b", 9, 10));
+            }
+            other => panic!("Expected text, expression and text, found {:?}", other),
+        },
+        other => panic!("Expected an interpolated string, found {:?}", other),
+    }
+
+    let source = "struct Point { x: i32 }
+fn main() -> i32 {
+    let s = \"abc\";
+    let _a = s - \"b\";
+    let _b = s[0];
+    let _c = 5[0..1];
+    let i: i32 = 1;
+    let _d = s[i..2];
+    let p = Point { x: 1 };
+    let _e = \"at {p}\";
+    s[0..1] = \"x\";
+    return 0;
+}
+";
+    let errors = Compiler::new(source.to_string(), "test_strings.o".to_string())
+        .compile()
+        .unwrap_err();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Binary operator `-` cannot be applied to strings",
+            "Cannot index into a value of type string",
+            "Cannot slice a value of type i32 with a range",
+            "Range bounds must be usize, found i32",
+            "Cannot convert a value of type Point to a string",
+            "Cannot assign to an element of a slice",
+        ]
+    );
+    assert_eq!(errors[0].code, Some("E0104"));
+
+    let errors = Compiler::new("fn main() -> i32 { println(\"{}\"); return 0; }".to_string(), "test_strings.o".to_string())
+        .compile()
+        .unwrap_err();
+    assert_eq!(errors[0].message, "Expected an expression between '{' and '}'");
+
+    // Errors inside the braces point at the offending token
+    for (text, message, start) in [("x{a +}y", "Expected expression", 33), ("x{a $ b}", "Unexpected character: $", 32)] {
+        let source = format!("fn main() -> i32 {{ println(\"{}\"); return 0; }}", text);
+        let errors = Compiler::new(source, "test_strings.o".to_string()).compile().unwrap_err();
+        assert_eq!(errors[0].message, message);
+        assert_eq!(errors[0].primary_span.map(|span| span.start), Some(start));
+    }
+
+    let formatted = swiftpp::formatter::Formatter::new()
+        .format(r#"fn f(name: string, n: i32) -> string { let s = "Hi {name}, \{n\} is {n+1}\n"; return s[0..2] + "it's"; }"#)
+        .expect("Failed to format");
+    assert_eq!(
+        formatted,
+        r#"fn f(name: string, n: i32) -> string {
+    let s = "Hi {name}, \{n\} is {n + 1}\n";
+    return s[0..2] + "it's";
+}
+"#
+    );
+}
+
+#[test]
+fn test_dump_ir_around_optimization() {
+    let source = r#"
+        fn square(x: i32) -> i32 {
+            return x * x;
+        }
+
+        fn main() -> i32 {
+            return square(4);
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_opt_test.o");
+    let options = CompilerOptions {
+        opt_level: OptLevel::O2,
+        dump_ir: true,
+        ..CompilerOptions::default()
+    };
+    let compiler = Compiler::with_options(
+        source.to_string(),
+        output.to_string_lossy().into_owned(),
+        options,
+    );
+    compiler.compile().expect("Failed to compile");
+
+    let before = std::fs::read_to_string(output.with_extension("pre-opt.ll")).expect("No pre-opt IR");
+    let after = std::fs::read_to_string(output.with_extension("post-opt.ll")).expect("No post-opt IR");
+    assert!(before.contains("call i32 @square"));
+    assert!(!after.contains("call i32 @square"));
+}
+
+#[test]
+fn test_custom_pass_pipeline() {
+    let source = r#"
+        fn main() -> i32 {
+            return 0;
+        }
+    "#;
+
+    let output = std::env::temp_dir().join("swiftpp_passes_test.o");
+    let compile_with = |passes: &str| {
+        let options = CompilerOptions {
+            opt_level: OptLevel::O2,
+            passes: Some(passes.to_string()),
+            ..CompilerOptions::default()
+        };
+        Compiler::with_options(source.to_string(), output.to_string_lossy().into_owned(), options)
+            .compile()
+    };
+
+    assert!(compile_with("default<O2>,loop-vectorize").is_ok());
+    let error = compile_with("no-such-pass").unwrap_err();
+    assert!(error[0].message.contains("Invalid pass pipeline"));
+}
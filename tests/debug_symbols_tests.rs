@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
-    use crate::debugger::symbols::{DebugSymbols, SourceLocation};
+    use swiftpp::debugger::symbols::{DebugSymbols, SourceLocation};
 
     #[test]
     fn test_load_debug_symbols() {